@@ -19,6 +19,14 @@ pub trait Aggregator {
     #[allow(missing_docs)]
     fn sample_size(&self) -> usize;
 
+    /// The number of aggregated samples which were overloaded.
+    fn overload_count(&self) -> usize;
+
+    /// The total number of aggregated samples. The same as [sample_size()](Self::sample_size()).
+    fn total(&self) -> usize {
+        self.sample_size()
+    }
+
     #[allow(missing_docs)]
     fn reset(&mut self);
 }
@@ -29,15 +37,95 @@ pub struct Average {
     latency_sum: Duration,
     in_flight_sum: u128,
     overload: Outcome,
+    overloads: usize,
     samples: usize,
 }
 
-/// A latency percentile, with sample-matched concurrency (in flight).
+/// A latency percentile, with sample-matched concurrency (in flight) by default. See
+/// [InFlightStrategy].
 pub struct Percentile {
     percentile: f64,
+    in_flight_strategy: InFlightStrategy,
     overload: Outcome,
+    overloads: usize,
     num_samples: usize,
     samples: BTreeMap<Duration, Vec<Sample>>,
+    /// The number of samples with each in flight value.
+    in_flights: BTreeMap<usize, usize>,
+    max_in_flight: usize,
+}
+
+/// How [Percentile] chooses the concurrency (in flight) of the aggregated sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InFlightStrategy {
+    /// The in flight of the sample at the latency percentile.
+    #[default]
+    Matched,
+    /// The maximum in flight of all samples.
+    ///
+    /// Often more appropriate for algorithms which only increase the limit at high utilisation.
+    Max,
+    /// The same percentile of in flight as of latency, independently of latency.
+    Percentile,
+}
+
+/// An exponentially-weighted moving latency percentile, using constant memory.
+///
+/// A single estimate is nudged up or down by each sample, depending on whether the sample is above
+/// or below it, in proportions which converge on the given percentile. Unlike [Percentile], the
+/// estimate is kept when [reset](Aggregator::reset), so it continues to adapt over time. Useful
+/// with a long-lived [Windowed](crate::limits::Windowed) where full resets are undesirable.
+///
+/// Concurrency (in flight) is an exponential moving average.
+///
+/// Based on [frugal streaming](https://arxiv.org/abs/1407.1121), with multiplicative steps.
+#[derive(Debug)]
+pub struct MovingPercentile {
+    percentile: f64,
+    /// The relative change in the estimate for each sample.
+    step: f64,
+    estimate: Option<Duration>,
+    in_flight: f64,
+    overload: Outcome,
+    overloads: usize,
+    samples: usize,
+}
+
+/// Average latency and concurrency (in flight), and overload only when the proportion of
+/// overloaded samples exceeds a threshold.
+///
+/// Tolerates a background rate of overload, unlike the other aggregators which report overload if
+/// any sample was overloaded.
+#[derive(Debug)]
+pub struct OverloadRate {
+    threshold: f64,
+    average: Average,
+}
+
+/// Several aggregators, each fed every sample, with each part of the aggregated sample taken from a
+/// chosen aggregator.
+///
+/// Lets an algorithm get the best signal for each dimension from the same window, e.g. latency
+/// from a [Percentile] and outcome from an [OverloadRate]. By default, everything is taken from the
+/// first aggregator.
+pub struct MultiAggregator {
+    aggregators: Vec<Box<dyn Aggregator + Send + Sync>>,
+    latency_from: usize,
+    in_flight_from: usize,
+    outcome_from: usize,
+}
+
+impl Average {
+    /// Combine the samples aggregated by `other` into this aggregator.
+    ///
+    /// Useful for hierarchical aggregation, e.g. rolling up per-shard windows.
+    pub fn merge(&mut self, other: &Self) {
+        self.latency_sum += other.latency_sum;
+        self.in_flight_sum += other.in_flight_sum;
+        self.overload = self.overload.overloaded_or(other.overload);
+        self.overloads += other.overloads;
+        self.samples += other.samples;
+    }
 }
 
 impl Aggregator for Average {
@@ -45,6 +133,7 @@ impl Aggregator for Average {
         self.latency_sum += sample.latency;
         self.in_flight_sum += sample.in_flight as u128;
         self.overload = self.overload.overloaded_or(sample.outcome);
+        self.overloads += usize::from(sample.outcome.is_overload());
         self.samples += 1;
         Sample {
             in_flight: (self.in_flight_sum / self.samples as u128) as usize,
@@ -57,6 +146,10 @@ impl Aggregator for Average {
         self.samples
     }
 
+    fn overload_count(&self) -> usize {
+        self.overloads
+    }
+
     fn reset(&mut self) {
         *self = Self::default();
     }
@@ -68,6 +161,7 @@ impl Default for Average {
             latency_sum: Duration::ZERO,
             in_flight_sum: 0,
             overload: Outcome::Success,
+            overloads: 0,
             samples: 0,
         }
     }
@@ -86,15 +180,55 @@ impl Percentile {
         }
     }
 
+    /// Choose how the in flight of the aggregated sample is calculated. Defaults to
+    /// [InFlightStrategy::Matched].
+    pub fn with_in_flight_strategy(mut self, strategy: InFlightStrategy) -> Self {
+        self.in_flight_strategy = strategy;
+        self
+    }
+
+    /// Combine the samples aggregated by `other` into this aggregator, keeping this aggregator's
+    /// percentile.
+    ///
+    /// Useful for hierarchical aggregation, e.g. rolling up per-shard windows.
+    pub fn merge(&mut self, other: &Self) {
+        self.overload = self.overload.overloaded_or(other.overload);
+        self.overloads += other.overloads;
+        for (latency, samples) in &other.samples {
+            self.samples
+                .entry(*latency)
+                .or_default()
+                .extend(samples.iter().cloned());
+        }
+        for (in_flight, count) in &other.in_flights {
+            *self.in_flights.entry(*in_flight).or_default() += count;
+        }
+        self.max_in_flight = self.max_in_flight.max(other.max_in_flight);
+        self.num_samples += other.num_samples;
+    }
+
+    fn aggregated_in_flight(&self, perc_sample: &Sample) -> usize {
+        match self.in_flight_strategy {
+            InFlightStrategy::Matched => perc_sample.in_flight,
+            InFlightStrategy::Max => self.max_in_flight,
+            InFlightStrategy::Percentile => {
+                let index = self.percentile_index().expect("should have samples");
+                let mut seen = 0;
+                self.in_flights
+                    .iter()
+                    .find_map(|(in_flight, count)| {
+                        seen += count;
+                        (seen > index).then_some(*in_flight)
+                    })
+                    .expect("in flight should exist at expected index")
+            }
+        }
+    }
+
     fn percentile_sample(&self) -> Option<&Sample> {
         let index = self.percentile_index();
 
-        index.and_then(|index| {
-            self.samples
-                .iter()
-                .flat_map(|(_, sample)| sample)
-                .nth(index)
-        })
+        index.and_then(|index| self.samples.values().flatten().nth(index))
     }
 
     fn percentile_index(&self) -> Option<usize> {
@@ -117,6 +251,9 @@ impl Percentile {
 impl Aggregator for Percentile {
     fn sample(&mut self, sample: Sample) -> Sample {
         self.overload = self.overload.overloaded_or(sample.outcome);
+        self.overloads += usize::from(sample.outcome.is_overload());
+        *self.in_flights.entry(sample.in_flight).or_default() += 1;
+        self.max_in_flight = self.max_in_flight.max(sample.in_flight);
         self.samples.entry(sample.latency).or_default().push(sample);
         self.num_samples += 1;
 
@@ -125,12 +262,7 @@ impl Aggregator for Percentile {
             .expect("Sample should exist at expected index");
 
         Sample {
-            // TODO: what is best to do with the concurrency (in flight)?
-            //
-            // - max?
-            // - percentile?
-            // - match the sample of the latency percentile? <- Doing this one for now
-            in_flight: perc_sample.in_flight,
+            in_flight: self.aggregated_in_flight(perc_sample),
             latency: perc_sample.latency,
             outcome: self.overload,
         }
@@ -140,9 +272,14 @@ impl Aggregator for Percentile {
         self.num_samples
     }
 
+    fn overload_count(&self) -> usize {
+        self.overloads
+    }
+
     fn reset(&mut self) {
         *self = Self {
             percentile: self.percentile,
+            in_flight_strategy: self.in_flight_strategy,
             ..Default::default()
         };
     }
@@ -152,18 +289,230 @@ impl Default for Percentile {
     fn default() -> Self {
         Self {
             percentile: 0.5,
+            in_flight_strategy: InFlightStrategy::default(),
             samples: BTreeMap::new(),
+            in_flights: BTreeMap::new(),
+            max_in_flight: 0,
             num_samples: 0,
             overload: Outcome::Success,
+            overloads: 0,
+        }
+    }
+}
+
+impl OverloadRate {
+    /// Report overload when more than `threshold` of samples are overloaded, e.g. 0.01 = 1%.
+    pub fn new(threshold: f64) -> Self {
+        assert!(
+            (0. ..1.).contains(&threshold),
+            "threshold must be >= 0 and < 1"
+        );
+        Self {
+            threshold,
+            average: Average::default(),
         }
     }
 }
 
+impl Aggregator for OverloadRate {
+    fn sample(&mut self, sample: Sample) -> Sample {
+        let aggregated = self.average.sample(sample);
+        let rate = self.overload_count() as f64 / self.sample_size() as f64;
+        Sample {
+            outcome: if rate > self.threshold {
+                aggregated.outcome
+            } else {
+                Outcome::Success
+            },
+            ..aggregated
+        }
+    }
+
+    fn sample_size(&self) -> usize {
+        self.average.sample_size()
+    }
+
+    fn overload_count(&self) -> usize {
+        self.average.overload_count()
+    }
+
+    fn reset(&mut self) {
+        self.average.reset();
+    }
+}
+
+impl MultiAggregator {
+    #[allow(missing_docs)]
+    pub fn new(aggregators: Vec<Box<dyn Aggregator + Send + Sync>>) -> Self {
+        assert!(!aggregators.is_empty(), "at least one aggregator required");
+        Self {
+            aggregators,
+            latency_from: 0,
+            in_flight_from: 0,
+            outcome_from: 0,
+        }
+    }
+
+    /// Take the latency from the aggregator at `index`.
+    pub fn with_latency_from(mut self, index: usize) -> Self {
+        self.assert_index(index);
+        self.latency_from = index;
+        self
+    }
+
+    /// Take the in flight from the aggregator at `index`.
+    pub fn with_in_flight_from(mut self, index: usize) -> Self {
+        self.assert_index(index);
+        self.in_flight_from = index;
+        self
+    }
+
+    /// Take the outcome from the aggregator at `index`.
+    pub fn with_outcome_from(mut self, index: usize) -> Self {
+        self.assert_index(index);
+        self.outcome_from = index;
+        self
+    }
+
+    fn assert_index(&self, index: usize) {
+        assert!(
+            index < self.aggregators.len(),
+            "aggregator index out of bounds"
+        );
+    }
+}
+
+impl Aggregator for MultiAggregator {
+    fn sample(&mut self, sample: Sample) -> Sample {
+        let aggregated: Vec<Sample> = self
+            .aggregators
+            .iter_mut()
+            .map(|aggregator| aggregator.sample(sample.clone()))
+            .collect();
+
+        Sample {
+            latency: aggregated[self.latency_from].latency,
+            in_flight: aggregated[self.in_flight_from].in_flight,
+            outcome: aggregated[self.outcome_from].outcome,
+        }
+    }
+
+    fn sample_size(&self) -> usize {
+        self.aggregators[0].sample_size()
+    }
+
+    /// The overload count of the aggregator the outcome is taken from.
+    fn overload_count(&self) -> usize {
+        self.aggregators[self.outcome_from].overload_count()
+    }
+
+    fn reset(&mut self) {
+        for aggregator in &mut self.aggregators {
+            aggregator.reset();
+        }
+    }
+}
+
+impl Debug for MultiAggregator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MultiAggregator")
+            .field("aggregators", &self.aggregators.len())
+            .field("latency_from", &self.latency_from)
+            .field("in_flight_from", &self.in_flight_from)
+            .field("outcome_from", &self.outcome_from)
+            .finish()
+    }
+}
+
+impl MovingPercentile {
+    /// Estimate `percentile`, adjusting the estimate by a fraction `step` (scaled by the
+    /// percentile) for each sample.
+    ///
+    /// Smaller steps are more stable but slower to adapt. 0.01 is a reasonable starting point.
+    pub fn new(percentile: f64, step: f64) -> Self {
+        assert!(
+            percentile > 0. && percentile < 1.,
+            "percentiles must be between 0 and 1 exclusive"
+        );
+        assert!(
+            step > 0. && step < 1.,
+            "step must be between 0 and 1 exclusive"
+        );
+        Self {
+            percentile,
+            step,
+            estimate: None,
+            in_flight: 0.,
+            overload: Outcome::Success,
+            overloads: 0,
+            samples: 0,
+        }
+    }
+}
+
+impl Aggregator for MovingPercentile {
+    fn sample(&mut self, sample: Sample) -> Sample {
+        self.overload = self.overload.overloaded_or(sample.outcome);
+        self.overloads += usize::from(sample.outcome.is_overload());
+        self.samples += 1;
+
+        let estimate = match self.estimate {
+            None => {
+                self.in_flight = sample.in_flight as f64;
+                sample.latency
+            }
+            Some(estimate) => {
+                self.in_flight += (sample.in_flight as f64 - self.in_flight) * self.step;
+
+                // At equilibrium, P(above) * p = P(below) * (1 - p), i.e. P(below) = p.
+                match sample.latency.cmp(&estimate) {
+                    std::cmp::Ordering::Greater => {
+                        estimate.mul_f64(1. + self.step * self.percentile)
+                    }
+                    std::cmp::Ordering::Less => {
+                        estimate.mul_f64(1. - self.step * (1. - self.percentile))
+                    }
+                    std::cmp::Ordering::Equal => estimate,
+                }
+            }
+        };
+        // Multiplicative steps can't move a zero estimate, so seed from the first non-zero latency.
+        self.estimate = Some(estimate).filter(|estimate| !estimate.is_zero());
+
+        Sample {
+            in_flight: self
+                .in_flight
+                .round()
+                .approx_as::<usize>()
+                .expect("average of usizes should fit in a usize"),
+            latency: estimate,
+            outcome: self.overload,
+        }
+    }
+
+    fn sample_size(&self) -> usize {
+        self.samples
+    }
+
+    fn overload_count(&self) -> usize {
+        self.overloads
+    }
+
+    /// Resets the outcome and sample counts, but keeps the estimate.
+    fn reset(&mut self) {
+        self.overload = Outcome::Success;
+        self.overloads = 0;
+        self.samples = 0;
+    }
+}
+
 impl Debug for Percentile {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Percentile")
             .field("percentile", &self.percentile)
+            .field("in_flight_strategy", &self.in_flight_strategy)
             .field("overload", &self.overload)
+            .field("overloads", &self.overloads)
             .field("samples", &self.samples)
             .field("(aggregated sample)", &self.percentile_sample())
             .finish()
@@ -187,7 +536,7 @@ mod tests {
         aggregator.sample(Sample {
             in_flight: 5,
             latency: Duration::from_millis(3),
-            outcome: Outcome::Overload,
+            outcome: Outcome::overload(),
         });
 
         let sample = aggregator.sample(Sample {
@@ -201,7 +550,7 @@ mod tests {
             Sample {
                 in_flight: 3,
                 latency: Duration::from_millis(3),
-                outcome: Outcome::Overload,
+                outcome: Outcome::overload(),
             }
         );
     }
@@ -235,6 +584,80 @@ mod tests {
         )
     }
 
+    #[test]
+    fn overload_count() {
+        let aggregators: [Box<dyn Aggregator>; 3] = [
+            Box::new(Average::default()),
+            Box::new(Percentile::new(0.5)),
+            Box::new(MovingPercentile::new(0.5, 0.1)),
+        ];
+
+        for mut aggregator in aggregators {
+            for outcome in [
+                Outcome::Success,
+                Outcome::overload(),
+                Outcome::Success,
+                Outcome::overload(),
+                Outcome::overload(),
+            ] {
+                aggregator.sample(Sample {
+                    in_flight: 1,
+                    latency: Duration::from_millis(1),
+                    outcome,
+                });
+            }
+            assert_eq!(aggregator.overload_count(), 3);
+            assert_eq!(aggregator.total(), 5);
+
+            aggregator.reset();
+            assert_eq!(aggregator.overload_count(), 0);
+            assert_eq!(aggregator.total(), 0);
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let samples: Vec<Sample> = (1..=6)
+            .map(|i| Sample {
+                in_flight: i,
+                latency: Duration::from_millis(i as u64 * 10),
+                outcome: if i == 2 {
+                    Outcome::overload()
+                } else {
+                    Outcome::Success
+                },
+            })
+            .collect();
+
+        let (mut all_avg, mut avg_a, mut avg_b) =
+            (Average::default(), Average::default(), Average::default());
+        let (mut all_perc, mut perc_a, mut perc_b) = (
+            Percentile::new(0.9),
+            Percentile::new(0.9),
+            Percentile::new(0.9),
+        );
+        for (i, sample) in samples.into_iter().enumerate() {
+            all_avg.sample(sample.clone());
+            all_perc.sample(sample.clone());
+            if i % 2 == 0 {
+                avg_a.sample(sample.clone());
+                perc_a.sample(sample);
+            } else {
+                avg_b.sample(sample.clone());
+                perc_b.sample(sample);
+            }
+        }
+
+        avg_a.merge(&avg_b);
+        assert_eq!(format!("{avg_a:?}"), format!("{all_avg:?}"));
+
+        perc_a.merge(&perc_b);
+        assert_eq!(perc_a.sample_size(), all_perc.sample_size());
+        assert_eq!(perc_a.samples, all_perc.samples);
+        assert_eq!(perc_a.percentile_sample(), all_perc.percentile_sample());
+        assert_eq!(perc_a.overload, all_perc.overload);
+    }
+
     #[tokio::test]
     async fn percentile_p01() {
         let mut aggregator = Percentile::new(0.01);
@@ -242,7 +665,7 @@ mod tests {
         aggregator.sample(Sample {
             in_flight: 5,
             latency: Duration::from_millis(3),
-            outcome: Outcome::Overload,
+            outcome: Outcome::overload(),
         });
 
         aggregator.sample(Sample {
@@ -262,7 +685,7 @@ mod tests {
             Sample {
                 in_flight: 1,
                 latency: Duration::from_millis(1),
-                outcome: Outcome::Overload,
+                outcome: Outcome::overload(),
             }
         );
     }
@@ -274,7 +697,7 @@ mod tests {
         aggregator.sample(Sample {
             in_flight: 5,
             latency: Duration::from_millis(3),
-            outcome: Outcome::Overload,
+            outcome: Outcome::overload(),
         });
 
         aggregator.sample(Sample {
@@ -294,7 +717,7 @@ mod tests {
             Sample {
                 in_flight: 3,
                 latency: Duration::from_millis(5),
-                outcome: Outcome::Overload,
+                outcome: Outcome::overload(),
             }
         );
     }
@@ -332,4 +755,135 @@ mod tests {
             "percentile shouldn't change after reset"
         );
     }
+
+    #[test]
+    fn percentile_in_flight_strategies() {
+        // The P50 latency sample has 2 in flight, while the P50 in flight is 5.
+        let samples =
+            [(10, 8), (20, 5), (30, 2), (40, 1), (50, 9)].map(|(latency, in_flight)| Sample {
+                in_flight,
+                latency: Duration::from_millis(latency),
+                outcome: Outcome::Success,
+            });
+
+        for (strategy, expected) in [
+            (InFlightStrategy::Matched, 2),
+            (InFlightStrategy::Max, 9),
+            (InFlightStrategy::Percentile, 5),
+        ] {
+            let mut aggregator = Percentile::new(0.5).with_in_flight_strategy(strategy);
+            let mut aggregated = None;
+            for sample in samples.clone() {
+                aggregated = Some(aggregator.sample(sample));
+            }
+            let aggregated = aggregated.unwrap();
+            assert_eq!(aggregated.latency, Duration::from_millis(30));
+            assert_eq!(aggregated.in_flight, expected, "{strategy:?}");
+        }
+    }
+
+    #[test]
+    fn multi_aggregator() {
+        let mut aggregator = MultiAggregator::new(vec![
+            Box::new(Percentile::new(0.99)),
+            Box::new(OverloadRate::new(0.2)),
+        ])
+        .with_outcome_from(1);
+
+        let mut aggregated = None;
+        for i in 1..=10 {
+            aggregated = Some(aggregator.sample(Sample {
+                in_flight: i,
+                latency: Duration::from_millis(i as u64),
+                outcome: if i == 5 {
+                    Outcome::overload()
+                } else {
+                    Outcome::Success
+                },
+            }));
+        }
+
+        assert_eq!(
+            aggregated.unwrap(),
+            Sample {
+                in_flight: 10,
+                latency: Duration::from_millis(10),
+                outcome: Outcome::Success,
+            },
+            "P99 latency, and 10% overload rate is within threshold"
+        );
+        assert_eq!(aggregator.overload_count(), 1);
+    }
+
+    #[test]
+    fn moving_percentile_converges() {
+        use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+        let mut rng = SmallRng::seed_from_u64(1);
+        let mut aggregator = MovingPercentile::new(0.9, 0.01);
+
+        let mut aggregated = None;
+        for _ in 0..20_000 {
+            let latency = Duration::from_micros(rng.gen_range(0..100_000));
+            aggregated = Some(aggregator.sample(Sample {
+                in_flight: 10,
+                latency,
+                outcome: Outcome::Success,
+            }));
+        }
+
+        let aggregated = aggregated.unwrap();
+        let error = aggregated.latency.abs_diff(Duration::from_millis(90));
+        assert!(
+            error < Duration::from_millis(5),
+            "P90 of uniform 0-100ms. Estimate: {:?}",
+            aggregated.latency
+        );
+        assert_eq!(aggregated.in_flight, 10);
+    }
+
+    #[test]
+    fn moving_percentile_reset_keeps_estimate() {
+        let mut aggregator = MovingPercentile::new(0.5, 0.1);
+
+        let sample = Sample {
+            in_flight: 1,
+            latency: Duration::from_millis(10),
+            outcome: Outcome::overload(),
+        };
+        aggregator.sample(sample.clone());
+        aggregator.reset();
+        assert_eq!(aggregator.sample_size(), 0);
+
+        let aggregated = aggregator.sample(Sample {
+            outcome: Outcome::Success,
+            ..sample
+        });
+        assert_eq!(aggregated.latency, Duration::from_millis(10));
+        assert_eq!(aggregated.outcome, Outcome::Success);
+    }
+
+    #[test]
+    fn moving_percentile_zero_first_sample() {
+        let mut aggregator = MovingPercentile::new(0.5, 0.1);
+
+        let sample = Sample {
+            in_flight: 1,
+            latency: Duration::ZERO,
+            outcome: Outcome::Success,
+        };
+        assert_eq!(aggregator.sample(sample.clone()).latency, Duration::ZERO);
+
+        let aggregated = aggregator.sample(Sample {
+            latency: Duration::from_millis(10),
+            ..sample.clone()
+        });
+        assert_eq!(aggregated.latency, Duration::from_millis(10), "seeded");
+
+        let aggregated = aggregator.sample(Sample {
+            latency: Duration::from_millis(20),
+            ..sample
+        });
+        assert!(aggregated.latency > Duration::from_millis(10), "adapts");
+    }
 }
@@ -11,3 +11,5 @@ pub mod aggregation;
 pub mod limiter;
 pub mod limits;
 mod moving_avg;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
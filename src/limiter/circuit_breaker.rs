@@ -0,0 +1,402 @@
+use std::{collections::VecDeque, time::Duration};
+
+use async_trait::async_trait;
+use tokio::{
+    sync::Mutex,
+    time::{sleep, Instant},
+};
+
+use super::{CapacityUnit, Limiter, LimiterState, Outcome, Token};
+
+/// A wrapper which stops admitting any jobs for a cooldown period when overload is severe and
+/// sustained.
+///
+/// While [closed](CircuitState::Closed), jobs are admitted by the inner limiter as normal, and the
+/// rate of overload across recent outcomes is tracked. If it reaches the threshold, the circuit
+/// [opens](CircuitState::Open) and all acquisitions are rejected for the cooldown period. After
+/// that, the circuit is [half-open](CircuitState::HalfOpen), admitting a small number of probe
+/// jobs. A successful probe closes the circuit, while an overloaded probe opens it again. Outcomes
+/// of jobs admitted before the circuit became half-open are ignored. Probes which haven't been
+/// released within the cooldown period, e.g. because their token was dropped, are assumed to be
+/// lost, and new probes are admitted in their place.
+///
+/// See [with_rejection_delay()](Self::with_rejection_delay()) to delay rejections by the circuit.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    overload_threshold: f64,
+    window_size: usize,
+    cooldown: Duration,
+    half_open_probes: usize,
+    rejection_delay: Duration,
+
+    inner: Box<dyn Limiter>,
+    state: Mutex<State>,
+}
+
+/// The state of a [CircuitBreaker].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Admitting jobs as normal.
+    Closed,
+    /// Rejecting all jobs.
+    Open,
+    /// Admitting a limited number of jobs to probe whether overload has ended.
+    HalfOpen,
+}
+
+#[derive(Debug)]
+enum State {
+    Closed {
+        /// Whether each recent outcome was overload, newest last.
+        recent: VecDeque<bool>,
+    },
+    Open {
+        until: Instant,
+    },
+    HalfOpen {
+        probes: usize,
+        /// Jobs admitted before this aren't probes.
+        since: Instant,
+        /// When the most recent probe was admitted.
+        last_probe: Instant,
+    },
+}
+
+impl CircuitBreaker {
+    const DEFAULT_HALF_OPEN_PROBES: usize = 1;
+
+    /// Open the circuit for `cooldown` when the proportion of overloaded jobs in the last
+    /// `window_size` outcomes reaches `overload_threshold`.
+    pub fn new(
+        limiter: impl Limiter + 'static,
+        overload_threshold: f64,
+        window_size: usize,
+        cooldown: Duration,
+    ) -> Self {
+        assert!(
+            overload_threshold > 0. && overload_threshold <= 1.,
+            "overload threshold must be > 0 and <= 1"
+        );
+        assert!(window_size > 0, "window size must be > 0");
+        Self {
+            overload_threshold,
+            window_size,
+            cooldown,
+            half_open_probes: Self::DEFAULT_HALF_OPEN_PROBES,
+            rejection_delay: Duration::ZERO,
+            inner: Box::new(limiter),
+            state: Mutex::new(State::closed()),
+        }
+    }
+
+    /// The number of jobs admitted to probe for recovery while half-open.
+    pub fn with_half_open_probes(mut self, probes: usize) -> Self {
+        assert!(probes > 0, "half-open probes must be > 0");
+        self.half_open_probes = probes;
+        self
+    }
+
+    /// Wait for `delay` before returning when the circuit rejects a job, i.e. while open, or while
+    /// half-open with all probes in progress.
+    ///
+    /// Rejections by the inner limiter aren't delayed. Wrap the inner limiter with a
+    /// [RejectionDelay](super::RejectionDelay) for that.
+    pub fn with_rejection_delay(mut self, delay: Duration) -> Self {
+        self.rejection_delay = delay;
+        self
+    }
+
+    /// The current state of the circuit.
+    pub async fn state(&self) -> CircuitState {
+        let mut state = self.state.lock().await;
+        state.expire_open();
+        match *state {
+            State::Closed { .. } => CircuitState::Closed,
+            State::Open { .. } => CircuitState::Open,
+            State::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Whether a job may be admitted. Counts half-open probes, and applies the rejection delay if
+    /// not.
+    async fn admit(&self) -> bool {
+        let admitted = {
+            let mut state = self.state.lock().await;
+            state.expire_open();
+            match &mut *state {
+                State::Closed { .. } => true,
+                State::Open { .. } => false,
+                State::HalfOpen {
+                    probes, last_probe, ..
+                } => {
+                    // Probes dropped without being released never free up their slot.
+                    if *probes >= self.half_open_probes && last_probe.elapsed() >= self.cooldown {
+                        *probes = 0;
+                    }
+                    if *probes < self.half_open_probes {
+                        *probes += 1;
+                        *last_probe = Instant::now();
+                        true
+                    } else {
+                        false
+                    }
+                }
+            }
+        };
+        if !admitted {
+            sleep(self.rejection_delay).await;
+        }
+        admitted
+    }
+
+    /// Free up a half-open probe which didn't produce an outcome.
+    async fn not_admitted(&self) {
+        if let State::HalfOpen { probes, .. } = &mut *self.state.lock().await {
+            *probes = probes.saturating_sub(1);
+        }
+    }
+
+    /// Free up a half-open probe if `token` is one, which didn't produce an outcome.
+    async fn ignored(&self, token: &Token) {
+        let mut state = self.state.lock().await;
+        if let State::HalfOpen { probes, since, .. } = &mut *state {
+            if token.started_at() >= *since {
+                *probes = probes.saturating_sub(1);
+            }
+        }
+    }
+
+    async fn record(&self, token: &Token, outcome: Outcome) {
+        let mut state = self.state.lock().await;
+        match &mut *state {
+            State::Closed { recent } => {
+                if recent.len() == self.window_size {
+                    recent.pop_front();
+                }
+                recent.push_back(outcome.is_overload());
+
+                let overloaded = recent.iter().filter(|&&overload| overload).count();
+                if recent.len() == self.window_size
+                    && overloaded as f64 / self.window_size as f64 >= self.overload_threshold
+                {
+                    *state = State::Open {
+                        until: Instant::now() + self.cooldown,
+                    };
+                }
+            }
+            // Jobs admitted before the circuit opened.
+            State::Open { .. } => {}
+            // Jobs admitted before the circuit became half-open aren't probes.
+            State::HalfOpen { since, .. } if token.started_at() < *since => {}
+            State::HalfOpen { .. } => {
+                *state = if outcome.is_overload() {
+                    State::Open {
+                        until: Instant::now() + self.cooldown,
+                    }
+                } else {
+                    State::closed()
+                };
+            }
+        }
+    }
+}
+
+impl State {
+    fn closed() -> Self {
+        State::Closed {
+            recent: VecDeque::new(),
+        }
+    }
+
+    fn expire_open(&mut self) {
+        if let State::Open { until } = self {
+            if Instant::now() >= *until {
+                *self = State::HalfOpen {
+                    probes: 0,
+                    since: Instant::now(),
+                    last_probe: Instant::now(),
+                };
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Limiter for CircuitBreaker {
+    async fn try_acquire(&self) -> Option<Token> {
+        if !self.admit().await {
+            return None;
+        }
+        let token = self.inner.try_acquire().await;
+        if token.is_none() {
+            self.not_admitted().await;
+        }
+        token
+    }
+
+    /// Rejects immediately while the circuit is open.
+    async fn acquire_timeout(&self, duration: Duration) -> Option<Token> {
+        if !self.admit().await {
+            return None;
+        }
+        let token = self.inner.acquire_timeout(duration).await;
+        if token.is_none() {
+            self.not_admitted().await;
+        }
+        token
+    }
+
+    async fn release(&self, token: Token, outcome: Option<Outcome>) -> CapacityUnit {
+        match outcome {
+            Some(outcome) => self.record(&token, outcome).await,
+            // An ignored probe doesn't tell us anything, so allow another.
+            None => self.ignored(&token).await,
+        }
+        self.inner.release(token, outcome).await
+    }
+
+    fn limiter_state(&self) -> Option<LimiterState> {
+        self.inner.limiter_state()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::{self, Instant};
+
+    use crate::{
+        limiter::{CircuitBreaker, CircuitState, DefaultLimiter, Limiter, Outcome},
+        limits::Fixed,
+    };
+
+    fn circuit_breaker() -> CircuitBreaker {
+        CircuitBreaker::new(
+            DefaultLimiter::new(Fixed::new(10)),
+            0.5,
+            4,
+            Duration::from_secs(10),
+        )
+    }
+
+    async fn run(limiter: &CircuitBreaker, outcome: Outcome) {
+        let token = limiter.try_acquire().await.unwrap();
+        limiter.release(token, Some(outcome)).await;
+    }
+
+    #[tokio::test]
+    async fn transitions() {
+        time::pause();
+
+        let limiter = circuit_breaker();
+
+        for outcome in [
+            Outcome::Success,
+            Outcome::Success,
+            Outcome::overload(),
+            Outcome::Success,
+        ] {
+            run(&limiter, outcome).await;
+        }
+        assert_eq!(limiter.state().await, CircuitState::Closed);
+
+        run(&limiter, Outcome::overload()).await;
+        assert_eq!(limiter.state().await, CircuitState::Open, "2/4 overloaded");
+        assert!(limiter.try_acquire().await.is_none());
+
+        time::advance(Duration::from_secs(10)).await;
+        assert_eq!(limiter.state().await, CircuitState::HalfOpen);
+
+        let probe = limiter.try_acquire().await.unwrap();
+        assert!(limiter.try_acquire().await.is_none(), "one probe at a time");
+
+        limiter.release(probe, Some(Outcome::Success)).await;
+        assert_eq!(limiter.state().await, CircuitState::Closed);
+        assert!(limiter.try_acquire().await.is_some());
+    }
+
+    #[tokio::test]
+    async fn overloaded_probe_reopens() {
+        time::pause();
+
+        let limiter = circuit_breaker();
+
+        for _ in 0..4 {
+            run(&limiter, Outcome::overload()).await;
+        }
+        assert_eq!(limiter.state().await, CircuitState::Open);
+
+        time::advance(Duration::from_secs(10)).await;
+        run(&limiter, Outcome::overload()).await;
+        assert_eq!(limiter.state().await, CircuitState::Open);
+
+        time::advance(Duration::from_secs(5)).await;
+        assert!(limiter.try_acquire().await.is_none(), "cooldown restarted");
+    }
+
+    #[tokio::test]
+    async fn only_probes_close_circuit() {
+        time::pause();
+
+        let limiter = circuit_breaker();
+
+        let stale = limiter.try_acquire().await.unwrap();
+        for _ in 0..4 {
+            run(&limiter, Outcome::overload()).await;
+        }
+        time::advance(Duration::from_secs(10)).await;
+        assert_eq!(limiter.state().await, CircuitState::HalfOpen);
+
+        limiter.release(stale, Some(Outcome::Success)).await;
+        assert_eq!(
+            limiter.state().await,
+            CircuitState::HalfOpen,
+            "admitted while closed, so not a probe"
+        );
+
+        run(&limiter, Outcome::Success).await;
+        assert_eq!(limiter.state().await, CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn dropped_probe_expires() {
+        time::pause();
+
+        let limiter = circuit_breaker();
+
+        for _ in 0..4 {
+            run(&limiter, Outcome::overload()).await;
+        }
+        time::advance(Duration::from_secs(10)).await;
+
+        drop(limiter.try_acquire().await.unwrap());
+        assert!(limiter.try_acquire().await.is_none(), "probe in progress");
+
+        time::advance(Duration::from_secs(10)).await;
+        run(&limiter, Outcome::Success).await;
+        assert_eq!(
+            limiter.state().await,
+            CircuitState::Closed,
+            "dropped probe replaced"
+        );
+    }
+
+    #[tokio::test]
+    async fn rejection_delay() {
+        time::pause();
+
+        let limiter = circuit_breaker().with_rejection_delay(Duration::from_millis(100));
+
+        for _ in 0..4 {
+            run(&limiter, Outcome::overload()).await;
+        }
+
+        let start = Instant::now();
+        assert!(limiter.try_acquire().await.is_none());
+        assert!(
+            start.elapsed() >= Duration::from_millis(100),
+            "delayed while open"
+        );
+    }
+}
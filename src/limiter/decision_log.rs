@@ -0,0 +1,76 @@
+use std::collections::VecDeque;
+
+use tokio::sync::Mutex;
+
+use crate::limits::Sample;
+
+use super::CapacityUnit;
+
+/// A limit algorithm's decision in response to a release, for debugging.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decision {
+    sample: Sample,
+    old_limit: CapacityUnit,
+    new_limit: CapacityUnit,
+    applied: bool,
+}
+
+impl Decision {
+    /// The sample given to the algorithm.
+    pub fn sample(&self) -> &Sample {
+        &self.sample
+    }
+    /// The limit before the update.
+    pub fn old_limit(&self) -> CapacityUnit {
+        self.old_limit
+    }
+    /// The limit returned by the algorithm, after limiting the rate of increase.
+    pub fn new_limit(&self) -> CapacityUnit {
+        self.new_limit
+    }
+    /// Whether the new limit was applied, or suppressed by a minimum change interval.
+    pub fn applied(&self) -> bool {
+        self.applied
+    }
+}
+
+/// A ring buffer of the most recent [Decision]s.
+#[derive(Debug)]
+pub(crate) struct DecisionLog {
+    capacity: usize,
+    decisions: Mutex<VecDeque<Decision>>,
+}
+
+impl DecisionLog {
+    pub(crate) fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "decision log capacity must be > 0");
+        Self {
+            capacity,
+            decisions: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub(crate) async fn record(
+        &self,
+        sample: Sample,
+        old_limit: CapacityUnit,
+        new_limit: CapacityUnit,
+        applied: bool,
+    ) {
+        let mut decisions = self.decisions.lock().await;
+        if decisions.len() == self.capacity {
+            decisions.pop_front();
+        }
+        decisions.push_back(Decision {
+            sample,
+            old_limit,
+            new_limit,
+            applied,
+        });
+    }
+
+    /// Oldest first.
+    pub(crate) async fn recent(&self) -> Vec<Decision> {
+        self.decisions.lock().await.iter().cloned().collect()
+    }
+}
@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use super::{CapacityUnit, Limiter, Outcome, Token};
+
+/// A wrapper which falls back to a secondary limiter when the primary limiter rejects.
+///
+/// Useful for sending excess work down an overflow path, which has its own, usually stricter,
+/// limit. Tokens remember which limiter served them, so they are released back to the right one.
+#[derive(Debug)]
+pub struct Fallback {
+    primary: Box<dyn Limiter>,
+    secondary: Box<dyn Limiter>,
+}
+
+/// Which limiter a [Token] was acquired from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Source {
+    Primary,
+    Secondary,
+}
+
+impl Fallback {
+    #[allow(missing_docs)]
+    pub fn new(primary: impl Limiter + 'static, secondary: impl Limiter + 'static) -> Self {
+        Self {
+            primary: Box::new(primary),
+            secondary: Box::new(secondary),
+        }
+    }
+
+    fn tag(token: Option<Token>, source: Source) -> Option<Token> {
+        token.map(|mut token| {
+            token.push_source(source);
+            token
+        })
+    }
+}
+
+#[async_trait]
+impl Limiter for Fallback {
+    /// Try the primary limiter, then the secondary.
+    async fn try_acquire(&self) -> Option<Token> {
+        if let Some(token) = Self::tag(self.primary.try_acquire().await, Source::Primary) {
+            return Some(token);
+        }
+        Self::tag(self.secondary.try_acquire().await, Source::Secondary)
+    }
+
+    /// Try the primary limiter without waiting, then wait for the secondary for up to `duration`.
+    async fn acquire_timeout(&self, duration: Duration) -> Option<Token> {
+        if let Some(token) = Self::tag(self.primary.try_acquire().await, Source::Primary) {
+            return Some(token);
+        }
+        Self::tag(
+            self.secondary.acquire_timeout(duration).await,
+            Source::Secondary,
+        )
+    }
+
+    /// Release the token to whichever limiter it was acquired from.
+    ///
+    /// Returns that limiter's new limit.
+    async fn release(&self, mut token: Token, outcome: Option<Outcome>) -> CapacityUnit {
+        match token
+            .pop_source()
+            .expect("token should have been acquired from this limiter")
+        {
+            Source::Primary => self.primary.release(token, outcome).await,
+            Source::Secondary => self.secondary.release(token, outcome).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        limiter::{DefaultLimiter, Fallback, Limiter, Outcome},
+        limits::Fixed,
+    };
+
+    #[tokio::test]
+    async fn falls_back_to_secondary() {
+        let limiter = Fallback::new(
+            DefaultLimiter::new(Fixed::new(1)),
+            DefaultLimiter::new(Fixed::new(2)),
+        );
+
+        let first = limiter.try_acquire().await.unwrap();
+        let second = limiter.try_acquire().await.unwrap();
+        let third = limiter.try_acquire().await.unwrap();
+        assert!(limiter.try_acquire().await.is_none(), "both saturated");
+
+        assert_eq!(
+            limiter.release(third, Some(Outcome::Success)).await,
+            2,
+            "released to the secondary"
+        );
+        assert_eq!(
+            limiter.release(first, Some(Outcome::Success)).await,
+            1,
+            "released to the primary"
+        );
+        assert_eq!(
+            limiter.release(second, Some(Outcome::Success)).await,
+            2,
+            "released to the secondary"
+        );
+    }
+}
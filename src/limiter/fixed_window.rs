@@ -0,0 +1,193 @@
+use std::{
+    sync::{atomic::AtomicUsize, Arc},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use tokio::{sync::Mutex, time::Instant};
+
+use super::{CapacityUnit, Limiter, LimiterState, Outcome, Token};
+
+/// A rate limiter, admitting up to a fixed number of jobs per window of time.
+///
+/// Unlike the other limiters, this doesn't limit concurrency. Each window admits up to `limit`
+/// jobs regardless of how many are still in flight, and the count resets at the start of the next
+/// window. Windows are aligned to when the limiter was created.
+///
+/// [Releasing](Limiter::release()) a token doesn't make any more capacity available, and the
+/// outcome is ignored.
+#[derive(Debug)]
+pub struct FixedWindowRateLimiter {
+    limit: u32,
+    window: Duration,
+    start: Instant,
+
+    state: Mutex<Window>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+#[derive(Debug)]
+struct Window {
+    index: u128,
+    count: u32,
+}
+
+impl FixedWindowRateLimiter {
+    /// Admit up to `limit` jobs in each `window`.
+    pub fn new(limit: u32, window: Duration) -> Self {
+        assert!(limit > 0, "limit must be at least 1");
+        assert!(window > Duration::ZERO, "window must be > 0");
+        Self {
+            limit,
+            window,
+            start: Instant::now(),
+            state: Mutex::new(Window { index: 0, count: 0 }),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    fn window_index(&self, now: Instant) -> u128 {
+        now.duration_since(self.start).as_nanos() / self.window.as_nanos()
+    }
+
+    fn window_start(&self, index: u128) -> Instant {
+        let offset = self.window.as_nanos() * index;
+        self.start + Duration::from_nanos(u64::try_from(offset).unwrap_or(u64::MAX))
+    }
+
+    /// Count a job against the current window, if it has room. Otherwise, returns when the next
+    /// window starts.
+    async fn try_count(&self) -> Result<Token, Instant> {
+        let mut state = self.state.lock().await;
+
+        let index = self.window_index(Instant::now());
+        if index != state.index {
+            *state = Window { index, count: 0 };
+        }
+
+        if state.count < self.limit {
+            state.count += 1;
+            Ok(Token::new_overflow(self.in_flight.clone()))
+        } else {
+            Err(self.window_start(index + 1))
+        }
+    }
+
+    /// How many more jobs the current window will admit.
+    pub async fn available(&self) -> u32 {
+        let state = self.state.lock().await;
+        if state.index == self.window_index(Instant::now()) {
+            self.limit - state.count
+        } else {
+            self.limit
+        }
+    }
+}
+
+#[async_trait]
+impl Limiter for FixedWindowRateLimiter {
+    async fn try_acquire(&self) -> Option<Token> {
+        self.try_count().await.ok()
+    }
+
+    /// Wait for up to `duration` for a window with room.
+    async fn acquire_timeout(&self, duration: Duration) -> Option<Token> {
+        let deadline = Instant::now() + duration;
+        loop {
+            match self.try_count().await {
+                Ok(token) => return Some(token),
+                Err(next_window) if next_window <= deadline => {
+                    tokio::time::sleep_until(next_window).await;
+                }
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// Returns the limit per window.
+    async fn release(&self, token: Token, _outcome: Option<Outcome>) -> CapacityUnit {
+        drop(token);
+        self.limit as CapacityUnit
+    }
+
+    fn limiter_state(&self) -> Option<LimiterState> {
+        let limit = self.limit as CapacityUnit;
+        let available = match self.state.try_lock() {
+            Ok(state) if state.index == self.window_index(Instant::now()) => {
+                (self.limit - state.count) as CapacityUnit
+            }
+            Ok(_) => limit,
+            Err(_) => return None,
+        };
+        Some(LimiterState {
+            limit,
+            max_limit: limit,
+            available,
+            in_flight: self.in_flight.load(std::sync::atomic::Ordering::SeqCst),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time;
+
+    use crate::limiter::{FixedWindowRateLimiter, Limiter, Outcome};
+
+    #[tokio::test]
+    async fn window_rollover() {
+        time::pause();
+
+        let limiter = FixedWindowRateLimiter::new(2, Duration::from_secs(1));
+
+        let token = limiter.try_acquire().await.unwrap();
+        limiter.release(token, Some(Outcome::Success)).await;
+        assert!(limiter.try_acquire().await.is_some());
+        assert!(
+            limiter.try_acquire().await.is_none(),
+            "window full, even though a job was released"
+        );
+        assert_eq!(limiter.limiter_state().unwrap().available(), 0);
+
+        time::advance(Duration::from_secs(1)).await;
+        assert_eq!(limiter.available().await, 2, "new window");
+        assert!(limiter.try_acquire().await.is_some());
+        assert!(limiter.try_acquire().await.is_some());
+        assert!(limiter.try_acquire().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn window_boundary() {
+        time::pause();
+
+        let limiter = FixedWindowRateLimiter::new(1, Duration::from_secs(1));
+
+        time::advance(Duration::from_millis(999)).await;
+        assert!(limiter.try_acquire().await.is_some(), "end of first window");
+
+        time::advance(Duration::from_millis(1)).await;
+        assert!(
+            limiter.try_acquire().await.is_some(),
+            "start of second window"
+        );
+
+        let start = time::Instant::now();
+        assert!(
+            limiter
+                .acquire_timeout(Duration::from_millis(500))
+                .await
+                .is_none(),
+            "next window is too far away"
+        );
+        assert!(
+            limiter
+                .acquire_timeout(Duration::from_secs(1))
+                .await
+                .is_some(),
+            "waits for next window"
+        );
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+}
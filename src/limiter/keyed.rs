@@ -0,0 +1,259 @@
+use std::{collections::HashMap, fmt::Debug, hash::Hash, sync::Arc, time::Duration};
+
+use tokio::{sync::Mutex, time::Instant};
+
+use crate::limits::LimitAlgorithm;
+
+use super::{CapacityUnit, DefaultLimiter, Limiter, Outcome, Token};
+
+type Factory<K, L> = Box<dyn Fn(&K) -> DefaultLimiter<L> + Send + Sync>;
+
+/// A separate limiter per key, e.g. per customer or API key.
+///
+/// Limiters are created lazily on first use of a key. Limiters with no jobs in flight are evicted
+/// once they have been idle for the configured TTL, so memory usage doesn't grow without bound.
+/// Idle limiters are checked for at most once per TTL, so they might be kept for up to twice as
+/// long.
+pub struct KeyedLimiter<K, L> {
+    factory: Factory<K, L>,
+    idle_ttl: Duration,
+
+    limiters: Mutex<Limiters<K, L>>,
+}
+
+#[derive(Debug)]
+struct Limiters<K, L> {
+    entries: HashMap<K, Entry<L>>,
+    last_eviction: Instant,
+}
+
+#[derive(Debug)]
+struct Entry<L> {
+    limiter: Arc<DefaultLimiter<L>>,
+    last_used: Instant,
+}
+
+impl<K, L> KeyedLimiter<K, L>
+where
+    K: Hash + Eq + Clone,
+    L: LimitAlgorithm + Send + Sync + Debug,
+{
+    /// Create a keyed limiter, using `factory` to create a new limiter for each key.
+    ///
+    /// Limiters which have been idle for `idle_ttl` will be evicted. The TTL must be non-zero.
+    pub fn new(
+        idle_ttl: Duration,
+        factory: impl Fn(&K) -> DefaultLimiter<L> + Send + Sync + 'static,
+    ) -> Self {
+        assert!(idle_ttl > Duration::ZERO, "idle TTL must be > 0");
+        Self {
+            factory: Box::new(factory),
+            idle_ttl,
+            limiters: Mutex::new(Limiters {
+                entries: HashMap::new(),
+                last_eviction: Instant::now(),
+            }),
+        }
+    }
+
+    /// Try to immediately acquire a concurrency [Token] from the limiter for `key`.
+    ///
+    /// Returns `None` if there are none available.
+    pub async fn try_acquire(&self, key: &K) -> Option<Token> {
+        self.limiter(key).await.try_acquire().await
+    }
+
+    /// Try to acquire a concurrency [Token] from the limiter for `key`, waiting for `duration` if
+    /// there are none available.
+    ///
+    /// Returns `None` if there are none available after `duration`.
+    pub async fn acquire_timeout(&self, key: &K, duration: Duration) -> Option<Token> {
+        self.limiter(key).await.acquire_timeout(duration).await
+    }
+
+    /// Return the concurrency [Token] to the limiter for `key`, along with the outcome of the job.
+    ///
+    /// Returns the new limit for `key`.
+    ///
+    /// The token must have been acquired for `key`, otherwise the wrong limiter is updated. Panics
+    /// if there is no limiter for `key`, which can only happen if the token wasn't acquired for it.
+    pub async fn release(&self, key: &K, token: Token, outcome: Option<Outcome>) -> CapacityUnit {
+        let limiter = {
+            let mut limiters = self.limiters.lock().await;
+            let entry = limiters
+                .entries
+                .get_mut(key)
+                .expect("limiters with jobs in flight shouldn't be evicted");
+            entry.last_used = Instant::now();
+            entry.limiter.clone()
+        };
+
+        limiter.release(token, outcome).await
+    }
+
+    /// The number of keys with a limiter.
+    pub async fn len(&self) -> usize {
+        self.limiters.lock().await.entries.len()
+    }
+
+    /// Whether there are no keys with a limiter.
+    pub async fn is_empty(&self) -> bool {
+        self.limiters.lock().await.entries.is_empty()
+    }
+
+    /// Evict limiters which have no jobs in flight and have been idle for at least the TTL.
+    ///
+    /// This happens automatically when acquiring, at most once per TTL.
+    pub async fn evict_idle(&self) {
+        let mut limiters = self.limiters.lock().await;
+        self.evict_idle_locked(&mut limiters);
+    }
+
+    fn evict_idle_locked(&self, limiters: &mut Limiters<K, L>) {
+        limiters.entries.retain(|_, entry| {
+            entry.limiter.in_flight() > 0 || entry.last_used.elapsed() < self.idle_ttl
+        });
+        limiters.last_eviction = Instant::now();
+    }
+
+    /// Get the limiter for `key`, creating one if necessary.
+    async fn limiter(&self, key: &K) -> Arc<DefaultLimiter<L>> {
+        let mut limiters = self.limiters.lock().await;
+
+        // Scanning every limiter is O(n), so amortise it over many acquisitions.
+        if limiters.last_eviction.elapsed() >= self.idle_ttl {
+            self.evict_idle_locked(&mut limiters);
+        }
+
+        let entry = limiters
+            .entries
+            .entry(key.clone())
+            .or_insert_with(|| Entry {
+                limiter: Arc::new((self.factory)(key)),
+                last_used: Instant::now(),
+            });
+        entry.last_used = Instant::now();
+
+        entry.limiter.clone()
+    }
+}
+
+impl<K, L> Debug for KeyedLimiter<K, L>
+where
+    K: Debug,
+    L: Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeyedLimiter")
+            .field("idle_ttl", &self.idle_ttl)
+            .field("limiters", &self.limiters)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time;
+
+    use crate::{
+        limiter::{DefaultLimiter, KeyedLimiter, Outcome},
+        limits::Fixed,
+    };
+
+    fn keyed_limiter() -> KeyedLimiter<&'static str, Fixed> {
+        KeyedLimiter::new(Duration::from_secs(60), |_| {
+            DefaultLimiter::new(Fixed::new(1))
+        })
+    }
+
+    #[tokio::test]
+    async fn creates_limiters_lazily() {
+        let limiter = keyed_limiter();
+        assert!(limiter.is_empty().await);
+
+        let token = limiter.try_acquire(&"a").await.unwrap();
+        assert_eq!(limiter.len().await, 1);
+
+        limiter.release(&"a", token, Some(Outcome::Success)).await;
+        assert_eq!(limiter.len().await, 1);
+    }
+
+    #[tokio::test]
+    async fn independent_limits_per_key() {
+        let limiter = keyed_limiter();
+
+        let _a = limiter.try_acquire(&"a").await.unwrap();
+        assert!(limiter.try_acquire(&"a").await.is_none());
+
+        let _b = limiter.try_acquire(&"b").await.unwrap();
+        assert_eq!(limiter.len().await, 2);
+    }
+
+    #[tokio::test]
+    async fn evicts_idle_limiters() {
+        time::pause();
+
+        let limiter = keyed_limiter();
+
+        let token = limiter.try_acquire(&"idle").await.unwrap();
+        limiter
+            .release(&"idle", token, Some(Outcome::Success))
+            .await;
+        let _busy = limiter.try_acquire(&"busy").await.unwrap();
+
+        time::advance(Duration::from_secs(30)).await;
+        limiter.evict_idle().await;
+        assert_eq!(limiter.len().await, 2, "not idle for long enough");
+
+        time::advance(Duration::from_secs(30)).await;
+        limiter.evict_idle().await;
+        assert_eq!(limiter.len().await, 1, "idle limiter evicted");
+
+        assert!(
+            limiter.try_acquire(&"busy").await.is_none(),
+            "limiter with jobs in flight is kept"
+        );
+    }
+
+    #[tokio::test]
+    async fn evicts_when_acquiring() {
+        async fn use_key(limiter: &KeyedLimiter<&'static str, Fixed>, key: &'static str) {
+            let token = limiter.try_acquire(&key).await.unwrap();
+            limiter.release(&key, token, Some(Outcome::Success)).await;
+        }
+
+        time::pause();
+
+        let limiter = keyed_limiter();
+
+        use_key(&limiter, "a").await;
+        time::advance(Duration::from_secs(30)).await;
+        use_key(&limiter, "b").await;
+
+        time::advance(Duration::from_secs(30)).await;
+        use_key(&limiter, "c").await;
+        assert_eq!(
+            limiter.len().await,
+            2,
+            "a evicted, b not idle for long enough"
+        );
+
+        time::advance(Duration::from_secs(30)).await;
+        use_key(&limiter, "d").await;
+        assert_eq!(
+            limiter.len().await,
+            3,
+            "not checked again until a TTL later"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "idle TTL must be > 0")]
+    fn zero_idle_ttl() {
+        KeyedLimiter::new(Duration::ZERO, |_: &&str| {
+            DefaultLimiter::new(Fixed::new(1))
+        });
+    }
+}
@@ -2,29 +2,54 @@
 
 use std::{
     cmp,
+    collections::{HashMap, VecDeque},
     fmt::Debug,
+    pin::pin,
     sync::{
-        atomic::{AtomicUsize, Ordering},
+        atomic::{self, AtomicBool, AtomicU64, AtomicUsize, Ordering},
         Arc,
     },
     time::Duration,
 };
 
 use async_trait::async_trait;
-use conv::ValueFrom;
+use conv::{ConvUtil, ValueFrom};
+use rand::{Rng, RngCore};
 use tokio::{
-    sync::{OwnedSemaphorePermit, Semaphore, TryAcquireError},
-    time::timeout,
+    sync::{mpsc, watch, Mutex, Notify, OwnedSemaphorePermit, Semaphore, TryAcquireError},
+    time::{timeout, Instant},
 };
 
-pub use partitioning::PartitionedLimiter;
-pub use rejection_delay::RejectionDelay;
+pub use circuit_breaker::{CircuitBreaker, CircuitState};
+pub use decision_log::Decision;
+pub use fallback::Fallback;
+pub use fixed_window::FixedWindowRateLimiter;
+pub use keyed::KeyedLimiter;
+pub use partitioning::{PartitionedLimiter, RoundingMode};
+pub use rejection_delay::{DelayFn, RejectionDelay};
+pub use smart_rejection_delay::SmartRejectionDelay;
 pub use token::Token;
 
-use crate::limits::{LimitAlgorithm, Sample};
+use crate::{
+    aggregation::{Aggregator, Percentile},
+    limits::{LimitAlgorithm, Sample},
+    moving_avg::ExpSmoothed,
+};
+
+use decision_log::DecisionLog;
+use slo::Slo;
 
+mod circuit_breaker;
+mod decision_log;
+mod fallback;
+mod fixed_window;
+mod keyed;
 mod partitioning;
+#[cfg(feature = "prometheus")]
+mod prometheus_collector;
 mod rejection_delay;
+mod slo;
+mod smart_rejection_delay;
 mod token;
 
 type CapacityUnit = usize;
@@ -44,6 +69,15 @@ pub trait Limiter: Debug + Sync {
     /// Returns `None` if there are none available.
     async fn try_acquire(&self) -> Option<Token>;
 
+    /// Like [try_acquire()](Self::try_acquire()), tagging the acquisition with a `label`, e.g. the
+    /// name of the operation, so rejections can be attributed to call sites.
+    ///
+    /// The label is ignored by default.
+    async fn try_acquire_labeled(&self, label: &'static str) -> Option<Token> {
+        let _ = label;
+        self.try_acquire().await
+    }
+
     /// Try to acquire a concurrency [Token], waiting for `duration` if there are none available.
     ///
     /// Returns `None` if there are none available after `duration`.
@@ -59,6 +93,13 @@ pub trait Limiter: Debug + Sync {
     /// Returns the new limit.
     /// TODO: do we need to return the new limit?
     async fn release(&self, token: Token, outcome: Option<Outcome>) -> CapacityUnit;
+
+    /// A snapshot of the limiter's state, if it has one.
+    ///
+    /// `None` by default. Wrappers should return the state of the limiter they wrap.
+    fn limiter_state(&self) -> Option<LimiterState> {
+        None
+    }
 }
 
 /// A basic limiter.
@@ -69,24 +110,346 @@ pub struct DefaultLimiter<T> {
     limit_algo: T,
     semaphore: Arc<Semaphore>,
     limit: AtomicCapacityUnit,
+    /// Publishes changes to the limit.
+    limit_changes: watch::Sender<CapacityUnit>,
+    /// When the limit last changed, or when the limiter was created.
+    limit_changed_at: std::sync::Mutex<Instant>,
+    /// Publishes whether the limiter is saturated, i.e. no concurrency is available.
+    saturated: watch::Sender<bool>,
+
+    /// If set, the semaphore is sized to this limit, and the algorithm's limit is a soft limit.
+    hard_limit: Option<CapacityUnit>,
+    /// If set, the limit never exceeds this, whatever the algorithm reports.
+    absolute_max: Option<CapacityUnit>,
+    /// The number of times the algorithm's limit has been clamped to `absolute_max`.
+    absolute_max_clamps: AtomicUsize,
+    /// Notified when a token is released.
+    released: Notify,
+    /// Number of tasks waiting on `released`, so releases can skip notifying when there are none.
+    release_waiters: AtomicUsize,
+
+    /// Forget permits for limit decreases within `release()`, rather than in a spawned task.
+    inline_decrease: bool,
+    /// Permits still to be forgotten for limit decreases, when decreasing inline.
+    pending_decrease: AtomicUsize,
+    /// Permits waiting to be forgotten for limit decreases, including pending inline decreases.
+    forgetting: Arc<AtomicUsize>,
 
     /// Best-effort
     in_flight: Arc<AtomicCapacityUnit>,
+    /// Reject new jobs when the number in flight reaches the limit multiplied by this.
+    in_flight_grace: Option<f64>,
+
+    /// Total number of tokens acquired.
+    acquired: AtomicUsize,
+    /// Total number of acquisitions rejected.
+    rejected: AtomicUsize,
+    /// The most jobs in flight at once.
+    peak_in_flight: AtomicCapacityUnit,
+    /// Recent successful releases, if tracked.
+    goodput: Option<std::sync::Mutex<Goodput>>,
+    /// The highest limit seen while healthy, if tracked.
+    safe_max: Option<std::sync::Mutex<SafeMax>>,
+    /// Counts since the last [take_stats()](Self::take_stats()).
+    interval_stats: IntervalStats,
+    /// Rejections of [labeled](Limiter::try_acquire_labeled()) acquisitions.
+    label_rejections: std::sync::Mutex<HashMap<&'static str, usize>>,
+
+    /// While frozen, the algorithm is updated but its limit isn't applied.
+    frozen: AtomicBool,
+
+    /// Changes to the limit won't be applied more often than this.
+    min_change_interval: Option<Duration>,
+    last_change: Mutex<Option<Instant>>,
+
+    /// The limit won't be increased faster than this.
+    max_increase_per_second: Option<CapacityUnit>,
+    last_increase: Mutex<Instant>,
+
+    /// What acquire timeouts do to the limit.
+    timeout_policy: TimeoutPolicy,
+    /// The latency of the most recently released job, in nanoseconds, or `u64::MAX` if none.
+    recent_latency: AtomicU64,
+    /// Smoothed latency of recently released jobs, if tracked.
+    mean_latency: Option<std::sync::Mutex<ExpSmoothed>>,
+    /// Number of callers waiting in [acquire_timeout()](Limiter::acquire_timeout()).
+    acquire_waiters: AtomicUsize,
+
+    /// Never reject, but count would-be rejections.
+    shadow: bool,
+    shadow_rejections: AtomicCapacityUnit,
+    /// Until this time, behave as if in shadow mode.
+    soft_start_until: Option<Instant>,
+
+    /// How long recent callers waited in [acquire_timeout()](Limiter::acquire_timeout()).
+    acquire_waits: Mutex<VecDeque<Duration>>,
+    /// Latencies of recent jobs, by outcome, if tracked.
+    outcome_latencies: Option<Mutex<OutcomeLatencies>>,
+    /// Which job latencies the limit algorithm sees.
+    latency_source: LatencySource,
+    /// The latency of the most recent successful job, in nanoseconds, or `u64::MAX` if none. Only
+    /// kept for [LatencySource::SuccessOnly].
+    recent_success_latency: AtomicU64,
+
+    /// Latency SLO compliance of recent jobs.
+    slo: Option<Slo>,
+
+    /// Recent algorithm decisions, for debugging.
+    decision_log: Option<DecisionLog>,
+
+    /// Receives a copy of every released job's sample.
+    sample_channel: Option<mpsc::Sender<Sample>>,
+
+    /// The latency used for out-of-band signals.
+    signal_latency: Option<Duration>,
+
+    /// Called on each rejection.
+    on_reject: Option<OnReject>,
+
+    /// Randomness, e.g. for jitter. Uses the thread RNG if not set.
+    rng: Option<SharedRng>,
 
     #[cfg(test)]
     notifier: Option<Arc<tokio::sync::Notify>>,
 }
 
+/// A function called with the state of the limiter each time an acquisition is rejected.
+pub type OnRejectFn = Box<dyn Fn(LimiterState) + Send + Sync>;
+
+struct OnReject(OnRejectFn);
+
+impl Debug for OnReject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("OnReject")
+    }
+}
+
+struct SharedRng(std::sync::Mutex<Box<dyn RngCore + Send>>);
+
+impl Debug for SharedRng {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SharedRng")
+    }
+}
+
+/// A snapshot of a [DefaultLimiter] in one call, for remote collection, e.g. by a central
+/// coordinator over RPC. See [DefaultLimiter::limiter_report()].
+///
+/// Serialisable with the `serde` feature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LimiterReport {
+    /// The current concurrency limit.
+    pub limit: CapacityUnit,
+    /// The number of jobs in flight.
+    pub in_flight: CapacityUnit,
+    /// The amount of concurrency available to use.
+    pub available: CapacityUnit,
+    /// The most jobs in flight at once.
+    pub peak_in_flight: CapacityUnit,
+    /// The total number of acquisitions rejected.
+    pub rejected: usize,
+    /// The latency the limit algorithm considers to be the baseline, if it has learned one.
+    pub baseline_latency: Option<Duration>,
+}
+
 /// A snapshot of the state of the [Limiter].
 ///
 /// Not guaranteed to be consistent under high concurrency.
 #[derive(Debug, Clone, Copy)]
 pub struct LimiterState {
     limit: CapacityUnit,
+    max_limit: CapacityUnit,
     available: CapacityUnit,
     in_flight: CapacityUnit,
 }
 
+/// What an acquire timeout does to the limit. See [DefaultLimiter::with_timeout_policy()].
+///
+/// Acquire timeouts are never treated as overload, since they are caused by the limit itself
+/// rather than by the system being protected. Treating them as overload would shrink the limit
+/// exactly when it is too small.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeoutPolicy {
+    /// Acquire timeouts don't affect the limit.
+    #[default]
+    Ignore,
+    /// Notify the limit algorithm, if the latency of recent jobs suggests that this limiter is the
+    /// bottleneck. See [DefaultLimiter::with_acquire_timeout_feedback()].
+    SelfShed,
+    /// Nudge the limit up, assuming this limiter is the bottleneck and the system being protected
+    /// is fine.
+    ///
+    /// Updates the algorithm with a successful sample at the current number in flight, the same as
+    /// [signal_success()](DefaultLimiter::signal_success()).
+    Grow,
+}
+
+/// Which job latencies are fed to the limit algorithm. See
+/// [DefaultLimiter::with_latency_source()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LatencySource {
+    /// The latency of every job.
+    #[default]
+    All,
+    /// Only the latency of successful jobs.
+    ///
+    /// Overloaded jobs, e.g. timeouts, can have very different latencies which skew delay-based
+    /// algorithms. With this, overloads still reach the algorithm as a loss signal, but carrying
+    /// the latency of the most recent successful job instead of their own.
+    SuccessOnly,
+}
+
+/// Counts of limiter activity over an interval. See [DefaultLimiter::take_stats()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimiterStats {
+    acquired: usize,
+    rejected: usize,
+    overloads: usize,
+}
+
+#[derive(Debug, Default)]
+struct IntervalStats {
+    acquired: AtomicUsize,
+    rejected: AtomicUsize,
+    overloads: AtomicUsize,
+}
+
+/// Recent job latencies, by outcome.
+#[derive(Debug, Default)]
+struct OutcomeLatencies {
+    success: VecDeque<Duration>,
+    overload: VecDeque<Duration>,
+}
+
+impl OutcomeLatencies {
+    fn record(&mut self, sample: &Sample, capacity: usize) {
+        let latencies = if sample.outcome.is_overload() {
+            &mut self.overload
+        } else {
+            &mut self.success
+        };
+        if latencies.len() == capacity {
+            latencies.pop_front();
+        }
+        latencies.push_back(sample.latency);
+    }
+}
+
+/// The given percentile of some durations, or zero if there are none.
+fn percentile_of<'a>(durations: impl Iterator<Item = &'a Duration>, percentile: f64) -> Duration {
+    let mut aggregator = Percentile::new(percentile);
+    let mut aggregated = None;
+    for &latency in durations {
+        aggregated = Some(aggregator.sample(Sample {
+            latency,
+            in_flight: 0,
+            outcome: Outcome::Success,
+        }));
+    }
+    aggregated.map_or(Duration::ZERO, |sample| sample.latency)
+}
+
+/// Tracks the highest limit under which jobs succeeded while the recent success rate was healthy.
+#[derive(Debug)]
+struct SafeMax {
+    /// Exponentially smoothed.
+    success_rate: f64,
+    max: Option<CapacityUnit>,
+}
+
+impl SafeMax {
+    const SMOOTHING: f64 = 0.1;
+    const HEALTHY_SUCCESS_RATE: f64 = 0.95;
+
+    fn record(&mut self, outcome: Outcome, limit: CapacityUnit) {
+        let success = if outcome.is_overload() { 0. } else { 1. };
+        self.success_rate += (success - self.success_rate) * Self::SMOOTHING;
+
+        if success == 1. && self.success_rate >= Self::HEALTHY_SUCCESS_RATE {
+            self.max = Some(self.max.map_or(limit, |max| max.max(limit)));
+        }
+    }
+}
+
+impl Default for SafeMax {
+    fn default() -> Self {
+        Self {
+            success_rate: 1.,
+            max: None,
+        }
+    }
+}
+
+/// Counts successful releases in time buckets over a rolling window.
+#[derive(Debug)]
+struct Goodput {
+    start: Instant,
+    /// Bucket index and count, oldest first.
+    buckets: VecDeque<(u64, usize)>,
+}
+
+impl Goodput {
+    const WINDOW: Duration = Duration::from_secs(10);
+    const BUCKET: Duration = Duration::from_millis(100);
+
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            buckets: VecDeque::new(),
+        }
+    }
+
+    /// Drop buckets which have left the window, returning the current bucket index.
+    fn prune(&mut self) -> u128 {
+        let current = self.start.elapsed().as_millis() / Self::BUCKET.as_millis();
+        let buckets_per_window = Self::WINDOW.as_millis() / Self::BUCKET.as_millis();
+        while self
+            .buckets
+            .front()
+            .is_some_and(|&(bucket, _)| u128::from(bucket) + buckets_per_window <= current)
+        {
+            self.buckets.pop_front();
+        }
+        current
+    }
+
+    fn record(&mut self) {
+        let current = u64::try_from(self.prune()).unwrap_or(u64::MAX);
+        match self.buckets.back_mut() {
+            Some((bucket, count)) if *bucket == current => *count += 1,
+            _ => self.buckets.push_back((current, 1)),
+        }
+    }
+
+    fn per_second(&mut self) -> f64 {
+        self.prune();
+        let successes: usize = self.buckets.iter().map(|(_, count)| count).sum();
+        successes as f64 / Self::WINDOW.as_secs_f64()
+    }
+}
+
+/// Decrements a waiter count when dropped, including on cancellation.
+struct WaiterGuard<'a>(&'a AtomicUsize);
+
+impl Drop for WaiterGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// The limiter has been [closed](DefaultLimiter::close()).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Closed;
+
+impl std::fmt::Display for Closed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("limiter closed")
+    }
+}
+
+impl std::error::Error for Closed {}
+
 /// Whether a job succeeded or failed as a result of congestion/overload.
 ///
 /// Errors not considered to be caused by overload should be ignored.
@@ -96,13 +459,40 @@ pub enum Outcome {
     Success,
     /// The job failed because of overload, e.g. it timed out or an explicit backpressure signal
     /// was observed.
-    Overload,
+    ///
+    /// Use [Outcome::overload] if the kind of overload isn't known or doesn't matter.
+    Overload(OverloadKind),
+}
+
+/// How overload was detected.
+///
+/// Algorithms may use this to react differently to different signals, but can ignore it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverloadKind {
+    /// No more specific reason was given.
+    #[default]
+    Unspecified,
+    /// The job timed out.
+    Timeout,
+    /// A response with the given status code, e.g. HTTP 503 or 429.
+    StatusCode(u16),
+    /// An explicit backpressure signal was received, e.g. a rejection from a downstream limiter.
+    Explicit,
 }
 
 impl<T> DefaultLimiter<T>
 where
     T: LimitAlgorithm,
 {
+    /// The number of recent acquire wait times to keep.
+    const ACQUIRE_WAIT_SAMPLES: usize = 1024;
+    /// The number of recent job latencies to keep, for each outcome.
+    const OUTCOME_LATENCY_SAMPLES: usize = 1024;
+    /// The window size for the mean latency used to estimate wait times.
+    const MEAN_LATENCY_WINDOW: u16 = 100;
+    /// The most samples a [release_bulk()](Self::release_bulk()) updates the algorithm with.
+    const MAX_BULK_SAMPLES: usize = 10;
+
     /// Create a limiter with a given limit control algorithm.
     pub fn new(limit_algo: T) -> Self {
         let initial_permits = limit_algo.limit();
@@ -111,187 +501,2711 @@ where
             limit_algo,
             semaphore: Arc::new(Semaphore::new(initial_permits)),
             limit: AtomicCapacityUnit::new(initial_permits),
+            limit_changes: watch::Sender::new(initial_permits),
+            limit_changed_at: std::sync::Mutex::new(Instant::now()),
+            saturated: watch::Sender::new(false),
             in_flight: Arc::new(AtomicCapacityUnit::new(0)),
+            in_flight_grace: None,
+
+            hard_limit: None,
+            absolute_max: None,
+            absolute_max_clamps: AtomicUsize::new(0),
+            released: Notify::new(),
+            release_waiters: AtomicUsize::new(0),
+
+            inline_decrease: false,
+            pending_decrease: AtomicUsize::new(0),
+            forgetting: Arc::new(AtomicUsize::new(0)),
+
+            acquired: AtomicUsize::new(0),
+            rejected: AtomicUsize::new(0),
+            peak_in_flight: AtomicCapacityUnit::new(0),
+            goodput: None,
+            safe_max: None,
+            interval_stats: IntervalStats::default(),
+            label_rejections: std::sync::Mutex::default(),
+
+            frozen: AtomicBool::new(false),
+
+            min_change_interval: None,
+            last_change: Mutex::new(None),
+
+            max_increase_per_second: None,
+            last_increase: Mutex::new(Instant::now()),
+
+            timeout_policy: TimeoutPolicy::default(),
+            recent_latency: AtomicU64::new(u64::MAX),
+            mean_latency: None,
+            acquire_waiters: AtomicUsize::new(0),
+
+            shadow: false,
+            soft_start_until: None,
+            shadow_rejections: AtomicCapacityUnit::new(0),
+
+            acquire_waits: Mutex::new(VecDeque::with_capacity(Self::ACQUIRE_WAIT_SAMPLES)),
+            outcome_latencies: None,
+            latency_source: LatencySource::default(),
+            recent_success_latency: AtomicU64::new(u64::MAX),
+
+            slo: None,
+
+            decision_log: None,
+
+            sample_channel: None,
+
+            signal_latency: None,
+            on_reject: None,
+            rng: None,
 
             #[cfg(test)]
             notifier: None,
         }
     }
 
-    /// In some cases [Token]s are acquired asynchronously when updating the limit.
-    #[cfg(test)]
-    pub fn with_release_notifier(mut self, n: Arc<tokio::sync::Notify>) -> Self {
-        self.notifier.replace(n);
+    /// Create a limiter from a target throughput, rather than a concurrency limit.
+    ///
+    /// Uses [Little's law](https://en.wikipedia.org/wiki/Little%27s_law) to derive the initial
+    /// limit: the concurrency needed to sustain `max_rps` requests per second when each takes
+    /// `expected_latency`, rounded up to at least 1. `algo_builder` creates the limit algorithm
+    /// with this initial limit, e.g. `|limit| Aimd::new_with_initial_limit(limit)`.
+    pub fn from_throughput(
+        max_rps: f64,
+        expected_latency: Duration,
+        algo_builder: impl FnOnce(CapacityUnit) -> T,
+    ) -> Self {
+        assert!(
+            max_rps.is_finite() && max_rps > 0.,
+            "max throughput must be finite and > 0"
+        );
+        let initial_limit = (max_rps * expected_latency.as_secs_f64())
+            .ceil()
+            .max(1.)
+            .approx_as::<CapacityUnit>()
+            .expect("limit should fit in a usize");
+
+        Self::new(algo_builder(initial_limit))
+    }
+
+    /// Use the algorithm's limit as a soft limit, with a separate, fixed hard limit.
+    ///
+    /// New jobs will be rejected when the number of jobs in flight reaches the soft limit. When the
+    /// soft limit decreases, jobs already in flight are allowed to finish. The hard limit is never
+    /// exceeded, even if the soft limit is higher.
+    ///
+    /// Jobs should be released using [release()](Limiter::release()), so that jobs waiting in
+    /// [acquire_timeout()](Limiter::acquire_timeout()) can be notified.
+    pub fn with_hard_limit(mut self, hard_limit: CapacityUnit) -> Self {
+        assert!(
+            hard_limit >= self.limit(),
+            "hard limit must not be less than the initial limit"
+        );
+        self.semaphore = Arc::new(Semaphore::new(hard_limit));
+        self.hard_limit = Some(hard_limit);
         self
     }
 
-    fn new_sample(&self, latency: Duration, outcome: Outcome) -> Sample {
-        Sample {
-            latency,
-            in_flight: self.in_flight(),
-            outcome,
+    /// Never let the limit exceed `absolute_max`, whatever the algorithm reports.
+    ///
+    /// A last-resort guard against a misbehaving algorithm, independent of the algorithm's own
+    /// maximum. The initial limit is clamped too. See
+    /// [absolute_max_clamps()](Self::absolute_max_clamps()) for how often this has engaged.
+    pub fn with_absolute_max(mut self, absolute_max: CapacityUnit) -> Self {
+        assert!(absolute_max >= 1, "absolute max must be at least 1");
+        self.absolute_max = Some(absolute_max);
+
+        let limit = self.limit();
+        if limit > absolute_max {
+            self.absolute_max_clamps.fetch_add(1, Ordering::Relaxed);
+            if self.hard_limit.is_none() {
+                self.semaphore = Arc::new(Semaphore::new(absolute_max));
+            }
+            self.limit = AtomicCapacityUnit::new(absolute_max);
+            self.limit_changes.send_replace(absolute_max);
         }
+        self
     }
 
-    fn available(&self) -> CapacityUnit {
-        self.semaphore.available_permits()
+    /// Apply changes to the limit at most once per `interval`.
+    ///
+    /// The limit algorithm will continue to be updated on every release, but changes to the
+    /// available concurrency will be suppressed until `interval` has elapsed since the last change.
+    /// The latest limit will then be applied on the next release.
+    ///
+    /// This dampens high-frequency oscillation of the limit.
+    pub fn with_min_change_interval(mut self, interval: Duration) -> Self {
+        self.min_change_interval = Some(interval);
+        self
     }
 
-    pub(crate) fn limit(&self) -> CapacityUnit {
-        self.limit.load(Ordering::Acquire)
+    /// Apply limit decreases within [release()](Limiter::release()), instead of in a spawned task.
+    ///
+    /// By default, decreases are applied by a task spawned onto the Tokio runtime, which waits for
+    /// enough jobs to finish. This mode avoids spawning, e.g. for determinism or to avoid tasks
+    /// outliving the runtime. However, `release()` can then take longer to complete while the
+    /// limiter is saturated, as it waits for other jobs to be released. Decreases caused by
+    /// [report()](Self::report()) are applied on the next release.
+    pub fn with_inline_decrease(mut self, enabled: bool) -> Self {
+        self.inline_decrease = enabled;
+        self
     }
 
-    fn in_flight(&self) -> CapacityUnit {
-        self.in_flight.load(Ordering::Acquire)
+    /// Increase the limit by at most `increase` per second, regardless of what the limit algorithm
+    /// requests. Decreases are applied as normal.
+    ///
+    /// Prevents the limit from quickly overshooting the real capacity, which can cause oscillation.
+    pub fn with_max_increase_per_second(mut self, increase: CapacityUnit) -> Self {
+        assert!(increase > 0, "max increase must be > 0");
+        self.max_increase_per_second = Some(increase);
+        self
     }
 
-    pub(crate) fn in_flight_shared(&self) -> Arc<AtomicCapacityUnit> {
-        self.in_flight.clone()
+    /// Notify the limit algorithm when [acquire_timeout()](Limiter::acquire_timeout()) times out
+    /// because of the limit. See [LimitAlgorithm::on_acquire_timeout()].
+    ///
+    /// An acquire timeout means this limiter is the bottleneck, which might indicate that the limit
+    /// is too low. However, if jobs are slow to complete, the timeout might instead be caused by
+    /// congestion downstream. As a heuristic, the algorithm will only be notified if the latency of
+    /// the most recently completed job is less than the acquire timeout duration.
+    ///
+    /// The same as [TimeoutPolicy::SelfShed] if enabled, or [TimeoutPolicy::Ignore] otherwise.
+    pub fn with_acquire_timeout_feedback(self, enabled: bool) -> Self {
+        self.with_timeout_policy(if enabled {
+            TimeoutPolicy::SelfShed
+        } else {
+            TimeoutPolicy::Ignore
+        })
     }
 
-    /// The current state of the limiter.
-    pub fn state(&self) -> LimiterState {
-        LimiterState {
-            limit: self.limit(),
-            available: self.available(),
-            in_flight: self.in_flight(),
-        }
+    /// Choose what, if anything, an [acquire_timeout()](Limiter::acquire_timeout()) timing out
+    /// does to the limit. Defaults to [TimeoutPolicy::Ignore].
+    pub fn with_timeout_policy(mut self, policy: TimeoutPolicy) -> Self {
+        self.timeout_policy = policy;
+        self
     }
 
-    pub(crate) fn mint_token(&self, permit: OwnedSemaphorePermit) -> Token {
-        Token::new(permit, self.in_flight.clone())
+    /// Choose which job latencies the limit algorithm sees. Defaults to [LatencySource::All].
+    pub fn with_latency_source(mut self, source: LatencySource) -> Self {
+        self.latency_source = source;
+        self
     }
-}
 
-#[async_trait]
-impl<T> Limiter for DefaultLimiter<T>
-where
-    T: LimitAlgorithm + Sync + Debug,
-{
-    async fn try_acquire(&self) -> Option<Token> {
-        match Arc::clone(&self.semaphore).try_acquire_owned() {
-            Ok(permit) => Some(self.mint_token(permit)),
-            Err(TryAcquireError::NoPermits) => None,
+    /// Reject new jobs once the number in flight reaches `limit * grace`, even if concurrency is
+    /// otherwise available.
+    ///
+    /// More jobs than the limit can be in flight, e.g. while a decrease in the limit is being
+    /// applied. This caps the overshoot. `grace` must be at least 1.
+    pub fn with_in_flight_grace(mut self, grace: f64) -> Self {
+        assert!(grace >= 1., "in-flight grace must be >= 1");
+        self.in_flight_grace = Some(grace);
+        self
+    }
 
-            Err(TryAcquireError::Closed) => {
-                panic!("we own the semaphore, we shouldn't have closed it")
-            }
+    /// Randomise the initial limit within `±fraction` of the algorithm's initial limit.
+    ///
+    /// When many instances start at the same time, e.g. after a deploy, they would otherwise all
+    /// ramp up in lockstep, creating synchronised waves of load downstream. The jittered limit is
+    /// kept within the algorithm's limit bounds (and any hard limit), and applies until the
+    /// algorithm first changes the limit.
+    ///
+    /// To use a [custom RNG](Self::with_rng()), set it before calling this.
+    pub fn with_initial_limit_jitter(mut self, fraction: f64) -> Self {
+        assert!(
+            (0. ..1.).contains(&fraction),
+            "jitter fraction must be >= 0 and < 1"
+        );
+        let initial = self.limit() as f64;
+        let jittered = (initial * (1. + self.gen_range(-fraction..=fraction)))
+            .round()
+            .approx_as::<CapacityUnit>()
+            .expect("limit should fit in a usize");
+        let max = self
+            .hard_limit
+            .unwrap_or(CapacityUnit::MAX)
+            .min(self.absolute_max.unwrap_or(CapacityUnit::MAX))
+            .min(self.limit_algo.max_limit());
+        let jittered = jittered.clamp(self.limit_algo.min_limit().max(1), max);
+
+        if self.hard_limit.is_none() {
+            self.semaphore = Arc::new(Semaphore::new(jittered));
         }
+        self.limit = AtomicCapacityUnit::new(jittered);
+        self.limit_changes.send_replace(jittered);
+        self
     }
 
-    async fn acquire_timeout(&self, duration: Duration) -> Option<Token> {
-        match timeout(duration, Arc::clone(&self.semaphore).acquire_owned()).await {
-            Ok(Ok(permit)) => Some(self.mint_token(permit)),
-            Err(_) => None,
+    /// Use `rng` for randomness, e.g. jitter, instead of the thread RNG.
+    ///
+    /// Inject a seeded RNG to make tests deterministic.
+    pub fn with_rng(mut self, rng: impl RngCore + Send + 'static) -> Self {
+        self.rng = Some(SharedRng(std::sync::Mutex::new(Box::new(rng))));
+        self
+    }
 
-            Ok(Err(_)) => {
-                panic!("we own the semaphore, we shouldn't have closed it")
-            }
+    /// A random number in `range`, from the [configured RNG](Self::with_rng()) if set.
+    fn gen_range(&self, range: std::ops::RangeInclusive<f64>) -> f64 {
+        match &self.rng {
+            Some(rng) => rng
+                .0
+                .lock()
+                .expect("lock should not be poisoned")
+                .gen_range(range),
+            None => rand::thread_rng().gen_range(range),
         }
     }
 
-    async fn release(&self, token: Token, outcome: Option<Outcome>) -> CapacityUnit {
-        let limit = if let Some(outcome) = outcome {
-            let sample = self.new_sample(token.latency(), outcome);
-
-            let new_limit = self.limit_algo.update(sample).await;
+    /// Run in shadow mode, where acquisitions always succeed.
+    ///
+    /// The limit is still updated as normal. When there is no available concurrency, instead of
+    /// rejecting, a [Token] is issued beyond the limit and the would-be rejection is counted. See
+    /// [shadow_rejections()](Self::shadow_rejections()).
+    ///
+    /// Useful for validating the behaviour of a limiter before enforcing it.
+    pub fn shadow(mut self, enabled: bool) -> Self {
+        self.shadow = enabled;
+        self
+    }
 
-            let old_limit = self.limit.swap(new_limit, Ordering::SeqCst);
+    /// Never reject during the initial `period`, as in [shadow mode](Self::shadow()).
+    ///
+    /// Before the limit algorithm has learned anything, the initial limit might be too low. Jobs
+    /// admitted beyond the limit still update the limit when released, so the algorithm can learn
+    /// without rejecting legitimate traffic. Normal rejection resumes after `period`.
+    pub fn with_soft_start(mut self, period: Duration) -> Self {
+        self.soft_start_until = Some(Instant::now() + period);
+        self
+    }
 
-            match new_limit.cmp(&old_limit) {
-                cmp::Ordering::Greater => {
-                    self.semaphore.add_permits(new_limit - old_limit);
+    /// Whether acquisitions should never be rejected, either in shadow mode or during soft start.
+    fn never_reject(&self) -> bool {
+        self.shadow
+            || self
+                .soft_start_until
+                .is_some_and(|until| Instant::now() < until)
+    }
 
-                    #[cfg(test)]
-                    if let Some(n) = &self.notifier {
-                        n.notify_one();
-                    }
-                }
-                cmp::Ordering::Less => {
-                    let semaphore = self.semaphore.clone();
-                    #[cfg(test)]
-                    let notifier = self.notifier.clone();
-
-                    tokio::spawn(async move {
-                        // If there aren't enough permits available then this will wait until enough
-                        // become available. This could take a while, so we do this in the background.
-                        let permits = semaphore
-                            .acquire_many(
-                                u32::value_from(old_limit - new_limit)
-                                    .expect("change in limit shouldn't be > u32::MAX"),
-                            )
-                            .await
-                            .expect("we own the semaphore, we shouldn't have closed it");
-
-                        // Acquiring some permits and throwing them away reduces the available limit.
-                        permits.forget();
+    /// Track the highest limit under which jobs succeed, readable using
+    /// [observed_safe_max()](Self::observed_safe_max()).
+    pub fn with_observed_safe_max(mut self) -> Self {
+        self.safe_max = Some(std::sync::Mutex::default());
+        self
+    }
 
-                        #[cfg(test)]
-                        if let Some(n) = notifier {
-                            n.notify_one();
-                        }
-                    });
-                }
-                _ =>
-                {
-                    #[cfg(test)]
-                    if let Some(n) = &self.notifier {
-                        n.notify_one();
-                    }
-                }
-            }
+    /// Track the mean latency of recent jobs, used by [estimated_wait()](Self::estimated_wait()).
+    pub fn with_estimated_wait(mut self) -> Self {
+        self.mean_latency = Some(std::sync::Mutex::new(ExpSmoothed::new_with_window_size(
+            Self::MEAN_LATENCY_WINDOW,
+        )));
+        self
+    }
 
-            new_limit
-        } else {
-            self.limit_algo.limit()
-        };
+    /// Track the rate of successful releases, readable using [goodput()](Self::goodput()).
+    pub fn with_goodput(mut self) -> Self {
+        self.goodput = Some(std::sync::Mutex::new(Goodput::new()));
+        self
+    }
 
-        drop(token);
+    /// Track the latencies of the last 1024 jobs released with each [Outcome], readable using
+    /// [success_latency_percentile()](Self::success_latency_percentile()) and
+    /// [overload_latency_percentile()](Self::overload_latency_percentile()).
+    pub fn with_outcome_latencies(mut self) -> Self {
+        self.outcome_latencies = Some(Mutex::default());
+        self
+    }
 
-        limit
+    /// Track the fraction of recent jobs completing within `threshold`, readable using
+    /// [slo_compliance()](Self::slo_compliance()).
+    ///
+    /// Uses the latencies of the last 1000 jobs released with an [Outcome].
+    pub fn with_slo(mut self, threshold: Duration) -> Self {
+        self.slo = Some(Slo::new(threshold));
+        self
     }
-}
 
-impl LimiterState {
-    /// The current concurrency limit.
-    pub fn limit(&self) -> CapacityUnit {
-        self.limit
+    /// Record the last `capacity` limit algorithm decisions, readable using
+    /// [recent_decisions()](Self::recent_decisions()).
+    ///
+    /// A debugging aid, e.g. for understanding why the limit isn't changing as expected.
+    pub fn with_decision_log(mut self, capacity: usize) -> Self {
+        self.decision_log = Some(DecisionLog::new(capacity));
+        self
     }
-    /// The amount of concurrency available to use.
-    pub fn available(&self) -> CapacityUnit {
-        self.available
+
+    /// Send the [Sample] of every released job to `channel`, e.g. for custom aggregation or tuning
+    /// outside the limiter.
+    ///
+    /// Never waits: samples are dropped while the channel is full, so a slow receiver doesn't slow
+    /// down releases.
+    pub fn with_sample_channel(mut self, channel: mpsc::Sender<Sample>) -> Self {
+        self.sample_channel = Some(channel);
+        self
     }
-    /// The number of jobs in flight.
-    pub fn in_flight(&self) -> CapacityUnit {
-        self.in_flight
+
+    /// The latency to use for samples created by [signal_overload()](Self::signal_overload()) and
+    /// [signal_success()](Self::signal_success()).
+    ///
+    /// Defaults to the algorithm's baseline latency, if it has one, otherwise zero.
+    pub fn with_signal_latency(mut self, latency: Duration) -> Self {
+        self.signal_latency = Some(latency);
+        self
     }
-}
 
-impl Outcome {
-    pub(crate) fn overloaded_or(self, other: Outcome) -> Outcome {
-        use Outcome::*;
-        match (self, other) {
-            (Success, Overload) => Overload,
-            _ => self,
-        }
+    /// Call `on_reject` with the state of the limiter each time an acquisition is rejected.
+    ///
+    /// Useful for logging or sampling rejections. Called before any rejection delay added by a
+    /// wrapper such as [RejectionDelay]. Not called for would-be rejections in
+    /// [shadow mode](Self::shadow()).
+    pub fn with_on_reject(mut self, on_reject: OnRejectFn) -> Self {
+        self.on_reject = Some(OnReject(on_reject));
+        self
     }
-}
 
-#[cfg(test)]
-mod tests {
+    /// In some cases [Token]s are acquired asynchronously when updating the limit.
+    #[cfg(test)]
+    pub fn with_release_notifier(mut self, n: Arc<tokio::sync::Notify>) -> Self {
+        self.notifier.replace(n);
+        self
+    }
+
+    fn new_sample(&self, latency: Duration, outcome: Outcome) -> Sample {
+        Sample {
+            latency,
+            in_flight: self.in_flight(),
+            outcome,
+        }
+    }
+
+    fn available(&self) -> CapacityUnit {
+        let available = self.semaphore.available_permits();
+        if self.hard_limit.is_some() {
+            available.min(self.limit().saturating_sub(self.in_flight()))
+        } else {
+            available
+        }
+    }
+
+    pub(crate) fn limit(&self) -> CapacityUnit {
+        self.limit.load(Ordering::Acquire)
+    }
+
+    fn in_flight(&self) -> CapacityUnit {
+        self.in_flight.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn in_flight_shared(&self) -> Arc<AtomicCapacityUnit> {
+        self.in_flight.clone()
+    }
+
+    /// Whether there is currently no available concurrency, i.e. a
+    /// [try_acquire()](Limiter::try_acquire()) would be rejected.
+    ///
+    /// Doesn't acquire a [Token].
+    pub fn is_saturated(&self) -> bool {
+        self.available() == 0
+    }
+
+    /// Wait until there is likely to be concurrency available.
+    ///
+    /// Doesn't acquire a [Token], so a subsequent [try_acquire()](Limiter::try_acquire()) might
+    /// still be rejected if other callers acquire the available concurrency first.
+    ///
+    /// Returns immediately if the limiter is [closed](Self::close()).
+    ///
+    /// Waiters are woken when a token is [released](Limiter::release()) or the limit increases.
+    pub async fn ready(&self) {
+        self.wait_for_release(|| (self.is_closed() || !self.is_saturated()).then_some(()))
+            .await
+    }
+
+    /// Subscribe to changes to the limit.
+    pub fn subscribe(&self) -> watch::Receiver<CapacityUnit> {
+        self.limit_changes.subscribe()
+    }
+
+    /// How long since the limit last changed, or since the limiter was created if it hasn't.
+    ///
+    /// A limit which changes on almost every release might be unstable, while a limit which hasn't
+    /// changed for a long time has likely converged.
+    pub fn time_since_last_change(&self) -> Duration {
+        self.limit_changed_at
+            .lock()
+            .expect("lock should not be poisoned")
+            .elapsed()
+    }
+
+    /// Subscribe to backpressure: `true` while the limiter is saturated, i.e. no concurrency is
+    /// available, and `false` otherwise.
+    ///
+    /// Updated when tokens are acquired and released, so producers can wait for changes and pause
+    /// while saturated.
+    pub fn backpressure(&self) -> watch::Receiver<bool> {
+        let backpressure = self.saturated.subscribe();
+        self.update_backpressure();
+        backpressure
+    }
+
+    /// Only publishes while there are subscribers, which are brought up to date when they
+    /// [subscribe](Self::backpressure()).
+    fn update_backpressure(&self) {
+        if self.saturated.receiver_count() == 0 {
+            return;
+        }
+        let saturated = self.available() == 0;
+        if *self.saturated.borrow() == saturated {
+            return;
+        }
+        self.saturated.send_if_modified(|current| {
+            let modified = *current != saturated;
+            *current = saturated;
+            modified
+        });
+    }
+
+    /// Wait until the limit is at least `at_least`.
+    ///
+    /// Useful for orchestrating warm-up, or in tests.
+    pub async fn wait_for_limit(&self, at_least: CapacityUnit) {
+        let mut limit = self.subscribe();
+        limit
+            .wait_for(|&limit| limit >= at_least)
+            .await
+            .expect("the limiter holds the sender");
+    }
+
+    /// Pin the limit at its current value.
+    ///
+    /// The limit algorithm continues to receive samples, but changes to the limit won't be applied
+    /// until [unfrozen](Self::unfreeze()). Useful as an operational safety valve, e.g. during an
+    /// incident where the algorithm is reacting to a chaotic signal.
+    pub fn freeze(&self) {
+        self.frozen.store(true, Ordering::Release);
+    }
+
+    /// Resume applying changes to the limit.
+    ///
+    /// The limit algorithm's current limit will be applied on the next update.
+    pub fn unfreeze(&self) {
+        self.frozen.store(false, Ordering::Release);
+    }
+
+    /// Whether the limit is [frozen](Self::freeze()).
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.load(Ordering::Acquire)
+    }
+
+    /// Close the limiter.
+    ///
+    /// All current and future acquisitions will fail, returning a [Closed] error from
+    /// [try_acquire_checked()](Self::try_acquire_checked()) and
+    /// [acquire_timeout_checked()](Self::acquire_timeout_checked()), or `None` from the [Limiter]
+    /// methods. Jobs already in flight can still be released.
+    pub fn close(&self) {
+        self.semaphore.close();
+        self.released.notify_waiters();
+    }
+
+    /// Whether the limiter has been [closed](Self::close()).
+    pub fn is_closed(&self) -> bool {
+        self.semaphore.is_closed()
+    }
+
+    /// The amount of concurrency currently available to use.
+    ///
+    /// Doesn't acquire a [Token].
+    pub fn headroom(&self) -> CapacityUnit {
+        self.available()
+    }
+
+    /// The most recent limit algorithm decisions, oldest first, if enabled using
+    /// [with_decision_log()](Self::with_decision_log()).
+    pub async fn recent_decisions(&self) -> Vec<Decision> {
+        match &self.decision_log {
+            Some(log) => log.recent().await,
+            None => Vec::new(),
+        }
+    }
+
+    /// The fraction of recent jobs which completed within the [SLO](Self::with_slo()), between 0
+    /// and 1.
+    ///
+    /// Returns 1 if no jobs have completed, or if no SLO is configured.
+    pub async fn slo_compliance(&self) -> f64 {
+        match &self.slo {
+            Some(slo) => slo.compliance().await,
+            None => 1.,
+        }
+    }
+
+    /// The given percentile of the time recent callers spent waiting in
+    /// [acquire_timeout()](Limiter::acquire_timeout()), whether or not they acquired a [Token].
+    ///
+    /// This is the queueing delay added by the limiter, separate from job latency. Returns zero if
+    /// nothing has waited yet.
+    pub async fn acquire_wait_percentile(&self, percentile: f64) -> Duration {
+        percentile_of(self.acquire_waits.lock().await.iter(), percentile)
+    }
+
+    /// The given percentile of the latency of recent jobs released with [Outcome::Success].
+    ///
+    /// Returns zero if there have been none, or without
+    /// [with_outcome_latencies()](Self::with_outcome_latencies()).
+    pub async fn success_latency_percentile(&self, percentile: f64) -> Duration {
+        match &self.outcome_latencies {
+            Some(latencies) => percentile_of(latencies.lock().await.success.iter(), percentile),
+            None => Duration::ZERO,
+        }
+    }
+
+    /// The given percentile of the latency of recent jobs released with [Outcome::Overload].
+    ///
+    /// Returns zero if there have been none, or without
+    /// [with_outcome_latencies()](Self::with_outcome_latencies()).
+    pub async fn overload_latency_percentile(&self, percentile: f64) -> Duration {
+        match &self.outcome_latencies {
+            Some(latencies) => percentile_of(latencies.lock().await.overload.iter(), percentile),
+            None => Duration::ZERO,
+        }
+    }
+
+    async fn record_acquire_wait(&self, wait: Duration) {
+        let mut waits = self.acquire_waits.lock().await;
+        if waits.len() == Self::ACQUIRE_WAIT_SAMPLES {
+            waits.pop_front();
+        }
+        waits.push_back(wait);
+    }
+
+    /// The current state of the limiter.
+    pub fn state(&self) -> LimiterState {
+        LimiterState {
+            limit: self.limit(),
+            max_limit: self.limit_algo.max_limit(),
+            available: self.available(),
+            in_flight: self.in_flight(),
+        }
+    }
+
+    /// Immediately acquire all currently available concurrency [Token]s.
+    ///
+    /// Useful for sizing a batch of jobs to the available capacity. Returns an empty `Vec` if there
+    /// are none available. Each token should be released independently.
+    pub fn acquire_available(&self) -> Vec<Token> {
+        let mut tokens = Vec::with_capacity(self.available());
+        while let Ok(Some(token)) = self.try_acquire_now() {
+            tokens.push(token);
+        }
+        tokens
+    }
+
+    fn try_acquire_now(&self) -> Result<Option<Token>, Closed> {
+        if self.over_in_flight_grace() && !self.semaphore.is_closed() {
+            return Ok(None);
+        }
+        if self.hard_limit.is_some()
+            && !self.semaphore.is_closed()
+            && self.in_flight() >= self.limit()
+        {
+            return Ok(None);
+        }
+
+        match Arc::clone(&self.semaphore).try_acquire_owned() {
+            Ok(permit) => Ok(Some(self.mint_token(permit))),
+            Err(TryAcquireError::NoPermits) => Ok(None),
+            Err(TryAcquireError::Closed) => Err(Closed),
+        }
+    }
+
+    /// Whether jobs in flight have reached the [grace](Self::with_in_flight_grace()) ceiling.
+    fn over_in_flight_grace(&self) -> bool {
+        self.in_flight_grace.is_some_and(|grace| {
+            let ceiling = (self.limit() as f64 * grace)
+                .floor()
+                .approx_as::<CapacityUnit>()
+                .unwrap_or(CapacityUnit::MAX);
+            self.in_flight() >= ceiling
+        })
+    }
+
+    /// Wait for a token within the soft limit.
+    async fn acquire_within_soft_limit(&self) -> Result<Token, Closed> {
+        self.wait_for_release(|| self.try_acquire_now().transpose())
+            .await
+    }
+
+    /// Retry `check` each time a token is released, until it returns `Some`.
+    async fn wait_for_release<R>(&self, mut check: impl FnMut() -> Option<R>) -> R {
+        self.release_waiters.fetch_add(1, Ordering::SeqCst);
+        let _waiting = WaiterGuard(&self.release_waiters);
+
+        loop {
+            let mut released = pin!(self.released.notified());
+            released.as_mut().enable();
+
+            if let Some(result) = check() {
+                return result;
+            }
+
+            released.await;
+        }
+    }
+
+    /// Wake any tasks waiting for a release.
+    fn notify_released(&self) {
+        // Pairs with registering a waiter before checking for capacity.
+        atomic::fence(Ordering::SeqCst);
+        if self.release_waiters.load(Ordering::SeqCst) > 0 {
+            self.released.notify_waiters();
+        }
+    }
+
+    pub(crate) fn mint_token(&self, permit: OwnedSemaphorePermit) -> Token {
+        self.acquired.fetch_add(1, Ordering::Relaxed);
+        self.interval_stats.acquired.fetch_add(1, Ordering::Relaxed);
+        let token = Token::new(permit, self.in_flight.clone());
+        self.record_peak_in_flight();
+        self.update_backpressure();
+        token
+    }
+
+    /// The number of permits waiting to be removed because the limit has decreased.
+    ///
+    /// Decreases are applied by waiting for enough jobs to finish, so until then
+    /// [available()](LimiterState::available()) can overstate the available concurrency.
+    pub fn forgetting(&self) -> CapacityUnit {
+        self.forgetting.load(Ordering::SeqCst)
+    }
+
+    /// The amount of concurrency available to use, once pending decreases have been applied. See
+    /// [forgetting()](Self::forgetting()).
+    pub fn effective_available(&self) -> CapacityUnit {
+        self.available().saturating_sub(self.forgetting())
+    }
+
+    /// The number of callers currently waiting in [acquire_timeout()](Limiter::acquire_timeout()).
+    ///
+    /// Consistently high numbers suggest the limit is too low for the demand.
+    pub fn waiting(&self) -> usize {
+        self.acquire_waiters.load(Ordering::SeqCst)
+    }
+
+    /// A rough estimate of how long a call to [acquire_timeout()](Limiter::acquire_timeout())
+    /// would wait right now.
+    ///
+    /// This is a heuristic. It assumes jobs keep finishing at the current rate, i.e. the number in
+    /// flight divided by the mean latency of recent jobs, and that each caller waiting ahead needs
+    /// one job to finish. Zero if capacity is available, if no jobs have finished yet, or without
+    /// [with_estimated_wait()](Self::with_estimated_wait()).
+    pub fn estimated_wait(&self) -> Duration {
+        let Some(mean_latency) = &self.mean_latency else {
+            return Duration::ZERO;
+        };
+        let ahead = (self.acquire_waiters.load(Ordering::SeqCst) + 1)
+            .saturating_sub(self.effective_available());
+        if ahead == 0 {
+            return Duration::ZERO;
+        }
+
+        let mean_latency = mean_latency
+            .lock()
+            .expect("lock should not be poisoned")
+            .value();
+        mean_latency.mul_f64(ahead as f64 / self.in_flight().max(1) as f64)
+    }
+
+    /// Take the counts of tokens acquired, acquisitions rejected and overloaded jobs since the last
+    /// call, resetting them to zero.
+    ///
+    /// Useful for periodic reporting. Each count is reset atomically, so every event is included in
+    /// exactly one interval. The counts are not reset together, so an event happening during the
+    /// call might be counted in a different interval to a related event. Cumulative counts, e.g.
+    /// [rejected()](Self::rejected()), are unaffected.
+    pub fn take_stats(&self) -> LimiterStats {
+        LimiterStats {
+            acquired: self.interval_stats.acquired.swap(0, Ordering::Relaxed),
+            rejected: self.interval_stats.rejected.swap(0, Ordering::Relaxed),
+            overloads: self.interval_stats.overloads.swap(0, Ordering::Relaxed),
+        }
+    }
+
+    /// The number of rejected [labeled](Limiter::try_acquire_labeled()) acquisitions, by label.
+    pub fn rejections_by_label(&self) -> HashMap<&'static str, usize> {
+        self.label_rejections
+            .lock()
+            .expect("lock should not be poisoned")
+            .clone()
+    }
+
+    /// The total number of acquisitions rejected.
+    pub fn rejected(&self) -> usize {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// The rate of successful releases per second, over the last 10 seconds.
+    ///
+    /// Only releases with an [Outcome::Success] are counted, so this measures useful work done,
+    /// e.g. for autoscaling decisions.
+    ///
+    /// Returns zero without [with_goodput()](Self::with_goodput()).
+    pub fn goodput(&self) -> f64 {
+        self.goodput.as_ref().map_or(0., |goodput| {
+            goodput
+                .lock()
+                .expect("lock should not be poisoned")
+                .per_second()
+        })
+    }
+
+    /// The highest limit under which jobs have succeeded while the recent success rate was healthy,
+    /// or `None` if there have been no such jobs.
+    ///
+    /// A data-driven starting point for configuring the algorithm's maximum limit, e.g. on the next
+    /// deploy. Always `None` without [with_observed_safe_max()](Self::with_observed_safe_max()).
+    pub fn observed_safe_max(&self) -> Option<CapacityUnit> {
+        self.safe_max
+            .as_ref()
+            .and_then(|safe_max| safe_max.lock().expect("lock should not be poisoned").max)
+    }
+
+    /// The number of times the algorithm's limit has been clamped to the
+    /// [absolute max](Self::with_absolute_max()).
+    pub fn absolute_max_clamps(&self) -> usize {
+        self.absolute_max_clamps.load(Ordering::Relaxed)
+    }
+
+    /// The most jobs in flight at once since the limiter was created.
+    pub fn peak_in_flight(&self) -> CapacityUnit {
+        self.peak_in_flight.load(Ordering::Relaxed)
+    }
+
+    /// The number of acquisitions which would have been rejected if not in shadow mode.
+    pub fn shadow_rejections(&self) -> usize {
+        self.shadow_rejections.load(Ordering::Acquire)
+    }
+
+    /// In shadow mode or during soft start, issue a token beyond the limit instead of rejecting.
+    fn shadow_rejection(&self) -> Option<Token> {
+        if self.never_reject() {
+            self.shadow_rejections.fetch_add(1, Ordering::SeqCst);
+            self.acquired.fetch_add(1, Ordering::Relaxed);
+            self.interval_stats.acquired.fetch_add(1, Ordering::Relaxed);
+            let token = Token::new_overflow(self.in_flight.clone());
+            self.record_peak_in_flight();
+            Some(token)
+        } else {
+            self.record_rejection();
+            None
+        }
+    }
+
+    fn record_peak_in_flight(&self) {
+        self.peak_in_flight
+            .fetch_max(self.in_flight(), Ordering::Relaxed);
+    }
+
+    fn record_rejection(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+        self.interval_stats.rejected.fetch_add(1, Ordering::Relaxed);
+        if let Some(OnReject(on_reject)) = &self.on_reject {
+            on_reject(self.state());
+        }
+    }
+
+    /// Cap an increase in the limit to the maximum rate of increase, if set.
+    ///
+    /// The allowance is only used up once the increase is [applied](Self::commit_slew()).
+    async fn slew(&self, new_limit: CapacityUnit) -> CapacityUnit {
+        let Some(per_second) = self.max_increase_per_second else {
+            return new_limit;
+        };
+        let limit = self.limit();
+        if new_limit <= limit {
+            return new_limit;
+        }
+
+        let last_increase = self.last_increase.lock().await;
+        let allowed = (last_increase.elapsed().as_secs_f64() * per_second as f64)
+            .floor()
+            .approx_as::<CapacityUnit>()
+            .unwrap_or(CapacityUnit::MAX);
+        if allowed == 0 {
+            return limit;
+        }
+
+        new_limit.min(limit.saturating_add(allowed))
+    }
+
+    /// Use up the allowance for an increase from `old_limit`, once it has been applied.
+    async fn commit_slew(&self, old_limit: CapacityUnit) {
+        if self.max_increase_per_second.is_some() && self.limit() > old_limit {
+            *self.last_increase.lock().await = Instant::now();
+        }
+    }
+
+    /// Whether a change to the limit should be applied now, respecting the minimum change interval.
+    async fn should_apply_change(&self, new_limit: CapacityUnit) -> bool {
+        if self.is_frozen() {
+            return false;
+        }
+        if new_limit == self.limit() {
+            return true;
+        }
+        let Some(interval) = self.min_change_interval else {
+            return true;
+        };
+
+        let mut last_change = self.last_change.lock().await;
+        match *last_change {
+            Some(last) if last.elapsed() < interval => false,
+            _ => {
+                last_change.replace(Instant::now());
+                true
+            }
+        }
+    }
+
+    /// Reconcile the semaphore with a new limit.
+    fn apply_limit(&self, mut new_limit: CapacityUnit) {
+        if let Some(absolute_max) = self.absolute_max.filter(|&max| new_limit > max) {
+            self.absolute_max_clamps.fetch_add(1, Ordering::Relaxed);
+            new_limit = absolute_max;
+        }
+
+        let old_limit = self.limit.swap(new_limit, Ordering::SeqCst);
+        if new_limit != old_limit {
+            *self
+                .limit_changed_at
+                .lock()
+                .expect("lock should not be poisoned") = Instant::now();
+
+            // Publish the latest limit, in case a concurrent change has been applied since.
+            self.limit_changes.send_if_modified(|limit| {
+                let latest = self.limit.load(Ordering::SeqCst);
+                let modified = *limit != latest;
+                *limit = latest;
+                modified
+            });
+        }
+
+        if self.hard_limit.is_some() {
+            // The semaphore is fixed to the hard limit.
+            if new_limit > old_limit {
+                self.released.notify_waiters();
+            }
+
+            #[cfg(test)]
+            if let Some(n) = &self.notifier {
+                n.notify_one();
+            }
+            return;
+        }
+
+        match new_limit.cmp(&old_limit) {
+            cmp::Ordering::Greater => {
+                self.semaphore.add_permits(new_limit - old_limit);
+                self.released.notify_waiters();
+
+                #[cfg(test)]
+                if let Some(n) = &self.notifier {
+                    n.notify_one();
+                }
+            }
+            cmp::Ordering::Less if self.inline_decrease => {
+                // Applied by the caller, once it no longer holds a permit.
+                self.forgetting
+                    .fetch_add(old_limit - new_limit, Ordering::SeqCst);
+                self.pending_decrease
+                    .fetch_add(old_limit - new_limit, Ordering::SeqCst);
+            }
+            cmp::Ordering::Less => {
+                let semaphore = self.semaphore.clone();
+                let forgetting = self.forgetting.clone();
+                forgetting.fetch_add(old_limit - new_limit, Ordering::SeqCst);
+                #[cfg(test)]
+                let notifier = self.notifier.clone();
+
+                tokio::spawn(async move {
+                    // If there aren't enough permits available then this will wait until enough
+                    // become available. This could take a while, so we do this in the background.
+                    let permits = semaphore
+                        .acquire_many(
+                            u32::value_from(old_limit - new_limit)
+                                .expect("change in limit shouldn't be > u32::MAX"),
+                        )
+                        .await;
+
+                    // Acquiring some permits and throwing them away reduces the available limit.
+                    // If the limiter has been closed, there's nothing left to reconcile.
+                    if let Ok(permits) = permits {
+                        permits.forget();
+                    }
+                    forgetting.fetch_sub(old_limit - new_limit, Ordering::SeqCst);
+
+                    #[cfg(test)]
+                    if let Some(n) = notifier {
+                        n.notify_one();
+                    }
+                });
+            }
+            _ =>
+            {
+                #[cfg(test)]
+                if let Some(n) = &self.notifier {
+                    n.notify_one();
+                }
+            }
+        }
+    }
+}
+
+impl<T> DefaultLimiter<T>
+where
+    T: LimitAlgorithm + Sync,
+{
+    /// Report an intermediate outcome for a job which is still in flight, without releasing its
+    /// [Token].
+    ///
+    /// For long-lived jobs such as streaming connections, so they can contribute to the congestion
+    /// signal continuously rather than only at the end. `latency` should be the latency of the
+    /// reported portion of the job, e.g. the time since the last report.
+    ///
+    /// Returns the new limit.
+    pub async fn report(
+        &self,
+        _token: &Token,
+        outcome: Outcome,
+        latency: Duration,
+    ) -> CapacityUnit {
+        self.update_limit(self.new_sample(latency, outcome)).await;
+        self.limit()
+    }
+
+    /// Release a [Token] used for a batch of items, reporting how many succeeded and how many were
+    /// overloaded.
+    ///
+    /// The algorithm is updated with one sample per item, up to 10, each with the token's latency,
+    /// with the overloads spread evenly through the batch. Larger batches are scaled down to 10
+    /// samples, rounding the number of overloads up. This reflects the overload fraction more
+    /// faithfully than collapsing the batch into a single [Outcome].
+    ///
+    /// Returns the new limit.
+    pub async fn release_bulk(
+        &self,
+        token: Token,
+        successes: usize,
+        overloads: usize,
+    ) -> CapacityUnit {
+        // Already released with an overload if its job timed out.
+        if !token.is_expired() {
+            let items = successes + overloads;
+            let total = items.min(Self::MAX_BULK_SAMPLES);
+            let overloads = (overloads * total).div_ceil(items.max(1));
+            for i in 0..total {
+                let outcome = if (i + 1) * overloads / total > i * overloads / total {
+                    Outcome::overload()
+                } else {
+                    Outcome::Success
+                };
+                self.record_outcome(self.new_sample(token.latency(), outcome))
+                    .await;
+            }
+        }
+
+        self.finish_release(token).await
+    }
+
+    /// Update the limit in response to an out-of-band overload signal, e.g. a failed health check,
+    /// without releasing a [Token].
+    ///
+    /// The sample uses the current number of jobs in flight. See
+    /// [with_signal_latency()](Self::with_signal_latency()) for the latency used.
+    ///
+    /// With [inline decreases](Self::with_inline_decrease()), this may wait for jobs to be
+    /// released.
+    ///
+    /// Returns the new limit.
+    pub async fn signal_overload(&self) -> CapacityUnit {
+        self.signal(Outcome::overload()).await
+    }
+
+    /// Like [signal_overload()](Self::signal_overload()), but for an out-of-band signal of
+    /// health.
+    pub async fn signal_success(&self) -> CapacityUnit {
+        self.signal(Outcome::Success).await
+    }
+
+    /// Record a measured latency and outcome as if a job had been released, without acquiring or
+    /// releasing a [Token], e.g. to replay historical data or report a synthetic probe.
+    ///
+    /// The sample uses the current number of jobs in flight. With
+    /// [inline decreases](Self::with_inline_decrease()), this may wait for jobs to be released.
+    ///
+    /// Returns the new limit.
+    pub async fn observe(&self, latency: Duration, outcome: Outcome) -> CapacityUnit {
+        self.record_outcome(self.new_sample(latency, outcome)).await;
+        self.apply_pending_decrease().await;
+
+        self.limit()
+    }
+
+    async fn signal(&self, outcome: Outcome) -> CapacityUnit {
+        let latency = match self.signal_latency {
+            Some(latency) => latency,
+            None => self.algorithm_baseline().await.unwrap_or_default(),
+        };
+        self.update_limit(self.new_sample(latency, outcome)).await;
+        self.apply_pending_decrease().await;
+
+        self.limit()
+    }
+
+    async fn record_outcome(&self, sample: Sample) {
+        if let Some(channel) = &self.sample_channel {
+            let _ = channel.try_send(sample.clone());
+        }
+        let nanos = u64::try_from(sample.latency.as_nanos()).unwrap_or(u64::MAX - 1);
+        self.recent_latency.store(nanos, Ordering::Relaxed);
+        if sample.outcome.is_overload() {
+            self.interval_stats
+                .overloads
+                .fetch_add(1, Ordering::Relaxed);
+        } else if let Some(goodput) = &self.goodput {
+            goodput
+                .lock()
+                .expect("lock should not be poisoned")
+                .record();
+        }
+        if let Some(mean_latency) = &self.mean_latency {
+            mean_latency
+                .lock()
+                .expect("lock should not be poisoned")
+                .sample(sample.latency);
+        }
+        if let Some(slo) = &self.slo {
+            slo.record(sample.latency).await;
+        }
+
+        if let Some(safe_max) = &self.safe_max {
+            safe_max
+                .lock()
+                .expect("lock should not be poisoned")
+                .record(sample.outcome, self.limit());
+        }
+
+        if let Some(latencies) = &self.outcome_latencies {
+            latencies
+                .lock()
+                .await
+                .record(&sample, Self::OUTCOME_LATENCY_SAMPLES);
+        }
+
+        let sample = match self.latency_source {
+            LatencySource::All => sample,
+            LatencySource::SuccessOnly if sample.outcome.is_overload() => {
+                match self.recent_success_latency.load(Ordering::Relaxed) {
+                    u64::MAX => sample,
+                    nanos => Sample {
+                        latency: Duration::from_nanos(nanos),
+                        ..sample
+                    },
+                }
+            }
+            LatencySource::SuccessOnly => {
+                self.recent_success_latency.store(nanos, Ordering::Relaxed);
+                sample
+            }
+        };
+
+        self.update_limit(sample).await;
+    }
+
+    async fn finish_release(&self, token: Token) -> CapacityUnit {
+        drop(token);
+        self.notify_released();
+
+        self.apply_pending_decrease().await;
+        self.update_backpressure();
+
+        self.limit()
+    }
+
+    /// The latency the limit algorithm considers to be the baseline, without congestion, if it has
+    /// learned one. See [LimitAlgorithm::baseline_latency()].
+    pub async fn algorithm_baseline(&self) -> Option<Duration> {
+        self.limit_algo.baseline_latency().await
+    }
+
+    /// A snapshot of the limiter in one call. See [LimiterReport].
+    ///
+    /// Not guaranteed to be consistent under high concurrency.
+    pub async fn limiter_report(&self) -> LimiterReport {
+        let state = self.state();
+        LimiterReport {
+            limit: state.limit(),
+            in_flight: state.in_flight(),
+            available: state.available(),
+            peak_in_flight: self.peak_in_flight(),
+            rejected: self.rejected(),
+            baseline_latency: self.algorithm_baseline().await,
+        }
+    }
+
+    /// Forget permits for any pending inline limit decreases. See
+    /// [with_inline_decrease()](Self::with_inline_decrease()).
+    ///
+    /// Must not be called while holding a permit, as it may wait for permits to be released.
+    async fn apply_pending_decrease(&self) {
+        let pending = self.pending_decrease.swap(0, Ordering::SeqCst);
+        if pending == 0 {
+            return;
+        }
+
+        let permits = self
+            .semaphore
+            .acquire_many(
+                u32::value_from(pending).expect("change in limit shouldn't be > u32::MAX"),
+            )
+            .await;
+
+        // If the limiter has been closed, there's nothing left to reconcile.
+        if let Ok(permits) = permits {
+            permits.forget();
+        }
+        self.forgetting.fetch_sub(pending, Ordering::SeqCst);
+
+        #[cfg(test)]
+        if let Some(n) = &self.notifier {
+            n.notify_one();
+        }
+    }
+
+    /// Update the limit algorithm with a sample, and apply the new limit if appropriate.
+    async fn update_limit(&self, sample: Sample) {
+        let old_limit = self.limit();
+        let logged_sample = self.decision_log.as_ref().map(|_| sample.clone());
+
+        let new_limit = match self.limit_algo.try_update_sync(sample) {
+            Ok(limit) => limit,
+            Err(sample) => self.limit_algo.update(sample).await,
+        };
+
+        let slewed_limit = self.slew(new_limit).await;
+        let applied = self.should_apply_change(slewed_limit).await;
+        if applied {
+            self.apply_limit(slewed_limit);
+            self.commit_slew(old_limit).await;
+        }
+
+        if let (Some(log), Some(sample)) = (&self.decision_log, logged_sample) {
+            log.record(sample, old_limit, slewed_limit, applied).await;
+        }
+    }
+
+    /// Like [try_acquire()](Limiter::try_acquire()), but returns an error if the limiter has been
+    /// [closed](Self::close()).
+    pub async fn try_acquire_checked(&self) -> Result<Option<Token>, Closed> {
+        Ok(self.try_acquire_now()?.or_else(|| self.shadow_rejection()))
+    }
+
+    /// Like [try_acquire()](Limiter::try_acquire()), but rejects immediately if the limit is below
+    /// `min_limit`.
+    ///
+    /// A low limit suggests that congestion has been detected. Non-critical jobs can use this to
+    /// back off voluntarily, reserving capacity for critical jobs.
+    pub async fn try_acquire_if_healthy(&self, min_limit: CapacityUnit) -> Option<Token> {
+        if self.limit() < min_limit {
+            return self.shadow_rejection();
+        }
+        self.try_acquire_checked().await.ok().flatten()
+    }
+
+    /// Like [acquire_timeout()](Limiter::acquire_timeout()), but returns an error if the limiter
+    /// has been [closed](Self::close()), including while waiting.
+    pub async fn acquire_timeout_checked(
+        &self,
+        duration: Duration,
+    ) -> Result<Option<Token>, Closed> {
+        if self.never_reject() {
+            return self.try_acquire_checked().await;
+        }
+
+        self.acquire_waiters.fetch_add(1, Ordering::SeqCst);
+        let _waiting = WaiterGuard(&self.acquire_waiters);
+
+        let start = Instant::now();
+        let acquired = if self.hard_limit.is_some() {
+            timeout(duration, self.acquire_within_soft_limit()).await
+        } else {
+            timeout(duration, async {
+                loop {
+                    let permit = Arc::clone(&self.semaphore)
+                        .acquire_owned()
+                        .await
+                        .map_err(|_| Closed)?;
+                    if !self.over_in_flight_grace() {
+                        return Ok(self.mint_token(permit));
+                    }
+                    // Wait for a job to finish.
+                    drop(permit);
+                    self.wait_for_release(|| (!self.over_in_flight_grace()).then_some(()))
+                        .await;
+                }
+            })
+            .await
+        };
+
+        if !matches!(acquired, Ok(Err(Closed))) {
+            self.record_acquire_wait(start.elapsed()).await;
+        }
+
+        match acquired {
+            Ok(token) => token.map(Some),
+            Err(_) => {
+                self.record_rejection();
+                self.acquire_timed_out(duration).await;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Apply the [TimeoutPolicy] to an acquire timeout.
+    async fn acquire_timed_out(&self, duration: Duration) {
+        match self.timeout_policy {
+            TimeoutPolicy::Ignore => {}
+            TimeoutPolicy::SelfShed => self.self_shed(duration).await,
+            TimeoutPolicy::Grow => {
+                self.signal(Outcome::Success).await;
+            }
+        }
+    }
+
+    /// Notify the limit algorithm that a job timed out after waiting `duration` to acquire, if the
+    /// latency of recent jobs suggests that this limiter is the bottleneck.
+    pub(crate) async fn self_shed(&self, duration: Duration) {
+        let recent_latency = match self.recent_latency.load(Ordering::Relaxed) {
+            u64::MAX => return,
+            nanos => Duration::from_nanos(nanos),
+        };
+        if recent_latency >= duration {
+            // Jobs are slow to complete. The bottleneck is likely downstream.
+            return;
+        }
+
+        let sample = self.new_sample(recent_latency, Outcome::Success);
+
+        let old_limit = self.limit();
+        let new_limit = self
+            .slew(self.limit_algo.on_acquire_timeout(sample).await)
+            .await;
+
+        if self.should_apply_change(new_limit).await {
+            self.apply_limit(new_limit);
+            self.commit_slew(old_limit).await;
+            self.apply_pending_decrease().await;
+        }
+    }
+}
+
+impl<T> DefaultLimiter<T>
+where
+    T: LimitAlgorithm + Send + Sync + 'static,
+{
+    /// Like [try_acquire()](Limiter::try_acquire()), but if the token isn't released within
+    /// `job_timeout`, it is released automatically with an [overload](Outcome::overload()),
+    /// treating the stuck job as congestion.
+    ///
+    /// Guards against permits leaking from hung jobs. Releasing the token after it has timed out
+    /// only drops it, without updating the limit again.
+    pub async fn acquire_with_job_timeout(
+        self: &Arc<Self>,
+        job_timeout: Duration,
+    ) -> Option<Token> {
+        let mut token = self.try_acquire_checked().await.ok().flatten()?;
+
+        let permit = token.share_permit();
+        let limiter = Arc::clone(self);
+        let reaper = tokio::spawn(async move {
+            tokio::time::sleep(job_timeout).await;
+            if let Some(token) = permit.take() {
+                limiter
+                    .record_outcome(limiter.new_sample(token.latency(), Outcome::overload()))
+                    .await;
+                limiter.finish_release(token).await;
+            }
+        });
+        token.set_reaper(reaper.abort_handle());
+
+        Some(token)
+    }
+}
+
+#[async_trait]
+impl<T> Limiter for DefaultLimiter<T>
+where
+    T: LimitAlgorithm + Sync + Debug,
+{
+    /// Returns `None` if the limiter is [closed](DefaultLimiter::close()). See
+    /// [try_acquire_checked()](DefaultLimiter::try_acquire_checked()).
+    async fn try_acquire(&self) -> Option<Token> {
+        self.try_acquire_checked().await.ok().flatten()
+    }
+
+    /// Rejections are counted by label. See [rejections_by_label()](Self::rejections_by_label()).
+    async fn try_acquire_labeled(&self, label: &'static str) -> Option<Token> {
+        let token = self.try_acquire().await;
+        if token.is_none() {
+            *self
+                .label_rejections
+                .lock()
+                .expect("lock should not be poisoned")
+                .entry(label)
+                .or_default() += 1;
+        }
+        token
+    }
+
+    /// In shadow mode, this won't wait if there is no available concurrency.
+    ///
+    /// Returns `None` if the limiter is [closed](DefaultLimiter::close()). See
+    /// [acquire_timeout_checked()](DefaultLimiter::acquire_timeout_checked()).
+    async fn acquire_timeout(&self, duration: Duration) -> Option<Token> {
+        self.acquire_timeout_checked(duration).await.ok().flatten()
+    }
+
+    async fn release(&self, token: Token, outcome: Option<Outcome>) -> CapacityUnit {
+        // Already released with an overload if its job timed out.
+        let outcome = outcome.filter(|_| !token.is_expired());
+        if let Some(outcome) = outcome {
+            self.record_outcome(self.new_sample(token.latency(), outcome))
+                .await;
+        }
+
+        self.finish_release(token).await
+    }
+
+    fn limiter_state(&self) -> Option<LimiterState> {
+        Some(self.state())
+    }
+}
+
+impl LimiterStats {
+    /// The number of tokens acquired.
+    pub fn acquired(&self) -> usize {
+        self.acquired
+    }
+    /// The number of acquisitions rejected.
+    pub fn rejected(&self) -> usize {
+        self.rejected
+    }
+    /// The number of jobs released with an [Outcome::Overload].
+    pub fn overloads(&self) -> usize {
+        self.overloads
+    }
+}
+
+impl LimiterState {
+    /// The current concurrency limit.
+    pub fn limit(&self) -> CapacityUnit {
+        self.limit
+    }
+    /// The maximum concurrency limit the algorithm will set.
+    pub fn max_limit(&self) -> CapacityUnit {
+        self.max_limit
+    }
+    /// The amount of concurrency available to use.
+    pub fn available(&self) -> CapacityUnit {
+        self.available
+    }
+    /// The number of jobs in flight.
+    pub fn in_flight(&self) -> CapacityUnit {
+        self.in_flight
+    }
+}
+
+impl Outcome {
+    /// An [Outcome::Overload] of an unspecified kind.
+    pub fn overload() -> Self {
+        Outcome::Overload(OverloadKind::Unspecified)
+    }
+
+    /// Whether this is an [Outcome::Overload] of any kind.
+    pub fn is_overload(&self) -> bool {
+        matches!(self, Outcome::Overload(_))
+    }
+
+    pub(crate) fn overloaded_or(self, other: Outcome) -> Outcome {
+        use Outcome::*;
+        match (self, other) {
+            (Success, Overload(kind)) => Overload(kind),
+            _ => self,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+    use tokio::{
+        sync::{Mutex, Notify},
+        time,
+    };
+
     use crate::{
-        limiter::{DefaultLimiter, Limiter, Outcome},
-        limits::Fixed,
+        limiter::{
+            Closed, DefaultLimiter, LatencySource, Limiter, Outcome, OverloadKind, TimeoutPolicy,
+            Token,
+        },
+        limits::{Aimd, Fixed, LimitAlgorithm, Sample, Vegas},
     };
 
     #[tokio::test]
-    async fn it_works() {
+    async fn it_works() {
+        let limiter = DefaultLimiter::new(Fixed::new(10));
+
+        let token = limiter.try_acquire().await.unwrap();
+
+        limiter.release(token, Some(Outcome::Success)).await;
+
+        assert_eq!(limiter.limit(), 10);
+    }
+
+    #[tokio::test]
+    async fn min_change_interval() {
+        time::pause();
+
+        let release_notifier = Arc::new(Notify::new());
+        let limiter = DefaultLimiter::new(
+            Aimd::new_with_initial_limit(10)
+                .decrease_factor(0.5)
+                .with_min_utilisation_threshold(0.01),
+        )
+        .with_min_change_interval(Duration::from_secs(1))
+        .with_release_notifier(release_notifier.clone());
+
+        let token = limiter.try_acquire().await.unwrap();
+        limiter.release(token, Some(Outcome::overload())).await;
+        release_notifier.notified().await;
+        assert_eq!(limiter.limit(), 5, "first change is applied");
+
+        for outcome in [Outcome::Success, Outcome::overload(), Outcome::Success] {
+            let token = limiter.try_acquire().await.unwrap();
+            limiter.release(token, Some(outcome)).await;
+        }
+        assert_eq!(limiter.limit_algo.limit(), 4);
+        assert_eq!(
+            limiter.limit(),
+            5,
+            "changes within the interval are suppressed"
+        );
+        assert_eq!(limiter.state().available(), 5);
+
+        time::advance(Duration::from_secs(1)).await;
+
+        let token = limiter.try_acquire().await.unwrap();
+        limiter.release(token, Some(Outcome::overload())).await;
+        release_notifier.notified().await;
+        assert_eq!(
+            limiter.limit(),
+            2,
+            "latest limit is applied after the interval"
+        );
+        assert_eq!(limiter.state().available(), 2);
+    }
+
+    #[tokio::test]
+    async fn acquire_available() {
+        let limiter = DefaultLimiter::new(Fixed::new(10));
+
+        let _held = [
+            limiter.try_acquire().await.unwrap(),
+            limiter.try_acquire().await.unwrap(),
+            limiter.try_acquire().await.unwrap(),
+            limiter.try_acquire().await.unwrap(),
+            limiter.try_acquire().await.unwrap(),
+        ];
+
+        let tokens = limiter.acquire_available();
+        assert_eq!(tokens.len(), 5);
+        assert_eq!(limiter.state().available(), 0);
+        assert_eq!(limiter.state().in_flight(), 10);
+
+        assert!(limiter.acquire_available().is_empty());
+
+        for token in tokens {
+            limiter.release(token, Some(Outcome::Success)).await;
+        }
+        assert_eq!(limiter.state().available(), 5);
+    }
+
+    #[tokio::test]
+    async fn is_saturated() {
+        let limiter = DefaultLimiter::new(Fixed::new(2));
+
+        let _token = limiter.try_acquire().await.unwrap();
+        assert!(!limiter.is_saturated());
+        assert_eq!(limiter.headroom(), 1);
+
+        let token = limiter.try_acquire().await.unwrap();
+        assert!(limiter.is_saturated());
+        assert_eq!(limiter.headroom(), 0);
+
+        limiter.release(token, Some(Outcome::Success)).await;
+        assert!(!limiter.is_saturated());
+        assert_eq!(limiter.headroom(), 1);
+    }
+
+    #[tokio::test]
+    async fn shadow_mode() {
+        let limiter = DefaultLimiter::new(Fixed::new(1)).shadow(true);
+
+        let token = limiter.try_acquire().await.unwrap();
+        assert_eq!(limiter.shadow_rejections(), 0);
+
+        let overflow = limiter.try_acquire().await;
+        assert!(overflow.is_some(), "never rejects in shadow mode");
+        let overflow_timeout = limiter.acquire_timeout(Duration::from_secs(1)).await;
+        assert!(overflow_timeout.is_some(), "never rejects in shadow mode");
+        assert_eq!(limiter.shadow_rejections(), 2);
+        assert_eq!(limiter.state().in_flight(), 3);
+
+        for token in [token, overflow.unwrap(), overflow_timeout.unwrap()] {
+            limiter.release(token, Some(Outcome::Success)).await;
+        }
+        assert_eq!(limiter.state().in_flight(), 0);
+        assert_eq!(limiter.state().available(), 1);
+    }
+
+    #[tokio::test]
+    async fn acquire_timeout_feedback() {
+        time::pause();
+
+        let limiter = DefaultLimiter::new(Aimd::new_with_initial_limit(2).increase_by(1))
+            .with_acquire_timeout_feedback(true);
+
+        let mut token = limiter.try_acquire().await.unwrap();
+        token.set_latency(Duration::from_millis(10));
+        limiter.release(token, Some(Outcome::Success)).await;
+        assert_eq!(limiter.limit(), 2);
+
+        let mut tokens = limiter.acquire_available();
+        for expected_limit in 3..=5 {
+            assert!(limiter
+                .acquire_timeout(Duration::from_millis(100))
+                .await
+                .is_none());
+            assert_eq!(
+                limiter.limit(),
+                expected_limit,
+                "low latency + acquire timeout: increase limit"
+            );
+            tokens.extend(limiter.acquire_available());
+        }
+    }
+
+    #[tokio::test]
+    async fn acquire_timeout_feedback_with_high_latency() {
+        time::pause();
+
+        let limiter = DefaultLimiter::new(Aimd::new_with_initial_limit(2).increase_by(1))
+            .with_acquire_timeout_feedback(true);
+
+        let mut token = limiter.try_acquire().await.unwrap();
+        token.set_latency(Duration::from_secs(1));
+        limiter.release(token, Some(Outcome::Success)).await;
+
+        let _tokens = limiter.acquire_available();
+        assert!(limiter
+            .acquire_timeout(Duration::from_millis(100))
+            .await
+            .is_none());
+        assert_eq!(limiter.limit(), 2, "high latency + acquire timeout: ignore");
+    }
+
+    #[tokio::test]
+    async fn timeout_policy() {
+        time::pause();
+
+        async fn limit_after_timeout(policy: TimeoutPolicy, recent_latency: Duration) -> usize {
+            let limiter = DefaultLimiter::new(Aimd::new_with_initial_limit(2).increase_by(1))
+                .with_timeout_policy(policy);
+
+            let mut token = limiter.try_acquire().await.unwrap();
+            token.set_latency(recent_latency);
+            limiter.release(token, Some(Outcome::Success)).await;
+
+            let _tokens = limiter.acquire_available();
+            assert!(limiter
+                .acquire_timeout(Duration::from_millis(100))
+                .await
+                .is_none());
+            limiter.limit()
+        }
+
+        let (fast, slow) = (Duration::from_millis(10), Duration::from_secs(1));
+        for (policy, fast_limit, slow_limit) in [
+            (TimeoutPolicy::Ignore, 2, 2),
+            (TimeoutPolicy::SelfShed, 3, 2),
+            (TimeoutPolicy::Grow, 3, 3),
+        ] {
+            assert_eq!(
+                limit_after_timeout(policy, fast).await,
+                fast_limit,
+                "{policy:?}"
+            );
+            assert_eq!(
+                limit_after_timeout(policy, slow).await,
+                slow_limit,
+                "{policy:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn soft_limit() {
+        let release_notifier = Arc::new(Notify::new());
+        let limiter = DefaultLimiter::new(Aimd::new_with_initial_limit(4).decrease_factor(0.5))
+            .with_hard_limit(8)
+            .with_release_notifier(release_notifier.clone());
+
+        let mut tokens = limiter.acquire_available();
+        assert_eq!(tokens.len(), 4, "limited by the soft limit");
+        assert!(limiter.try_acquire().await.is_none());
+
+        limiter
+            .release(tokens.pop().unwrap(), Some(Outcome::overload()))
+            .await;
+        release_notifier.notified().await;
+        assert_eq!(limiter.limit(), 2);
+        assert_eq!(limiter.state().in_flight(), 3, "existing jobs continue");
+        assert!(
+            limiter.try_acquire().await.is_none(),
+            "above the soft limit: reject"
+        );
+
+        limiter.release(tokens.pop().unwrap(), None).await;
+        limiter.release(tokens.pop().unwrap(), None).await;
+        assert_eq!(limiter.state().in_flight(), 1);
+
+        let _token = limiter.try_acquire().await.unwrap();
+        assert!(limiter.is_saturated());
+    }
+
+    #[tokio::test]
+    async fn soft_limit_acquire_timeout() {
+        time::pause();
+
+        let limiter = Arc::new(DefaultLimiter::new(Fixed::new(1)).with_hard_limit(2));
+
+        let token = limiter.try_acquire().await.unwrap();
+        assert!(limiter
+            .acquire_timeout(Duration::from_millis(100))
+            .await
+            .is_none());
+
+        let waiter = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire_timeout(Duration::from_secs(1)).await }
+        });
+        tokio::task::yield_now().await;
+
+        limiter.release(token, Some(Outcome::Success)).await;
+        assert!(waiter.await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn overload_kind_reaches_algorithm() {
+        #[derive(Debug, Default)]
+        struct Recording(Mutex<Vec<Outcome>>);
+
+        #[async_trait]
+        impl LimitAlgorithm for Recording {
+            fn limit(&self) -> usize {
+                1
+            }
+
+            async fn update(&self, sample: Sample) -> usize {
+                self.0.lock().await.push(sample.outcome());
+                1
+            }
+        }
+
+        let limiter = DefaultLimiter::new(Recording::default());
+
+        let token = limiter.try_acquire().await.unwrap();
+        let outcome = Outcome::Overload(OverloadKind::StatusCode(503));
+        limiter.release(token, Some(outcome)).await;
+
+        assert_eq!(*limiter.limit_algo.0.lock().await, vec![outcome]);
+    }
+
+    #[tokio::test]
+    async fn ready() {
+        let limiter = Arc::new(DefaultLimiter::new(Fixed::new(1)));
+
+        let token = limiter.try_acquire().await.unwrap();
+
+        let ready = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.ready().await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!ready.is_finished(), "saturated");
+
+        limiter.release(token, Some(Outcome::Success)).await;
+        ready.await.unwrap();
+
+        assert_eq!(limiter.state().in_flight(), 0, "ready() doesn't acquire");
+        assert!(limiter.try_acquire().await.is_some());
+    }
+
+    #[tokio::test]
+    async fn decision_log() {
+        let limiter = DefaultLimiter::new(Aimd::new_with_initial_limit(10).decrease_factor(0.5))
+            .with_decision_log(2);
+
+        for outcome in [Outcome::overload(), Outcome::Success, Outcome::overload()] {
+            let token = limiter.try_acquire().await.unwrap();
+            limiter.release(token, Some(outcome)).await;
+        }
+
+        let transitions: Vec<_> = limiter
+            .recent_decisions()
+            .await
+            .iter()
+            .map(|d| (d.sample().outcome(), d.old_limit(), d.new_limit()))
+            .collect();
+        assert_eq!(
+            transitions,
+            vec![(Outcome::Success, 5, 5), (Outcome::overload(), 5, 2)],
+            "oldest decision dropped"
+        );
+    }
+
+    #[tokio::test]
+    async fn closed() {
+        let limiter = Arc::new(DefaultLimiter::new(Fixed::new(1)));
+
+        let token = limiter.try_acquire().await.unwrap();
+
+        let waiter = tokio::spawn({
+            let limiter = limiter.clone();
+            async move {
+                limiter
+                    .acquire_timeout_checked(Duration::from_secs(1))
+                    .await
+            }
+        });
+        tokio::task::yield_now().await;
+
+        limiter.close();
+        assert!(limiter.is_closed());
+
+        assert_eq!(waiter.await.unwrap().unwrap_err(), Closed, "waiter woken");
+        assert_eq!(limiter.try_acquire_checked().await.unwrap_err(), Closed);
+        assert!(limiter.try_acquire().await.is_none());
+
+        limiter.release(token, Some(Outcome::Success)).await;
+        assert_eq!(limiter.try_acquire_checked().await.unwrap_err(), Closed);
+    }
+
+    #[tokio::test]
+    async fn acquire_wait_percentile() {
+        time::pause();
+
+        let limiter = Arc::new(DefaultLimiter::new(Fixed::new(1)));
+        assert_eq!(limiter.acquire_wait_percentile(0.9).await, Duration::ZERO);
+
+        let token = limiter.try_acquire().await.unwrap();
+
+        let waiter = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire_timeout(Duration::from_secs(1)).await }
+        });
+        tokio::task::yield_now().await;
+
+        time::advance(Duration::from_millis(100)).await;
+        limiter.release(token, Some(Outcome::Success)).await;
+        assert!(waiter.await.unwrap().is_some());
+
+        let wait = limiter.acquire_wait_percentile(0.9).await;
+        assert!(
+            wait >= Duration::from_millis(100),
+            "waited for release. Wait: {wait:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn in_flight_grace() {
+        let limiter = DefaultLimiter::new(Fixed::new(10)).with_in_flight_grace(1.2);
+
+        // Jobs in flight which don't hold the limiter's permits.
+        let external = Arc::new(tokio::sync::Semaphore::new(10));
+        let mut tokens = vec![];
+        for _ in 0..10 {
+            let permit = external.clone().acquire_owned().await.unwrap();
+            tokens.push(Token::from_permit(permit, &limiter));
+        }
+        assert_eq!(limiter.state().available(), 10);
+
+        tokens.push(limiter.try_acquire().await.unwrap());
+        tokens.push(limiter.try_acquire().await.unwrap());
+        assert!(
+            limiter.try_acquire().await.is_none(),
+            "12 in flight, limit 10 * 1.2"
+        );
+        assert!(limiter
+            .acquire_timeout(Duration::from_millis(10))
+            .await
+            .is_none());
+
+        limiter
+            .release(tokens.pop().unwrap(), Some(Outcome::Success))
+            .await;
+        assert!(limiter.try_acquire().await.is_some());
+    }
+
+    #[tokio::test]
+    async fn freeze() {
+        let limiter = DefaultLimiter::new(Aimd::new_with_initial_limit(10));
+
+        limiter.freeze();
+        for _ in 0..3 {
+            let token = limiter.try_acquire().await.unwrap();
+            limiter.release(token, Some(Outcome::overload())).await;
+        }
+        assert_eq!(limiter.limit(), 10, "frozen");
+
+        limiter.unfreeze();
+        let token = limiter.try_acquire().await.unwrap();
+        limiter.release(token, Some(Outcome::Success)).await;
+        assert_eq!(limiter.limit(), 7, "resumes from the algorithm's state");
+    }
+
+    #[tokio::test]
+    async fn slo_compliance() {
+        let limiter = DefaultLimiter::new(Fixed::new(10)).with_slo(Duration::from_millis(100));
+        assert_eq!(limiter.slo_compliance().await, 1.);
+
+        for latency in [10, 50, 100, 150, 500] {
+            let mut token = limiter.try_acquire().await.unwrap();
+            token.set_latency(Duration::from_millis(latency));
+            limiter.release(token, Some(Outcome::Success)).await;
+        }
+
+        assert_eq!(limiter.slo_compliance().await, 0.6);
+    }
+
+    #[tokio::test]
+    async fn wait_for_limit() {
+        let limiter = Arc::new(DefaultLimiter::new(Aimd::new_with_initial_limit(2)));
+
+        let waiter = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.wait_for_limit(4).await }
+        });
+
+        for _ in 0..2 {
+            let tokens = limiter.acquire_available();
+            for token in tokens {
+                limiter.release(token, Some(Outcome::Success)).await;
+            }
+        }
+
+        waiter.await.unwrap();
+        assert!(limiter.limit() >= 4);
+    }
+
+    #[tokio::test]
+    async fn report_mid_flight() {
+        let limiter = DefaultLimiter::new(Aimd::new_with_initial_limit(10));
+
+        let token = limiter.try_acquire().await.unwrap();
+        for _ in 0..3 {
+            token
+                .report(&limiter, Outcome::overload(), Duration::from_millis(10))
+                .await;
+        }
+
+        assert_eq!(limiter.limit(), 7, "reacts before release");
+        assert_eq!(limiter.state().in_flight(), 1, "token still held");
+
+        limiter.release(token, None).await;
+        assert_eq!(limiter.state().in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn initial_limit_jitter() {
+        use rand::{rngs::SmallRng, SeedableRng};
+
+        let limits: Vec<_> = (0..20)
+            .map(|seed| {
+                DefaultLimiter::new(Aimd::new_with_initial_limit(100))
+                    .with_rng(SmallRng::seed_from_u64(seed))
+                    .with_initial_limit_jitter(0.2)
+                    .state()
+                    .available()
+            })
+            .collect();
+
+        assert!(
+            limits.iter().all(|limit| (80..=120).contains(limit)),
+            "within the band: {limits:?}"
+        );
+        assert!(
+            limits.iter().any(|limit| *limit != limits[0]),
+            "varied: {limits:?}"
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn inline_decrease() {
+        let limiter = DefaultLimiter::new(Aimd::new_with_initial_limit(10).decrease_factor(0.5))
+            .with_inline_decrease(true);
+
+        let mut tokens = Vec::new();
+        for _ in 0..4 {
+            tokens.push(limiter.try_acquire().await.unwrap());
+        }
+
+        let token = tokens.pop().unwrap();
+        limiter.release(token, Some(Outcome::overload())).await;
+
+        // No yielding to a spawned task: the decrease has already been applied.
+        assert_eq!(limiter.limit(), 5);
+        assert_eq!(limiter.state().available(), 2);
+
+        for token in tokens.drain(..) {
+            limiter.release(token, None).await;
+        }
+        assert_eq!(limiter.state().available(), 5);
+    }
+
+    #[tokio::test]
+    async fn release_bulk() {
+        async fn limit_after_batch(successes: usize, overloads: usize) -> usize {
+            let limiter = DefaultLimiter::new(Aimd::new_with_initial_limit(100));
+            let token = limiter.try_acquire().await.unwrap();
+            limiter.release_bulk(token, successes, overloads).await
+        }
+
+        let low = limit_after_batch(95, 5).await;
+        let high = limit_after_batch(50, 50).await;
+
+        assert!(low < 100, "some overload: decrease limit");
+        assert!(
+            high < low,
+            "more overload, bigger decrease. Low: {low}, high: {high}"
+        );
+    }
+
+    #[tokio::test]
+    async fn release_bulk_bounds_samples() {
         let limiter = DefaultLimiter::new(Fixed::new(10));
 
         let token = limiter.try_acquire().await.unwrap();
+        limiter.release_bulk(token, 500, 500).await;
+        assert_eq!(limiter.take_stats().overloads(), 5, "scaled to 10 samples");
+
+        let token = limiter.try_acquire().await.unwrap();
+        limiter.release_bulk(token, 999, 1).await;
+        assert_eq!(limiter.take_stats().overloads(), 1, "overloads rounded up");
+    }
+
+    #[tokio::test]
+    async fn release_bulk_after_job_timeout() {
+        time::pause();
+
+        let limiter = Arc::new(DefaultLimiter::new(Fixed::new(10)));
+        let token = limiter
+            .acquire_with_job_timeout(Duration::from_secs(1))
+            .await
+            .unwrap();
+
+        time::sleep(Duration::from_secs(1)).await;
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(limiter.take_stats().overloads(), 1, "overload recorded");
+
+        limiter.release_bulk(token, 0, 5).await;
+        assert_eq!(limiter.take_stats().overloads(), 0, "late release ignored");
+    }
+
+    #[tokio::test]
+    async fn try_acquire_if_healthy() {
+        let limiter = DefaultLimiter::new(Aimd::new_with_initial_limit(10));
+
+        let token = limiter.try_acquire_if_healthy(8).await;
+        assert!(token.is_some(), "healthy: acquire");
+        limiter.release(token.unwrap(), None).await;
+
+        while limiter.limit() >= 8 {
+            let token = limiter.try_acquire().await.unwrap();
+            limiter.release(token, Some(Outcome::overload())).await;
+        }
+
+        assert!(
+            limiter.try_acquire_if_healthy(8).await.is_none(),
+            "limit below threshold: reject"
+        );
+        assert!(
+            limiter.try_acquire().await.is_some(),
+            "capacity still available for critical jobs"
+        );
+    }
+
+    #[tokio::test]
+    async fn signal_overload() {
+        let limiter = DefaultLimiter::new(Aimd::new_with_initial_limit(10));
+
+        for _ in 0..3 {
+            limiter.signal_overload().await;
+        }
+
+        assert!(limiter.limit() < 10, "decreased without any tokens");
+        assert_eq!(limiter.state().in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn absolute_max() {
+        let limiter = DefaultLimiter::new(Aimd::new_with_initial_limit(10)).with_absolute_max(4);
+        assert_eq!(limiter.limit(), 4, "initial limit clamped");
+
+        for _ in 0..10 {
+            let mut tokens = Vec::new();
+            while let Some(token) = limiter.try_acquire().await {
+                tokens.push(token);
+            }
+            assert_eq!(tokens.len(), 4, "capacity clamped");
+            for token in tokens {
+                limiter.release(token, Some(Outcome::Success)).await;
+            }
+        }
+
+        assert!(limiter.limit_algo.limit() > 4, "algorithm wants more");
+        assert_eq!(limiter.limit(), 4);
+        assert!(limiter.absolute_max_clamps() > 1);
+    }
+
+    #[tokio::test]
+    async fn observe() {
+        let observed = DefaultLimiter::new(Aimd::new_with_initial_limit(10).decrease_factor(0.5))
+            .with_inline_decrease(true);
+        let released = DefaultLimiter::new(Aimd::new_with_initial_limit(10).decrease_factor(0.5));
+
+        let outcomes = [Outcome::overload(), Outcome::Success, Outcome::overload()];
+        for outcome in outcomes {
+            observed.observe(Duration::from_millis(10), outcome).await;
+
+            let mut token = released.try_acquire().await.unwrap();
+            token.set_latency(Duration::from_millis(10));
+            released.release(token, Some(outcome)).await;
+        }
+
+        assert_eq!(observed.limit(), 2);
+        assert_eq!(observed.limit(), released.limit(), "adapts like real jobs");
+        assert_eq!(observed.state().available(), 2, "semaphore reconciled");
+        assert_eq!(observed.state().in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn forgetting() {
+        let limiter = DefaultLimiter::new(Aimd::new_with_initial_limit(10).decrease_factor(0.5));
+
+        let mut tokens = Vec::new();
+        for _ in 0..9 {
+            tokens.push(limiter.try_acquire().await.unwrap());
+        }
+        limiter
+            .release(tokens.pop().unwrap(), Some(Outcome::overload()))
+            .await;
+
+        assert_eq!(limiter.limit(), 5);
+        assert_eq!(limiter.forgetting(), 5, "waiting for jobs to finish");
+        assert_eq!(
+            limiter.effective_available(),
+            0,
+            "8 in flight, over the new limit"
+        );
+
+        for token in tokens {
+            limiter.release(token, None).await;
+        }
+        tokio::task::yield_now().await;
+
+        assert_eq!(limiter.forgetting(), 0);
+        assert_eq!(limiter.effective_available(), 5);
+    }
+
+    #[tokio::test]
+    async fn max_increase_per_second() {
+        #[derive(Debug)]
+        struct Jump(AtomicUsize);
+
+        #[async_trait]
+        impl LimitAlgorithm for Jump {
+            fn limit(&self) -> usize {
+                self.0.load(Ordering::Acquire)
+            }
+
+            async fn update(&self, _sample: Sample) -> usize {
+                self.0.store(100, Ordering::Release);
+                100
+            }
+        }
+
+        time::pause();
+
+        let limiter =
+            DefaultLimiter::new(Jump(AtomicUsize::new(10))).with_max_increase_per_second(5);
+
+        let mut limits = vec![];
+        for _ in 0..4 {
+            let token = limiter.try_acquire().await.unwrap();
+            limits.push(limiter.release(token, Some(Outcome::Success)).await);
+            time::advance(Duration::from_secs(1)).await;
+        }
+
+        assert_eq!(limits, vec![10, 15, 20, 25], "ramped up at 5 per second");
+    }
+
+    #[tokio::test]
+    async fn suppressed_increase_keeps_slew_allowance() {
+        time::pause();
+
+        let limiter = DefaultLimiter::new(
+            Aimd::new_with_initial_limit(10)
+                .increase_by(50)
+                .with_utilisation_gate(false),
+        )
+        .with_max_increase_per_second(5)
+        .with_decision_log(1);
+
+        time::advance(Duration::from_secs(1)).await;
+        limiter.freeze();
+        limiter.signal_success().await;
+        assert_eq!(limiter.limit(), 10, "frozen");
+
+        time::advance(Duration::from_secs(1)).await;
+        limiter.unfreeze();
+        limiter.signal_success().await;
+        assert_eq!(limiter.limit(), 20, "allowance for both seconds");
+
+        let decision = limiter.recent_decisions().await.pop().unwrap();
+        assert_eq!(decision.new_limit(), 20, "logs the slewed limit");
+    }
+
+    #[tokio::test]
+    async fn labeled_rejections() {
+        let limiter = DefaultLimiter::new(Fixed::new(1));
+
+        let _token = limiter.try_acquire_labeled("checkout").await.unwrap();
+        for _ in 0..2 {
+            assert!(limiter.try_acquire_labeled("checkout").await.is_none());
+        }
+        assert!(limiter.try_acquire_labeled("search").await.is_none());
+
+        let rejections = limiter.rejections_by_label();
+        assert_eq!(rejections.len(), 2);
+        assert_eq!(rejections["checkout"], 2);
+        assert_eq!(rejections["search"], 1);
+    }
+
+    #[tokio::test]
+    async fn from_throughput() {
+        let limiter = DefaultLimiter::from_throughput(200., Duration::from_millis(50), |limit| {
+            Aimd::new_with_initial_limit(limit)
+        });
+        assert_eq!(limiter.limit(), 10, "200 rps * 0.05 s");
+
+        let limiter = DefaultLimiter::from_throughput(30., Duration::from_millis(50), Fixed::new);
+        assert_eq!(limiter.limit(), 2, "rounded up");
+    }
+
+    #[tokio::test]
+    async fn soft_start() {
+        time::pause();
+
+        let limiter = DefaultLimiter::new(Aimd::new_with_initial_limit(1))
+            .with_soft_start(Duration::from_secs(10));
+
+        let mut tokens = vec![];
+        for _ in 0..5 {
+            tokens.push(
+                limiter
+                    .try_acquire()
+                    .await
+                    .expect("soft start: never reject"),
+            );
+        }
+        assert!(limiter
+            .acquire_timeout(Duration::from_secs(1))
+            .await
+            .is_some());
+
+        for token in tokens {
+            limiter.release(token, Some(Outcome::Success)).await;
+        }
+        assert!(limiter.limit() > 1, "overflow jobs still update the limit");
+
+        time::advance(Duration::from_secs(10)).await;
+
+        let mut tokens = vec![];
+        while let Some(token) = limiter.try_acquire().await {
+            tokens.push(token);
+        }
+        assert_eq!(tokens.len(), limiter.limit(), "rejects after soft start");
+    }
+
+    #[tokio::test]
+    async fn estimated_wait() {
+        time::pause();
+
+        let limiter = Arc::new(DefaultLimiter::new(Fixed::new(4)).with_estimated_wait());
+        assert_eq!(
+            limiter.estimated_wait(),
+            Duration::ZERO,
+            "capacity available"
+        );
+
+        let mut tokens = vec![];
+        for _ in 0..4 {
+            tokens.push(limiter.try_acquire().await.unwrap());
+        }
+        for mut token in tokens.drain(..) {
+            token.set_latency(Duration::from_millis(100));
+            limiter.release(token, Some(Outcome::Success)).await;
+        }
+
+        for _ in 0..4 {
+            tokens.push(limiter.try_acquire().await.unwrap());
+        }
+        let alone = limiter.estimated_wait();
+        assert_eq!(
+            alone,
+            Duration::from_millis(25),
+            "4 jobs in flight with 100ms latency: one finishes every 25ms"
+        );
+
+        let waiters: Vec<_> = (0..3)
+            .map(|_| {
+                tokio::spawn({
+                    let limiter = Arc::clone(&limiter);
+                    async move { limiter.acquire_timeout(Duration::from_secs(10)).await }
+                })
+            })
+            .collect();
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+
+        let queued = limiter.estimated_wait();
+        assert!(
+            queued > alone && queued <= Duration::from_millis(100),
+            "3 waiters ahead: wait for 4 jobs, got {queued:?}"
+        );
+
+        for token in tokens {
+            limiter.release(token, Some(Outcome::Success)).await;
+        }
+        for waiter in waiters {
+            assert!(waiter.await.unwrap().is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn on_reject() {
+        time::pause();
+
+        let rejections = Arc::new(std::sync::Mutex::new(vec![]));
+        let limiter = DefaultLimiter::new(Fixed::new(2)).with_on_reject(Box::new({
+            let rejections = Arc::clone(&rejections);
+            move |state| rejections.lock().unwrap().push(state.in_flight())
+        }));
+
+        let _tokens = [
+            limiter.try_acquire().await.unwrap(),
+            limiter.try_acquire().await.unwrap(),
+        ];
+        assert!(
+            rejections.lock().unwrap().is_empty(),
+            "not called on success"
+        );
+
+        assert!(limiter.try_acquire().await.is_none());
+        assert!(limiter
+            .acquire_timeout(Duration::from_secs(1))
+            .await
+            .is_none());
+
+        assert_eq!(*rejections.lock().unwrap(), [2, 2]);
+    }
+
+    #[tokio::test]
+    async fn backpressure() {
+        let limiter = DefaultLimiter::new(Fixed::new(2));
+        let mut backpressure = limiter.backpressure();
+        assert!(!*backpressure.borrow_and_update());
+
+        let token = limiter.try_acquire().await.unwrap();
+        assert!(
+            !backpressure.has_changed().unwrap(),
+            "capacity still available"
+        );
+
+        let _token = limiter.try_acquire().await.unwrap();
+        assert!(backpressure.has_changed().unwrap());
+        assert!(*backpressure.borrow_and_update(), "saturated");
 
         limiter.release(token, Some(Outcome::Success)).await;
+        assert!(backpressure.has_changed().unwrap());
+        assert!(
+            !*backpressure.borrow_and_update(),
+            "capacity available again"
+        );
+    }
 
-        assert_eq!(limiter.limit(), 10);
+    #[tokio::test]
+    async fn backpressure_subscribed_while_saturated() {
+        let limiter = DefaultLimiter::new(Fixed::new(1));
+        let _token = limiter.try_acquire().await.unwrap();
+
+        assert!(*limiter.backpressure().borrow(), "saturated");
+    }
+
+    #[tokio::test]
+    async fn take_stats() {
+        let limiter = Arc::new(DefaultLimiter::new(Fixed::new(3)));
+
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                tokio::spawn({
+                    let limiter = Arc::clone(&limiter);
+                    async move {
+                        for _ in 0..100 {
+                            if let Some(token) = limiter.try_acquire().await {
+                                tokio::task::yield_now().await;
+                                limiter.release(token, Some(Outcome::overload())).await;
+                            }
+                            tokio::task::yield_now().await;
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let (mut acquired, mut rejected, mut overloads) = (0, 0, 0);
+        let mut take = || {
+            let stats = limiter.take_stats();
+            acquired += stats.acquired();
+            rejected += stats.rejected();
+            overloads += stats.overloads();
+        };
+        while !workers.iter().all(|worker| worker.is_finished()) {
+            take();
+            tokio::task::yield_now().await;
+        }
+        take();
+
+        assert_eq!(acquired + rejected, 400, "every acquisition counted once");
+        assert!(rejected > 0);
+        assert_eq!(overloads, acquired);
+        assert_eq!(limiter.take_stats().acquired(), 0, "reset");
+    }
+
+    #[tokio::test]
+    async fn limiter_report() {
+        let limiter = DefaultLimiter::new(Vegas::new_with_initial_limit(3));
+
+        let mut tokens = limiter.acquire_available();
+        assert!(limiter.try_acquire().await.is_none());
+        let mut token = tokens.pop().unwrap();
+        token.set_latency(Duration::from_millis(10));
+        limiter.release(token, Some(Outcome::Success)).await;
+
+        let report = limiter.limiter_report().await;
+        let state = limiter.state();
+        assert_eq!(report.limit, state.limit());
+        assert_eq!(report.in_flight, state.in_flight());
+        assert_eq!(report.available, state.available());
+        assert_eq!(report.peak_in_flight, limiter.peak_in_flight());
+        assert_eq!(report.rejected, limiter.rejected());
+        assert_eq!(report.baseline_latency, limiter.algorithm_baseline().await);
+
+        assert_eq!(report.in_flight, 2);
+        assert_eq!(report.peak_in_flight, 3);
+        assert_eq!(report.rejected, 1);
+        assert_eq!(report.baseline_latency, Some(Duration::from_millis(10)));
+    }
+
+    #[tokio::test]
+    async fn goodput() {
+        time::pause();
+
+        let limiter = DefaultLimiter::new(Fixed::new(10)).with_goodput();
+
+        // 5 successes and 1 overload every 100ms, for 10 seconds.
+        for step in 0..100 {
+            if step > 0 {
+                time::advance(Duration::from_millis(100)).await;
+            }
+            for _ in 0..5 {
+                let token = limiter.try_acquire().await.unwrap();
+                limiter.release(token, Some(Outcome::Success)).await;
+            }
+            let token = limiter.try_acquire().await.unwrap();
+            limiter.release(token, Some(Outcome::overload())).await;
+        }
+        assert_eq!(limiter.goodput(), 50., "successes per second");
+
+        time::advance(Duration::from_secs(5)).await;
+        assert_eq!(limiter.goodput(), 25., "half the window has passed");
+    }
+
+    #[tokio::test]
+    async fn per_outcome_latency_percentiles() {
+        let limiter = DefaultLimiter::new(Fixed::new(10)).with_outcome_latencies();
+        assert_eq!(
+            limiter.success_latency_percentile(0.5).await,
+            Duration::ZERO
+        );
+
+        for millis in 1..=10 {
+            let mut token = limiter.try_acquire().await.unwrap();
+            token.set_latency(Duration::from_millis(millis));
+            limiter.release(token, Some(Outcome::Success)).await;
+
+            let mut token = limiter.try_acquire().await.unwrap();
+            token.set_latency(Duration::from_secs(millis));
+            limiter.release(token, Some(Outcome::overload())).await;
+        }
+
+        assert_eq!(
+            limiter.success_latency_percentile(0.99).await,
+            Duration::from_millis(10)
+        );
+        assert_eq!(
+            limiter.overload_latency_percentile(0.01).await,
+            Duration::from_secs(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn success_only_latency_source() {
+        #[derive(Debug, Default)]
+        struct Recording(Mutex<Vec<Duration>>);
+
+        #[async_trait]
+        impl LimitAlgorithm for Recording {
+            fn limit(&self) -> usize {
+                1
+            }
+
+            async fn update(&self, sample: Sample) -> usize {
+                self.0.lock().await.push(sample.latency);
+                1
+            }
+        }
+
+        let limiter = DefaultLimiter::new(Recording::default())
+            .with_latency_source(LatencySource::SuccessOnly)
+            .with_outcome_latencies();
+
+        for (latency, outcome) in [
+            (Duration::from_millis(10), Outcome::Success),
+            (Duration::from_secs(30), Outcome::overload()),
+        ] {
+            let mut token = limiter.try_acquire().await.unwrap();
+            token.set_latency(latency);
+            limiter.release(token, Some(outcome)).await;
+        }
+
+        assert_eq!(
+            *limiter.limit_algo.0.lock().await,
+            vec![Duration::from_millis(10), Duration::from_millis(10)],
+            "overload carries the success latency"
+        );
+        assert_eq!(
+            limiter.overload_latency_percentile(0.5).await,
+            Duration::from_secs(30),
+            "still tracked"
+        );
+    }
+
+    #[tokio::test]
+    async fn time_since_last_change() {
+        time::pause();
+
+        let limiter = DefaultLimiter::new(Aimd::new(10, 1..=10).decrease_factor(0.5));
+
+        time::advance(Duration::from_secs(5)).await;
+        assert_eq!(
+            limiter.time_since_last_change(),
+            Duration::from_secs(5),
+            "since creation"
+        );
+
+        limiter.signal_overload().await;
+        time::advance(Duration::from_secs(3)).await;
+        assert_eq!(limiter.time_since_last_change(), Duration::from_secs(3));
+
+        let token = limiter.try_acquire().await.unwrap();
+        limiter.release(token, None).await;
+        assert_eq!(
+            limiter.time_since_last_change(),
+            Duration::from_secs(3),
+            "limit unchanged"
+        );
+
+        limiter.signal_overload().await;
+        assert_eq!(limiter.time_since_last_change(), Duration::ZERO, "reset");
+    }
+
+    #[tokio::test]
+    async fn acquire_with_job_timeout() {
+        time::pause();
+
+        let limiter = Arc::new(DefaultLimiter::new(
+            Aimd::new(10, 1..=10).decrease_factor(0.5),
+        ));
+
+        let token = limiter
+            .acquire_with_job_timeout(Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(limiter.state().in_flight(), 1);
+
+        time::sleep(Duration::from_secs(1)).await;
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(limiter.state().in_flight(), 0, "permit auto-released");
+        assert_eq!(limiter.state().available(), 5);
+        assert_eq!(limiter.take_stats().overloads(), 1, "overload recorded");
+
+        limiter.release(token, Some(Outcome::overload())).await;
+        assert_eq!(limiter.limit(), 5, "late release ignored");
+        assert_eq!(limiter.take_stats().overloads(), 0);
+
+        let token = limiter
+            .acquire_with_job_timeout(Duration::from_secs(1))
+            .await
+            .unwrap();
+        limiter.release(token, Some(Outcome::Success)).await;
+        time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(
+            limiter.take_stats().overloads(),
+            0,
+            "released in time: no overload"
+        );
+    }
+
+    #[tokio::test]
+    async fn waiting() {
+        time::pause();
+
+        let limiter = Arc::new(DefaultLimiter::new(Fixed::new(1)));
+        let token = limiter.try_acquire().await.unwrap();
+        assert_eq!(limiter.waiting(), 0);
+
+        let waiters: Vec<_> = (0..2)
+            .map(|_| {
+                let limiter = limiter.clone();
+                tokio::spawn(async move { limiter.acquire_timeout(Duration::from_secs(1)).await })
+            })
+            .collect();
+        tokio::task::yield_now().await;
+        assert_eq!(limiter.waiting(), 2);
+
+        limiter.release(token, None).await;
+        tokio::task::yield_now().await;
+        assert_eq!(limiter.waiting(), 1, "one acquired");
+
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+        assert_eq!(limiter.waiting(), 0, "one timed out");
+    }
+
+    #[tokio::test]
+    async fn sample_channel() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(2);
+        let limiter = DefaultLimiter::new(Fixed::new(10)).with_sample_channel(tx);
+
+        for (latency, outcome) in [
+            (10, Outcome::Success),
+            (20, Outcome::overload()),
+            (30, Outcome::Success),
+        ] {
+            let mut token = limiter.try_acquire().await.unwrap();
+            token.set_latency(Duration::from_millis(latency));
+            limiter.release(token, Some(outcome)).await;
+        }
+
+        let first = rx.recv().await.unwrap();
+        assert_eq!(first.latency(), Duration::from_millis(10));
+        assert_eq!(first.outcome(), Outcome::Success);
+        assert_eq!(first.in_flight(), 1);
+
+        let second = rx.recv().await.unwrap();
+        assert_eq!(second.latency(), Duration::from_millis(20));
+        assert_eq!(second.outcome(), Outcome::overload());
+
+        assert!(rx.try_recv().is_err(), "dropped when full");
+    }
+
+    #[tokio::test]
+    async fn seeded_rng_is_reproducible() {
+        use rand::{rngs::SmallRng, SeedableRng};
+
+        let jittered = |seed| {
+            DefaultLimiter::new(Aimd::new_with_initial_limit(100))
+                .with_rng(SmallRng::seed_from_u64(seed))
+                .with_initial_limit_jitter(0.2)
+                .limit()
+        };
+
+        let runs: Vec<_> = (0..5).map(|_| jittered(42)).collect();
+        assert!(runs.iter().all(|limit| *limit == runs[0]), "{runs:?}");
+    }
+
+    #[tokio::test]
+    async fn observed_safe_max() {
+        async fn release_at_full_utilisation(limiter: &DefaultLimiter<Aimd>, outcome: Outcome) {
+            let tokens: Vec<_> = (0..limiter.limit())
+                .map(|_| limiter.try_acquire_now().unwrap().unwrap())
+                .collect();
+            let mut tokens = tokens.into_iter();
+            limiter.release(tokens.next().unwrap(), Some(outcome)).await;
+            for token in tokens {
+                limiter.release(token, None).await;
+            }
+        }
+
+        let limiter = DefaultLimiter::new(Aimd::new(10, 1..=100).decrease_factor(0.5))
+            .with_observed_safe_max();
+        assert_eq!(limiter.observed_safe_max(), None);
+
+        for _ in 0..10 {
+            release_at_full_utilisation(&limiter, Outcome::Success).await;
+        }
+        assert_eq!(limiter.limit(), 20);
+
+        for _ in 0..3 {
+            release_at_full_utilisation(&limiter, Outcome::overload()).await;
+        }
+        assert!(limiter.limit() < 10, "decreased");
+
+        assert_eq!(
+            limiter.observed_safe_max(),
+            Some(19),
+            "the highest limit jobs succeeded under"
+        );
     }
 }
@@ -1,15 +1,20 @@
 use std::{
-    collections::LinkedList,
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
     fmt::Debug,
-    sync::{atomic, Arc},
+    ops::RangeInclusive,
+    sync::{
+        atomic::{self, AtomicU64},
+        Arc,
+    },
     time::Duration,
 };
 
 use async_trait::async_trait;
 use conv::{ConvAsUtil, ConvUtil};
 use tokio::{
-    sync::{oneshot, RwLock},
-    time::timeout,
+    sync::{oneshot, Mutex, RwLock},
+    time::{sleep, timeout, timeout_at, Instant},
 };
 
 use crate::{
@@ -30,14 +35,104 @@ pub(crate) struct Scheduler {
 
     partition_states: Vec<PartitionState>,
 
-    waiters: RwLock<LinkedList<(StateIndex, oneshot::Sender<Token>)>>,
+    waiters: RwLock<BinaryHeap<Waiter>>,
+    next_waiter_seq: AtomicU64,
+
+    /// If set, partition fractions are periodically adjusted towards observed demand.
+    adaptive: Option<Adaptive>,
+
+    rounding: RoundingMode,
+    /// The total limit and the resulting partition limits, for [RoundingMode::LargestRemainder].
+    allocation: std::sync::Mutex<Option<(CapacityUnit, Vec<CapacityUnit>)>>,
+
+    /// If set, caps the rate of acquisitions across all partitions.
+    global_rate: std::sync::Mutex<Option<GlobalRate>>,
+}
+
+/// A token bucket capping the rate of acquisitions, allowing bursts of up to one second's worth.
+#[derive(Debug)]
+struct GlobalRate {
+    per_second: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// A token taken from the [GlobalRate], refunded when dropped unless it was used to acquire a
+/// [Token].
+struct RateToken<'a> {
+    /// Absent if there is no global rate, or once used.
+    scheduler: Option<&'a Scheduler>,
+}
+
+/// How to round each partition's fractional share of the total limit to a whole number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RoundingMode {
+    /// Round up. Partitions are never starved, but their limits can add up to more than the total.
+    #[default]
+    Ceil,
+    /// Round down. Useful for strict caps, but limits can add up to less than the total.
+    ///
+    /// Partitions with a non-zero weight get a limit of at least 1, so they aren't starved by a
+    /// low total limit.
+    Floor,
+    /// Round to the nearest whole number.
+    ///
+    /// Partitions with a non-zero weight get a limit of at least 1, as for [Floor](Self::Floor).
+    Round,
+    /// Round down, then give the remainder to the partitions with the largest fractional parts.
+    ///
+    /// Limits add up to exactly the total.
+    LargestRemainder,
+}
+
+#[derive(Debug)]
+struct Adaptive {
+    interval: Duration,
+    last_rebalance: Mutex<Instant>,
+    policy: AdaptivePolicy,
+}
+
+/// What adaptive partition fractions are moved towards.
+#[derive(Debug, Clone, Copy)]
+enum AdaptivePolicy {
+    /// Each partition's share of recent demand.
+    Demand,
+    /// Each partition's initial fraction, scaled down by the fraction of its recent jobs slower
+    /// than the given latency SLO.
+    Slo(Duration),
+}
+
+/// A job waiting for a [Token].
+#[derive(Debug)]
+struct Waiter {
+    priority: u8,
+    /// Preserves FIFO ordering for waiters with the same priority.
+    seq: u64,
+    index: StateIndex,
+    /// Among waiters with the same priority, prefer the least subscribed partition, rather than
+    /// FIFO. See [PartitionedLimiter::acquire_deadline_fair()].
+    fair: bool,
+    sender: oneshot::Sender<Token>,
 }
 
 #[derive(Debug)]
 struct PartitionState {
-    fraction: f64,
+    /// The bits of an `f64` fraction of the total limit.
+    fraction: AtomicU64,
+    /// The fraction the partition was created with, which SLO-driven partitions recover towards.
+    initial_fraction: f64,
+    /// Bounds for the fraction when adapting to demand.
+    fraction_bounds: RangeInclusive<f64>,
     /// Shared with [Token]s.
     in_flight: Arc<AtomicCapacityUnit>,
+    /// Rejected acquisitions since the last rebalance.
+    rejected: AtomicCapacityUnit,
+    /// Capacity which other partitions can't borrow, even when unused.
+    reserved: AtomicCapacityUnit,
+    /// Released jobs since the last rebalance, for SLO-driven partitions.
+    released: AtomicCapacityUnit,
+    /// Released jobs which breached the SLO since the last rebalance.
+    slo_breaches: AtomicCapacityUnit,
 }
 
 /// A partition, using some fraction of the concurrency limit.
@@ -48,6 +143,21 @@ pub struct PartitionedLimiter<L> {
 
     scheduler: Arc<Scheduler>,
     limiter: Arc<DefaultLimiter<L>>,
+
+    /// Feed acquire timeouts back to the shared limit algorithm.
+    self_shed_signal: bool,
+}
+
+/// Clones refer to the same partition, sharing its capacity and in-flight count.
+impl<L> Clone for PartitionedLimiter<L> {
+    fn clone(&self) -> Self {
+        Self {
+            index: self.index,
+            scheduler: Arc::clone(&self.scheduler),
+            limiter: Arc::clone(&self.limiter),
+            self_shed_signal: self.self_shed_signal,
+        }
+    }
 }
 
 impl<L: LimitAlgorithm + Sync> DefaultLimiter<L> {
@@ -56,28 +166,114 @@ impl<L: LimitAlgorithm + Sync> DefaultLimiter<L> {
     /// The provided weights will be normalised. E.g. weights of 2, 2 and 4 will result in
     /// partitions of 25%, 25% and 50% of the total limit, respectively.
     ///
-    /// `weights` must not be empty.
+    /// `weights` must not be empty, and must be finite and non-negative with a positive total.
     pub fn create_static_partitions(self, weights: Vec<f64>) -> Vec<PartitionedLimiter<L>> {
-        assert!(!weights.is_empty(), "Must provide at least one weight");
+        self.create_static_partitions_with_rounding(weights, RoundingMode::default())
+    }
 
-        let total: f64 = weights.iter().sum();
+    /// Like [create_static_partitions()](Self::create_static_partitions()), using the given
+    /// [RoundingMode] to calculate each partition's share of the total limit.
+    pub fn create_static_partitions_with_rounding(
+        self,
+        weights: Vec<f64>,
+        rounding: RoundingMode,
+    ) -> Vec<PartitionedLimiter<L>> {
+        let total = validate_weights(weights.iter().copied());
 
-        let mut partition_states = Vec::with_capacity(weights.len());
+        let partition_states = weights
+            .into_iter()
+            .map(|weight| PartitionState::new(weight / total, 0.0..=1.0))
+            .collect();
 
-        for weight in weights {
-            let fraction = weight / total;
+        self.create_partitions(partition_states, None, rounding)
+    }
 
-            partition_states.push(PartitionState {
-                fraction,
-                in_flight: Arc::new(AtomicCapacityUnit::new(0)),
-            });
-        }
+    /// Divide up this limiter into a set of partitions with the given initial relative weights,
+    /// which adapt to observed demand.
+    ///
+    /// Every `interval`, each partition's fraction of the total limit is moved towards its share
+    /// of recent demand (jobs in flight plus rejected acquisitions), within the given bounds. This
+    /// redistributes capacity from idle partitions to busy ones.
+    ///
+    /// `partitions` must not be empty, and weights must be finite and non-negative with a positive
+    /// total.
+    pub fn create_adaptive_partitions(
+        self,
+        partitions: Vec<(f64, RangeInclusive<f64>)>,
+        interval: Duration,
+    ) -> Vec<PartitionedLimiter<L>> {
+        self.create_adaptive_partitions_with_policy(partitions, interval, AdaptivePolicy::Demand)
+    }
+
+    /// Divide up this limiter into a set of partitions with the given initial relative weights,
+    /// which adapt to how well each partition meets a latency SLO.
+    ///
+    /// Every `interval`, partitions whose recent jobs took longer than `slo` cede some of their
+    /// fraction of the total limit to compliant partitions, within the given bounds. A partition's
+    /// target fraction is its initial fraction scaled by the proportion of its jobs within the SLO,
+    /// normalised across all partitions. This throttles partitions whose dependencies are
+    /// struggling, and restores their share once they recover.
+    ///
+    /// `partitions` must not be empty, and weights must be finite and non-negative with a positive
+    /// total.
+    pub fn create_slo_partitions(
+        self,
+        partitions: Vec<(f64, RangeInclusive<f64>)>,
+        slo: Duration,
+        interval: Duration,
+    ) -> Vec<PartitionedLimiter<L>> {
+        self.create_adaptive_partitions_with_policy(partitions, interval, AdaptivePolicy::Slo(slo))
+    }
+
+    fn create_adaptive_partitions_with_policy(
+        self,
+        partitions: Vec<(f64, RangeInclusive<f64>)>,
+        interval: Duration,
+        policy: AdaptivePolicy,
+    ) -> Vec<PartitionedLimiter<L>> {
+        let total = validate_weights(partitions.iter().map(|(weight, _)| *weight));
+
+        let partition_states = partitions
+            .into_iter()
+            .map(|(weight, bounds)| {
+                assert!(
+                    *bounds.start() >= 0. && *bounds.end() <= 1. && !bounds.is_empty(),
+                    "fraction bounds must be within 0 and 1"
+                );
+                PartitionState::new(
+                    (weight / total).clamp(*bounds.start(), *bounds.end()),
+                    bounds,
+                )
+            })
+            .collect();
+
+        self.create_partitions(
+            partition_states,
+            Some(Adaptive {
+                interval,
+                last_rebalance: Mutex::new(Instant::now()),
+                policy,
+            }),
+            RoundingMode::default(),
+        )
+    }
 
+    fn create_partitions(
+        self,
+        partition_states: Vec<PartitionState>,
+        adaptive: Option<Adaptive>,
+        rounding: RoundingMode,
+    ) -> Vec<PartitionedLimiter<L>> {
         let shared_limiter = Arc::new(self);
         let scheduler = Arc::new(Scheduler {
             _total_in_flight: shared_limiter.in_flight_shared(),
             partition_states,
             waiters: RwLock::default(),
+            next_waiter_seq: AtomicU64::new(0),
+            adaptive,
+            rounding,
+            allocation: std::sync::Mutex::new(None),
+            global_rate: std::sync::Mutex::new(None),
         });
 
         let mut partitions = Vec::with_capacity(scheduler.partition_states.len());
@@ -86,6 +282,7 @@ impl<L: LimitAlgorithm + Sync> DefaultLimiter<L> {
                 index: partitions.len(),
                 scheduler: scheduler.clone(),
                 limiter: shared_limiter.clone(),
+                self_shed_signal: false,
             });
         }
 
@@ -93,8 +290,34 @@ impl<L: LimitAlgorithm + Sync> DefaultLimiter<L> {
     }
 }
 
+/// Check that partition weights can be normalised into fractions, returning their total.
+///
+/// Panics with a descriptive message otherwise, rather than producing NaN fractions.
+fn validate_weights(weights: impl Iterator<Item = f64>) -> f64 {
+    let mut count = 0;
+    let mut total = 0.;
+    for weight in weights {
+        assert!(
+            weight.is_finite(),
+            "partition weights must be finite, got {weight}"
+        );
+        assert!(
+            weight >= 0.,
+            "partition weights must not be negative, got {weight}"
+        );
+        count += 1;
+        total += weight;
+    }
+    assert!(count > 0, "Must provide at least one weight");
+    assert!(total > 0., "partition weights must not all be zero");
+    total
+}
+
 impl Scheduler {
-    /// When a permit becomes available, give it to the next job in the queue with the higher
+    /// How far to move each partition's fraction towards its target on each rebalance.
+    const REBALANCE_SMOOTHING: f64 = 0.5;
+
+    /// When a permit becomes available, give it to the next job in the queue with the highest
     /// priority.
     ///
     /// The underlying semaphore is simply a FIFO queue. Instead, what we want is to give out tokens
@@ -102,17 +325,21 @@ impl Scheduler {
     /// oversubscribed.
     pub(crate) fn reuse_permit(self: Arc<Scheduler>, token_inner: TokenInner) {
         tokio::spawn(async move {
-            // TODO: A better strategy for choosing which waiter to wake, based on priority.
-            // For now this is just a FIFO queue, so it's kind of pointless!
-            let waiter = self.waiters.write().await.pop_front();
+            let waiter = {
+                let mut waiters = self.waiters.write().await;
+                match waiters.peek() {
+                    Some(top) if top.fair => self.pop_fairest(&mut waiters),
+                    _ => waiters.pop(),
+                }
+            };
             match waiter {
-                Some((index, waiter)) => {
+                Some(waiter) => {
                     let token =
                         Token::new_from_inner(token_inner).for_partition(token::Partition::new(
-                            self.partition_states[index].in_flight.clone(),
+                            self.partition_states[waiter.index].in_flight.clone(),
                             self.clone(),
                         ));
-                    match waiter.send(token) {
+                    match waiter.sender.send(token) {
                         Ok(()) => {}
                         Err(_) => {
                             // Nothing to do, the token will be dropped
@@ -124,33 +351,372 @@ impl Scheduler {
         });
     }
 
-    /// Total spare capacity which can be used by any partition.
-    fn spare(&self, total_limit: CapacityUnit) -> CapacityUnit {
-        self.partition_states
+    /// Pop the fair waiter with the highest priority whose partition is least subscribed, i.e. has
+    /// the fewest jobs in flight relative to its fraction of the limit. Ties are FIFO.
+    fn pop_fairest(&self, waiters: &mut BinaryHeap<Waiter>) -> Option<Waiter> {
+        let priority = waiters.peek()?.priority;
+        let mut candidates = std::mem::take(waiters).into_vec();
+
+        let subscription = |index: StateIndex| {
+            let state = &self.partition_states[index];
+            match state.fraction() {
+                0. => f64::INFINITY,
+                fraction => state.in_flight() as f64 / fraction,
+            }
+        };
+        let fairest = candidates
             .iter()
-            .fold(0, |total, partition| total + partition.spare(total_limit))
+            .enumerate()
+            .filter(|(_, waiter)| waiter.fair && waiter.priority == priority)
+            .min_by(|(_, a), (_, b)| {
+                subscription(a.index)
+                    .total_cmp(&subscription(b.index))
+                    .then(a.seq.cmp(&b.seq))
+            })
+            .map(|(position, _)| position)
+            .expect("the top waiter is fair");
+
+        let waiter = candidates.swap_remove(fairest);
+        *waiters = BinaryHeap::from(candidates);
+        Some(waiter)
     }
-}
 
-impl PartitionState {
-    const BUFFER_FRACTION: f64 = 0.1;
+    async fn push_waiter(
+        &self,
+        index: StateIndex,
+        priority: u8,
+        fair: bool,
+    ) -> oneshot::Receiver<Token> {
+        let (sender, rx) = oneshot::channel();
+        let seq = self.next_waiter_seq.fetch_add(1, atomic::Ordering::SeqCst);
+        self.waiters.write().await.push(Waiter {
+            priority,
+            seq,
+            index,
+            fair,
+            sender,
+        });
+        rx
+    }
 
-    fn limit(&self, total_limit: CapacityUnit) -> CapacityUnit {
-        fractional_limit(total_limit, self.fraction)
+    /// Take a token from the global rate cap, if there is one. Otherwise, returns how long until
+    /// one will be available.
+    fn take_rate_token(&self) -> Result<RateToken<'_>, Duration> {
+        let mut global_rate = self
+            .global_rate
+            .lock()
+            .expect("lock should not be poisoned");
+        match global_rate.as_mut() {
+            Some(rate) => rate.take().map(|()| RateToken {
+                scheduler: Some(self),
+            }),
+            None => Ok(RateToken { scheduler: None }),
+        }
     }
 
-    fn in_flight(&self) -> CapacityUnit {
-        self.in_flight.load(atomic::Ordering::SeqCst)
+    /// Wait until a token can be taken from the global rate cap, if there is one.
+    async fn wait_for_rate_token(&self) -> RateToken<'_> {
+        loop {
+            match self.take_rate_token() {
+                Ok(rate_token) => return rate_token,
+                Err(wait) => tokio::time::sleep(wait).await,
+            }
+        }
     }
 
-    /// Spare capacity which can be used by other partitions.
-    fn spare(&self, total_limit: CapacityUnit) -> CapacityUnit {
-        let partition_limit = self.limit(total_limit);
-        let buffer = (partition_limit as f64 * Self::BUFFER_FRACTION)
+    fn refund_rate_token(&self) {
+        if let Some(rate) = self
+            .global_rate
+            .lock()
+            .expect("lock should not be poisoned")
+            .as_mut()
+        {
+            rate.refund();
+        }
+    }
+
+    /// Whether any fair waiters are queued.
+    async fn has_fair_waiters(&self) -> bool {
+        self.waiters.read().await.iter().any(|waiter| waiter.fair)
+    }
+
+    /// For adaptive partitions, move each partition's fraction towards its share of recent demand,
+    /// if the rebalance interval has elapsed.
+    fn maybe_rebalance(&self) {
+        let Some(adaptive) = &self.adaptive else {
+            return;
+        };
+        // Someone else is already rebalancing.
+        let Ok(mut last_rebalance) = adaptive.last_rebalance.try_lock() else {
+            return;
+        };
+        if last_rebalance.elapsed() < adaptive.interval {
+            return;
+        }
+        *last_rebalance = Instant::now();
+
+        let weights: Vec<f64> = self
+            .partition_states
+            .iter()
+            .map(|state| match adaptive.policy {
+                AdaptivePolicy::Demand => {
+                    let rejected = state.rejected.swap(0, atomic::Ordering::SeqCst);
+                    (state.in_flight() + rejected) as f64
+                }
+                AdaptivePolicy::Slo(_) => {
+                    // Not used by this policy, but reset so it doesn't grow without bound.
+                    state.rejected.store(0, atomic::Ordering::SeqCst);
+                    let released = state.released.swap(0, atomic::Ordering::SeqCst);
+                    let breaches = state.slo_breaches.swap(0, atomic::Ordering::SeqCst);
+                    let compliance = if released == 0 {
+                        1.
+                    } else {
+                        1. - breaches as f64 / released as f64
+                    };
+                    state.initial_fraction * compliance
+                }
+            })
+            .collect();
+        let total_weight: f64 = weights.iter().sum();
+        if total_weight == 0. {
+            return;
+        }
+
+        for (state, weight) in self.partition_states.iter().zip(weights) {
+            let target = weight / total_weight;
+            let fraction = state.fraction();
+            let new_fraction = fraction + (target - fraction) * Self::REBALANCE_SMOOTHING;
+            state.set_fraction(
+                new_fraction.clamp(*state.fraction_bounds.start(), *state.fraction_bounds.end()),
+            );
+        }
+        *self.allocation.lock().expect("lock should not be poisoned") = None;
+    }
+
+    /// For SLO-driven partitions, record the latency of a job released from the partition at
+    /// `index`.
+    fn record_latency(&self, index: StateIndex, latency: Duration) {
+        let Some(Adaptive {
+            policy: AdaptivePolicy::Slo(slo),
+            ..
+        }) = &self.adaptive
+        else {
+            return;
+        };
+        let state = &self.partition_states[index];
+        state.released.fetch_add(1, atomic::Ordering::SeqCst);
+        if latency > *slo {
+            state.slo_breaches.fetch_add(1, atomic::Ordering::SeqCst);
+        }
+    }
+
+    /// The limit for the partition at `index`, given the total limit.
+    fn partition_limit(&self, index: StateIndex, total_limit: CapacityUnit) -> CapacityUnit {
+        let fraction = self.partition_states[index].fraction();
+        let at_least_one = |limit: CapacityUnit| {
+            if fraction > 0. {
+                limit.max(1)
+            } else {
+                limit
+            }
+        };
+        match self.rounding {
+            RoundingMode::Ceil => fractional_limit(total_limit, fraction, f64::ceil),
+            RoundingMode::Floor => {
+                at_least_one(fractional_limit(total_limit, fraction, f64::floor))
+            }
+            RoundingMode::Round => {
+                at_least_one(fractional_limit(total_limit, fraction, f64::round))
+            }
+            RoundingMode::LargestRemainder => self.largest_remainder_limit(index, total_limit),
+        }
+    }
+
+    /// The limit for the partition at `index`, allocated using the largest remainder method.
+    ///
+    /// The allocation is cached until the total limit or the partition fractions change.
+    fn largest_remainder_limit(
+        &self,
+        index: StateIndex,
+        total_limit: CapacityUnit,
+    ) -> CapacityUnit {
+        let mut allocation = self.allocation.lock().expect("lock should not be poisoned");
+        match &*allocation {
+            Some((total, limits)) if *total == total_limit => limits[index],
+            _ => {
+                let limits = self.largest_remainder_allocation(total_limit);
+                let limit = limits[index];
+                *allocation = Some((total_limit, limits));
+                limit
+            }
+        }
+    }
+
+    /// Allocate the total limit between partitions using the largest remainder method.
+    fn largest_remainder_allocation(&self, total_limit: CapacityUnit) -> Vec<CapacityUnit> {
+        let quotas: Vec<f64> = self
+            .partition_states
+            .iter()
+            .map(|state| total_limit as f64 * state.fraction())
+            .collect();
+
+        let allocated: f64 = quotas.iter().map(|quota| quota.floor()).sum();
+        let remainder = (quotas.iter().sum::<f64>().round() - allocated)
+            .max(0.)
+            .approx_as::<usize>()
+            .expect("remainder should be less than the number of partitions");
+
+        // Ties are broken in favour of earlier partitions.
+        let mut by_remainder: Vec<StateIndex> = (0..quotas.len()).collect();
+        by_remainder.sort_by(|&a, &b| {
+            let (a_rem, b_rem) = (quotas[a] - quotas[a].floor(), quotas[b] - quotas[b].floor());
+            b_rem.total_cmp(&a_rem).then(a.cmp(&b))
+        });
+
+        let mut limits: Vec<CapacityUnit> = self
+            .partition_states
+            .iter()
+            .map(|state| fractional_limit(total_limit, state.fraction(), f64::floor))
+            .collect();
+        for &index in by_remainder.iter().take(remainder) {
+            limits[index] += 1;
+        }
+        limits
+    }
+
+    /// Spare capacity in the partition at `index` which can be used by other partitions.
+    fn partition_spare(&self, index: StateIndex, total_limit: CapacityUnit) -> CapacityUnit {
+        let state = &self.partition_states[index];
+        let partition_limit = self.partition_limit(index, total_limit);
+        let buffer = (partition_limit as f64 * PartitionState::BUFFER_FRACTION)
             .ceil()
             .approx_as::<CapacityUnit>()
             .expect("should be < usize::MAX");
-        (partition_limit - self.in_flight()).saturating_sub(buffer)
+        partition_limit
+            .saturating_sub(state.in_flight().max(state.reserved()))
+            .saturating_sub(buffer)
+    }
+
+    /// Total spare capacity which can be used by any partition, minus what is already borrowed.
+    fn spare(&self, total_limit: CapacityUnit) -> CapacityUnit {
+        let (lendable, borrowed) =
+            (0..self.partition_states.len()).fold((0, 0), |(lendable, borrowed), index| {
+                let over_limit = self.partition_states[index]
+                    .in_flight()
+                    .saturating_sub(self.partition_limit(index, total_limit));
+                (
+                    lendable + self.partition_spare(index, total_limit),
+                    borrowed + over_limit,
+                )
+            });
+        lendable.saturating_sub(borrowed)
+    }
+
+    /// Spare capacity which the partition at `index` can borrow from other partitions.
+    fn spare_for(&self, index: StateIndex, total_limit: CapacityUnit) -> CapacityUnit {
+        self.spare(total_limit)
+            .saturating_sub(self.partition_spare(index, total_limit))
+    }
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Waiter {}
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.priority, Reverse(self.seq)).cmp(&(other.priority, Reverse(other.seq)))
+    }
+}
+
+impl GlobalRate {
+    fn new(per_second: f64) -> Self {
+        let burst = per_second.max(1.);
+        Self {
+            per_second,
+            tokens: burst,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn take(&mut self) -> Result<(), Duration> {
+        let now = Instant::now();
+        let burst = self.per_second.max(1.);
+        let refill = now.duration_since(self.last_refill).as_secs_f64() * self.per_second;
+        self.tokens = (self.tokens + refill).min(burst);
+        self.last_refill = now;
+
+        if self.tokens >= 1. {
+            self.tokens -= 1.;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1. - self.tokens) / self.per_second,
+            ))
+        }
+    }
+
+    fn refund(&mut self) {
+        self.tokens = (self.tokens + 1.).min(self.per_second.max(1.));
+    }
+}
+
+impl RateToken<'_> {
+    /// Keep this rate token if `token` was acquired, otherwise refund it.
+    fn used_for(mut self, token: Option<Token>) -> Option<Token> {
+        if token.is_some() {
+            self.scheduler = None;
+        }
+        token
+    }
+}
+
+impl Drop for RateToken<'_> {
+    fn drop(&mut self) {
+        if let Some(scheduler) = self.scheduler {
+            scheduler.refund_rate_token();
+        }
+    }
+}
+
+impl PartitionState {
+    const BUFFER_FRACTION: f64 = 0.1;
+
+    fn new(fraction: f64, fraction_bounds: RangeInclusive<f64>) -> Self {
+        Self {
+            fraction: AtomicU64::new(fraction.to_bits()),
+            initial_fraction: fraction,
+            fraction_bounds,
+            in_flight: Arc::new(AtomicCapacityUnit::new(0)),
+            rejected: AtomicCapacityUnit::new(0),
+            reserved: AtomicCapacityUnit::new(0),
+            released: AtomicCapacityUnit::new(0),
+            slo_breaches: AtomicCapacityUnit::new(0),
+        }
+    }
+
+    fn reserved(&self) -> CapacityUnit {
+        self.reserved.load(atomic::Ordering::Acquire)
+    }
+
+    fn fraction(&self) -> f64 {
+        f64::from_bits(self.fraction.load(atomic::Ordering::Acquire))
+    }
+
+    fn set_fraction(&self, fraction: f64) {
+        self.fraction
+            .store(fraction.to_bits(), atomic::Ordering::Release);
+    }
+
+    fn in_flight(&self) -> CapacityUnit {
+        self.in_flight.load(atomic::Ordering::SeqCst)
     }
 }
 
@@ -160,67 +726,876 @@ where
     L: LimitAlgorithm + Sync + Send + Debug,
 {
     async fn try_acquire(&self) -> Option<Token> {
+        self.scheduler.maybe_rebalance();
+
         let state = &self.scheduler.partition_states[self.index];
 
-        let total_limit = self.limiter.limit();
-        if state.in_flight() < state.limit(total_limit) || self.scheduler.spare(total_limit) > 0 {
-            self.limiter.try_acquire().await.map(|token| {
-                token.for_partition(token::Partition::new(
-                    state.in_flight.clone(),
-                    self.scheduler.clone(),
-                ))
-            })
-        } else {
-            None
+        let token = match self.scheduler.take_rate_token() {
+            Ok(rate_token) if self.has_capacity() => {
+                let token = self.limiter.try_acquire().await;
+                rate_token.used_for(token.map(|token| self.assign(token)))
+            }
+            _ => None,
+        };
+
+        if token.is_none() {
+            state.rejected.fetch_add(1, atomic::Ordering::SeqCst);
         }
+        token
     }
 
+    /// Waiters will be given the lowest priority. See
+    /// [acquire_timeout_priority()](PartitionedLimiter::acquire_timeout_priority()).
     async fn acquire_timeout(&self, duration: Duration) -> Option<Token> {
+        self.acquire_timeout_priority(duration, 0).await
+    }
+
+    /// Returns the new total limit, shared between all partitions. See
+    /// [partition_limit()](PartitionedLimiter::partition_limit()) for this partition's share.
+    async fn release(&self, token: Token, outcome: Option<Outcome>) -> CapacityUnit {
+        if outcome.is_some() {
+            self.scheduler.record_latency(self.index, token.latency());
+        }
+        self.limiter.release(token, outcome).await
+    }
+}
+
+impl<L> PartitionedLimiter<L>
+where
+    L: LimitAlgorithm + Sync + Send + Debug,
+{
+    /// Try to acquire a concurrency [Token], waiting for `duration` if there are none available.
+    ///
+    /// When a token becomes available, it will be given to the waiter with the highest `priority`.
+    /// Waiters with the same priority are served in FIFO order.
+    ///
+    /// Returns `None` if there are none available after `duration`.
+    pub async fn acquire_timeout_priority(
+        &self,
+        duration: Duration,
+        priority: u8,
+    ) -> Option<Token> {
+        self.scheduler.maybe_rebalance();
+
         let state = &self.scheduler.partition_states[self.index];
-        match timeout(duration, async {
-            let total_limit = self.limiter.limit();
-            if state.in_flight() < state.limit(total_limit) || self.scheduler.spare(total_limit) > 0
-            {
-                self.limiter.try_acquire().await
+        let token = timeout(duration, async {
+            let rate_token = self.scheduler.wait_for_rate_token().await;
+
+            let token = if self.has_capacity() {
+                let token = self.limiter.try_acquire().await;
+                token.map(|token| self.assign(token))
             } else {
-                let (snd, rx) = oneshot::channel();
-                let mut waiters = self.scheduler.waiters.write().await;
-                waiters.push_back((self.index, snd));
-                match rx.await {
-                    Ok(token) => Some(token),
-                    Err(_) => None,
+                // Tokens reused from other jobs are already assigned to this partition.
+                let rx = self
+                    .scheduler
+                    .push_waiter(self.index, priority, false)
+                    .await;
+                rx.await.ok()
+            };
+            rate_token.used_for(token)
+        })
+        .await;
+
+        let token = match token {
+            Ok(token) => token,
+            Err(_) => {
+                if self.self_shed_signal {
+                    self.limiter.self_shed(duration).await;
+                }
+                None
+            }
+        };
+
+        if token.is_none() {
+            state.rejected.fetch_add(1, atomic::Ordering::SeqCst);
+        }
+        token
+    }
+
+    /// Try to acquire a concurrency [Token] before `deadline`, sharing freed capacity fairly
+    /// between partitions under contention.
+    ///
+    /// A job within this partition's share of the limit is admitted immediately, if possible. Spare
+    /// capacity is only borrowed from other partitions if no other fair acquisitions are waiting.
+    /// Otherwise the job waits, and each freed token is given to the waiting job whose partition
+    /// has the fewest jobs in flight relative to its share, rather than in FIFO order.
+    ///
+    /// Fair waiters have the lowest priority, the same as
+    /// [acquire_timeout()](Limiter::acquire_timeout()).
+    ///
+    /// Returns `None` if there are none available by `deadline`.
+    pub async fn acquire_deadline_fair(&self, deadline: Instant) -> Option<Token> {
+        self.scheduler.maybe_rebalance();
+
+        let state = &self.scheduler.partition_states[self.index];
+        let token = timeout_at(deadline, async {
+            let rate_token = self.scheduler.wait_for_rate_token().await;
+
+            let total_limit = self.limiter.limit();
+            let within_share =
+                state.in_flight() < self.scheduler.partition_limit(self.index, total_limit);
+            let can_borrow =
+                self.scheduler.spare(total_limit) > 0 && !self.scheduler.has_fair_waiters().await;
+            if within_share || can_borrow {
+                if let Some(token) = self.limiter.try_acquire().await {
+                    return rate_token.used_for(Some(self.assign(token)));
                 }
             }
+            // Tokens reused from other jobs are already assigned to this partition.
+            let rx = self.scheduler.push_waiter(self.index, 0, true).await;
+            rate_token.used_for(rx.await.ok())
         })
         .await
+        .ok()
+        .flatten();
+
+        if token.is_none() {
+            state.rejected.fetch_add(1, atomic::Ordering::SeqCst);
+        }
+        token
+    }
+
+    /// Move a [Token] acquired from this partition to another partition of the same limiter.
+    ///
+    /// The token keeps its underlying concurrency permit, so this can't fail due to lack of
+    /// capacity. Only the per-partition accounting changes.
+    ///
+    /// Returns the token unchanged as an error if it wasn't acquired from this partition, or if
+    /// `to` is a partition of a different limiter.
+    pub fn transfer(&self, mut token: Token, to: &PartitionedLimiter<L>) -> Result<Token, Token> {
+        let state = &self.scheduler.partition_states[self.index];
+        if !token.is_for_partition(&state.in_flight) || !Arc::ptr_eq(&self.scheduler, &to.scheduler)
         {
-            Ok(Some(token)) => Some(token.for_partition(token::Partition::new(
-                state.in_flight.clone(),
-                self.scheduler.clone(),
-            ))),
-            Err(_) => None,
-            Ok(None) => None,
+            return Err(token);
         }
+
+        token.transfer_partition(token::Partition::new(
+            to.scheduler.partition_states[to.index].in_flight.clone(),
+            to.scheduler.clone(),
+        ));
+        Ok(token)
     }
 
-    async fn release(&self, token: Token, outcome: Option<Outcome>) -> CapacityUnit {
-        self.limiter.release(token, outcome).await
+    /// Acquire a [Token] from whichever of `partitions` has the most spare capacity, returning its
+    /// index in `partitions` along with the token.
+    ///
+    /// Partitions are tried in order of spare capacity, so if the least-loaded partition is
+    /// saturated by the time we try to acquire, the next-least-loaded is tried, and so on. If all
+    /// partitions are saturated, a single rejection is recorded against the least-loaded partition,
+    /// and `None` is returned after waiting for `rejection_delay` once, rather than once per
+    /// partition.
+    ///
+    /// The partitions may belong to different limiters.
+    pub async fn acquire_least_loaded(
+        partitions: &[&Self],
+        rejection_delay: Duration,
+    ) -> Option<(usize, Token)> {
+        let mut by_spare: Vec<(usize, CapacityUnit)> = partitions
+            .iter()
+            .enumerate()
+            .map(|(index, partition)| {
+                let spare = partition
+                    .partition_limit()
+                    .saturating_sub(partition.in_flight());
+                (index, spare)
+            })
+            .collect();
+        // Stable, so ties go to the earliest partition.
+        by_spare.sort_by_key(|&(_, spare)| Reverse(spare));
+
+        for &(index, _) in &by_spare {
+            let partition = partitions[index];
+            partition.scheduler.maybe_rebalance();
+            match partition.scheduler.take_rate_token() {
+                Ok(rate_token) if partition.has_capacity() => {
+                    let token = partition.limiter.try_acquire_now().ok().flatten();
+                    if let Some(token) = rate_token.used_for(token.map(|t| partition.assign(t))) {
+                        return Some((index, token));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(&(index, _)) = by_spare.first() {
+            let partition = partitions[index];
+            if let Some(token) = partition.limiter.shadow_rejection() {
+                return Some((index, partition.assign(token)));
+            }
+            partition.scheduler.partition_states[partition.index]
+                .rejected
+                .fetch_add(1, atomic::Ordering::SeqCst);
+        }
+
+        sleep(rejection_delay).await;
+        None
+    }
+
+    /// Whether this partition is within its share of the limit, or can borrow spare capacity from
+    /// other partitions.
+    fn has_capacity(&self) -> bool {
+        let state = &self.scheduler.partition_states[self.index];
+        let total_limit = self.limiter.limit();
+        state.in_flight() < self.scheduler.partition_limit(self.index, total_limit)
+            || self.scheduler.spare(total_limit) > 0
+    }
+
+    /// Assign a token acquired from the limiter to this partition.
+    fn assign(&self, token: Token) -> Token {
+        token.for_partition(token::Partition::new(
+            self.scheduler.partition_states[self.index]
+                .in_flight
+                .clone(),
+            self.scheduler.clone(),
+        ))
+    }
+
+    /// Notify the shared limit algorithm when [acquire_timeout()](Limiter::acquire_timeout()) times
+    /// out waiting in this partition. See
+    /// [DefaultLimiter::with_acquire_timeout_feedback()], which uses the same heuristic.
+    pub fn with_self_shed_signal(mut self, enabled: bool) -> Self {
+        self.self_shed_signal = enabled;
+        self
+    }
+
+    /// Reserve `permits` of this partition's share of the limit, so that other partitions can't
+    /// borrow them as spare capacity, even while they're unused.
+    ///
+    /// Protects critical traffic from being starved by bursts in other partitions.
+    pub fn with_reserved(self, permits: CapacityUnit) -> Self {
+        self.scheduler.partition_states[self.index]
+            .reserved
+            .store(permits, atomic::Ordering::Release);
+        self
+    }
+
+    /// Cap the rate of acquisitions across all partitions of this limiter to `per_second`, e.g. the
+    /// absolute rate limit of a backend. Partitions still divide up the concurrency limit.
+    ///
+    /// Each acquisition uses up a token from the global rate cap, allowing bursts of up to one
+    /// second's worth. If none is available, [try_acquire()](Limiter::try_acquire()) rejects, while
+    /// waiting acquisitions wait for one. Acquisitions which fail, e.g. for lack of concurrency,
+    /// don't use up a token.
+    ///
+    /// The cap is shared by all partitions of the limiter, so this only needs to be called on one
+    /// of them. If called more than once, the last call wins.
+    pub fn with_global_rate(self, per_second: f64) -> Self {
+        assert!(
+            per_second.is_finite() && per_second > 0.,
+            "global rate must be finite and > 0"
+        );
+        *self
+            .scheduler
+            .global_rate
+            .lock()
+            .expect("lock should not be poisoned") = Some(GlobalRate::new(per_second));
+        self
+    }
+
+    /// This partition's current share of the total limit.
+    pub fn partition_limit(&self) -> CapacityUnit {
+        self.scheduler
+            .partition_limit(self.index, self.limiter.limit())
+    }
+
+    /// This partition's fraction of the total limit, plus the spare capacity it could currently
+    /// borrow from other partitions as a fraction of the total limit.
+    ///
+    /// Shows how much of the limit this partition can actually use right now, which can differ
+    /// from its nominal weight.
+    pub fn effective_fraction(&self) -> f64 {
+        let total_limit = self.limiter.limit();
+        let spare = self.scheduler.spare_for(self.index, total_limit);
+        self.scheduler.partition_states[self.index].fraction() + spare as f64 / total_limit as f64
+    }
+
+    /// The number of callers currently waiting for a token, across all partitions of this limiter.
+    ///
+    /// Consistently high numbers suggest the limit is too low for the demand.
+    pub async fn waiting(&self) -> usize {
+        self.scheduler
+            .waiters
+            .read()
+            .await
+            .iter()
+            // Waiters which have timed out are removed lazily.
+            .filter(|waiter| !waiter.sender.is_closed())
+            .count()
+    }
+
+    /// The number of jobs in flight in this partition.
+    pub fn in_flight(&self) -> CapacityUnit {
+        self.scheduler.partition_states[self.index].in_flight()
     }
 }
 
-fn fractional_limit(limit: CapacityUnit, fraction: f64) -> CapacityUnit {
+fn fractional_limit(limit: CapacityUnit, fraction: f64, round: fn(f64) -> f64) -> CapacityUnit {
     let limit_f64 = limit as f64 * fraction;
 
-    limit_f64
-        .ceil()
+    round(limit_f64)
         .approx()
         .expect("should be clamped within usize bounds")
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use tokio::time::{self, Instant};
+
+    use crate::{
+        limiter::{DefaultLimiter, Limiter, Outcome, PartitionedLimiter, RoundingMode},
+        limits::Fixed,
+    };
+
+    async fn yield_many() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn release_returns_total_limit() {
+        let partitions = DefaultLimiter::new(Fixed::new(10)).create_static_partitions(vec![1., 1.]);
+        let partition = &partitions[0];
+
+        assert_eq!(
+            partition.partition_limit(),
+            5,
+            "50% share of the total limit"
+        );
+
+        let token = partition.try_acquire().await.unwrap();
+        let limit = partition.release(token, Some(Outcome::Success)).await;
+
+        assert_eq!(limit, 10, "release returns the total limit");
+        assert_eq!(partition.partition_limit(), 5);
+    }
+
+    #[tokio::test]
+    async fn wakes_highest_priority_waiter() {
+        let partitions =
+            Arc::new(DefaultLimiter::new(Fixed::new(2)).create_static_partitions(vec![1., 1.]));
+
+        let token = partitions[0].try_acquire().await.unwrap();
+        let _other = partitions[1].try_acquire().await.unwrap();
+
+        let spawn_waiter = |index: usize, priority| {
+            let partitions = partitions.clone();
+            tokio::spawn(async move {
+                partitions[index]
+                    .acquire_timeout_priority(Duration::from_secs(10), priority)
+                    .await
+            })
+        };
+
+        let low = spawn_waiter(0, 1);
+        yield_many().await;
+        let high = spawn_waiter(1, 5);
+        yield_many().await;
+        let low_later = spawn_waiter(0, 1);
+        yield_many().await;
+
+        partitions[0].release(token, Some(Outcome::Success)).await;
+
+        let high_token = high.await.unwrap();
+        assert!(
+            high_token.is_some(),
+            "highest priority waiter should get the token"
+        );
+        assert!(!low.is_finished());
+        assert!(!low_later.is_finished());
+
+        drop(high_token);
+        let low_token = low.await.unwrap();
+        assert!(low_token.is_some(), "FIFO within the same priority");
+        assert!(!low_later.is_finished());
+    }
+
+    #[tokio::test]
+    async fn partitions_over_cloned_fixed_limit() {
+        let fixed = Fixed::new(10);
+        let partitions = DefaultLimiter::new(fixed.clone()).create_static_partitions(vec![1., 4.]);
+
+        assert_eq!(partitions[0].partition_limit(), 2);
+        assert_eq!(partitions[1].partition_limit(), 8);
+
+        let other_partitions = DefaultLimiter::new(fixed).create_static_partitions(vec![1.]);
+        assert_eq!(other_partitions[0].partition_limit(), 10);
+    }
+
+    #[tokio::test]
+    async fn transfer() {
+        let partitions = DefaultLimiter::new(Fixed::new(10)).create_static_partitions(vec![1., 1.]);
+        let (read, write) = (&partitions[0], &partitions[1]);
+
+        let token = read.try_acquire().await.unwrap();
+        assert_eq!(read.in_flight(), 1);
+        assert_eq!(write.in_flight(), 0);
+
+        let token = read.transfer(token, write).unwrap();
+        assert_eq!(read.in_flight(), 0);
+        assert_eq!(write.in_flight(), 1);
+        assert_eq!(
+            read.limiter.state().in_flight(),
+            1,
+            "total in flight is unchanged"
+        );
+
+        write.release(token, Some(Outcome::Success)).await;
+        assert_eq!(write.in_flight(), 0);
+
+        // The permit is released in the background.
+        yield_many().await;
+        assert_eq!(read.limiter.state().in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn invalid_transfers() {
+        let partitions = DefaultLimiter::new(Fixed::new(10)).create_static_partitions(vec![1., 1.]);
+        let others = DefaultLimiter::new(Fixed::new(10)).create_static_partitions(vec![1.]);
+
+        let token = partitions[0].try_acquire().await.unwrap();
+
+        let token = partitions[1]
+            .transfer(token, &partitions[0])
+            .expect_err("token from another partition");
+        let token = partitions[0]
+            .transfer(token, &others[0])
+            .expect_err("partition of another limiter");
+        assert_eq!(partitions[0].in_flight(), 1, "token unchanged");
+
+        partitions[0].release(token, None).await;
+        assert_eq!(partitions[0].in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn adaptive_partitions_follow_demand() {
+        tokio::time::pause();
+
+        let partitions = DefaultLimiter::new(Fixed::new(10)).create_adaptive_partitions(
+            vec![(1., 0.1..=0.9), (1., 0.1..=0.9)],
+            Duration::from_secs(1),
+        );
+        let (idle, busy) = (&partitions[0], &partitions[1]);
+        assert_eq!(busy.partition_limit(), 5);
+
+        let mut tokens = vec![];
+        for _ in 0..5 {
+            while let Some(token) = busy.try_acquire().await {
+                tokens.push(token);
+            }
+            tokio::time::advance(Duration::from_secs(1)).await;
+        }
+
+        assert!(
+            busy.partition_limit() > 5,
+            "busy partition's share should grow. Limit: {}",
+            busy.partition_limit()
+        );
+        assert!(idle.partition_limit() >= 1, "bounded by minimum fraction");
+    }
+
+    #[test]
+    #[should_panic(expected = "Must provide at least one weight")]
+    fn no_weights() {
+        DefaultLimiter::new(Fixed::new(10)).create_static_partitions(vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "partition weights must not be negative")]
+    fn negative_weight() {
+        DefaultLimiter::new(Fixed::new(10)).create_static_partitions(vec![1., -1.]);
+    }
+
+    #[test]
+    #[should_panic(expected = "partition weights must be finite")]
+    fn nan_weight() {
+        DefaultLimiter::new(Fixed::new(10)).create_static_partitions(vec![1., f64::NAN]);
+    }
+
+    #[test]
+    #[should_panic(expected = "partition weights must be finite")]
+    fn infinite_weight() {
+        DefaultLimiter::new(Fixed::new(10)).create_static_partitions(vec![1., f64::INFINITY]);
+    }
+
+    #[test]
+    #[should_panic(expected = "partition weights must not all be zero")]
+    fn all_zero_weights() {
+        DefaultLimiter::new(Fixed::new(10)).create_static_partitions(vec![0., 0.]);
+    }
+
+    #[tokio::test]
+    async fn acquire_least_loaded() {
+        let partitions =
+            DefaultLimiter::new(Fixed::new(30)).create_static_partitions(vec![1., 1., 1.]);
+
+        let mut tokens = vec![];
+        for (partition, load) in partitions.iter().zip([5, 2, 8]) {
+            for _ in 0..load {
+                tokens.push(partition.try_acquire().await.unwrap());
+            }
+        }
+
+        let refs: Vec<_> = partitions.iter().collect();
+        let (index, _token) = PartitionedLimiter::acquire_least_loaded(&refs, Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(partitions[1].in_flight(), 3);
+    }
+
+    #[tokio::test]
+    async fn acquire_least_loaded_all_saturated() {
+        let partitions =
+            DefaultLimiter::new(Fixed::new(6)).create_static_partitions(vec![1., 1., 1.]);
+
+        let mut tokens = vec![];
+        for (partition, load) in partitions.iter().zip([3, 1, 2]) {
+            for _ in 0..load {
+                tokens.push(partition.try_acquire().await.unwrap());
+            }
+        }
+
+        time::pause();
+        let start = Instant::now();
+
+        let refs: Vec<_> = partitions.iter().collect();
+        assert!(
+            PartitionedLimiter::acquire_least_loaded(&refs, Duration::from_millis(100))
+                .await
+                .is_none()
+        );
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed >= Duration::from_millis(100) && elapsed < Duration::from_millis(200),
+            "delayed once, not per partition: {elapsed:?}"
+        );
+
+        let rejected: Vec<_> = partitions[0]
+            .scheduler
+            .partition_states
+            .iter()
+            .map(|state| state.rejected.load(std::sync::atomic::Ordering::SeqCst))
+            .collect();
+        assert_eq!(rejected, [0, 1, 0], "recorded once, for the least loaded");
+        assert_eq!(partitions[0].limiter.rejected(), 1);
+    }
+
+    #[test]
+    fn rounding_modes() {
+        for (rounding, expected) in [
+            (RoundingMode::Ceil, vec![4, 4, 4]),
+            (RoundingMode::Floor, vec![3, 3, 3]),
+            (RoundingMode::Round, vec![3, 3, 3]),
+            (RoundingMode::LargestRemainder, vec![4, 3, 3]),
+        ] {
+            let partitions = DefaultLimiter::new(Fixed::new(10))
+                .create_static_partitions_with_rounding(vec![1., 1., 1.], rounding);
+
+            let limits: Vec<_> = partitions.iter().map(|p| p.partition_limit()).collect();
+            assert_eq!(limits, expected, "{rounding:?}");
+        }
+    }
+
     #[test]
-    fn todo() {
-        // TODO: write some tests
+    fn rounding_doesnt_starve_partitions() {
+        for (rounding, expected) in [
+            (RoundingMode::Floor, vec![1, 1, 0]),
+            (RoundingMode::Round, vec![1, 1, 0]),
+            (RoundingMode::LargestRemainder, vec![1, 0, 0]),
+        ] {
+            let partitions = DefaultLimiter::new(Fixed::new(1))
+                .create_static_partitions_with_rounding(vec![1., 1., 0.], rounding);
+
+            let limits: Vec<_> = partitions.iter().map(|p| p.partition_limit()).collect();
+            assert_eq!(limits, expected, "{rounding:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn reserved_capacity_is_not_lent() {
+        async fn acquire_all(partition: &PartitionedLimiter<Fixed>) -> Vec<crate::limiter::Token> {
+            let mut tokens = vec![];
+            while let Some(token) = partition.try_acquire().await {
+                tokens.push(token);
+            }
+            tokens
+        }
+
+        let mut partitions =
+            DefaultLimiter::new(Fixed::new(10)).create_static_partitions(vec![1., 1.]);
+        let low = partitions.pop().unwrap();
+        let protected = partitions.pop().unwrap().with_reserved(3);
+
+        let low_tokens = acquire_all(&low).await;
+        assert_eq!(low_tokens.len(), 6, "own share plus unreserved spare");
+
+        let protected_tokens = acquire_all(&protected).await;
+        assert_eq!(
+            protected_tokens.len(),
+            4,
+            "reserved capacity still available"
+        );
+    }
+
+    #[tokio::test]
+    async fn self_shed_signal() {
+        use crate::limits::Aimd;
+
+        tokio::time::pause();
+
+        async fn limit_after_timeout(self_shed_signal: bool) -> usize {
+            let mut partitions = DefaultLimiter::new(Aimd::new_with_initial_limit(10))
+                .create_static_partitions(vec![1., 1.]);
+            let other = partitions.pop().unwrap();
+            let partition = partitions
+                .pop()
+                .unwrap()
+                .with_self_shed_signal(self_shed_signal);
+
+            // A fast job.
+            let mut token = partition.try_acquire().await.unwrap();
+            token.set_latency(Duration::from_millis(1));
+            partition.release(token, Some(Outcome::Success)).await;
+            yield_many().await;
+
+            let mut tokens = vec![];
+            for _ in 0..5 {
+                tokens.push(partition.try_acquire().await.unwrap());
+                tokens.push(other.try_acquire().await.unwrap());
+            }
+
+            assert!(partition
+                .acquire_timeout(Duration::from_millis(100))
+                .await
+                .is_none());
+
+            partition.limiter.limit()
+        }
+
+        assert_eq!(limit_after_timeout(false).await, 10, "no signal");
+        assert!(
+            limit_after_timeout(true).await > 10,
+            "self-shed signal: increase"
+        );
+    }
+
+    #[tokio::test]
+    async fn effective_fraction() {
+        let partitions = DefaultLimiter::new(Fixed::new(10)).create_static_partitions(vec![1., 1.]);
+
+        let _token = partitions[0].try_acquire().await.unwrap();
+
+        assert_eq!(
+            partitions[0].effective_fraction(),
+            0.9,
+            "nominal 0.5, plus 0.4 spare from the idle partition"
+        );
+        assert_eq!(
+            partitions[1].effective_fraction(),
+            0.8,
+            "nominal 0.5, plus 0.3 spare from the busy partition"
+        );
+    }
+
+    #[tokio::test]
+    async fn cloned_partition_shares_state() {
+        let partitions = DefaultLimiter::new(Fixed::new(10)).create_static_partitions(vec![1., 1.]);
+        let partition = partitions[0].clone();
+
+        let token_a = partitions[0].try_acquire().await.unwrap();
+        let token_b = partition.try_acquire().await.unwrap();
+
+        assert_eq!(partitions[0].in_flight(), 2);
+        assert_eq!(partition.in_flight(), 2);
+        assert_eq!(partitions[1].in_flight(), 0);
+
+        partitions[0].release(token_b, Some(Outcome::Success)).await;
+        partition.release(token_a, Some(Outcome::Success)).await;
+        yield_many().await;
+
+        assert_eq!(partition.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn acquire_deadline_fair() {
+        time::pause();
+
+        let partitions = DefaultLimiter::new(Fixed::new(10)).create_static_partitions(vec![1., 1.]);
+        let (a, b) = (partitions[0].clone(), partitions[1].clone());
+
+        // Partition A borrows all spare capacity.
+        let mut a_tokens = vec![];
+        while let Some(token) = a.try_acquire().await {
+            a_tokens.push(token);
+        }
+        assert_eq!(a_tokens.len(), 9);
+        let _b_token = b.try_acquire().await.unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(1);
+        let mut waiters = vec![];
+        for partition in [&a, &a, &b, &b] {
+            let partition = partition.clone();
+            waiters.push(tokio::spawn(async move {
+                partition.acquire_deadline_fair(deadline).await
+            }));
+            yield_many().await;
+        }
+
+        for token in a_tokens.drain(..2) {
+            a.release(token, Some(Outcome::Success)).await;
+            yield_many().await;
+        }
+
+        let mut tokens = vec![];
+        for waiter in waiters {
+            tokens.push(waiter.await.unwrap());
+        }
+        assert_eq!(
+            tokens.iter().map(Option::is_some).collect::<Vec<_>>(),
+            [false, false, true, true],
+            "freed tokens go to the less subscribed partition, despite arriving later"
+        );
+        assert_eq!(b.in_flight(), 3);
+    }
+
+    #[tokio::test]
+    async fn global_rate() {
+        time::pause();
+
+        let mut partitions =
+            DefaultLimiter::new(Fixed::new(100)).create_static_partitions(vec![1., 1.]);
+        let a = partitions.remove(0).with_global_rate(10.);
+        let b = partitions.remove(0);
+
+        let mut acquired = 0;
+        for _ in 0..100 {
+            for partition in [&a, &b] {
+                for _ in 0..5 {
+                    if let Some(token) = partition.try_acquire().await {
+                        acquired += 1;
+                        partition.release(token, Some(Outcome::Success)).await;
+                    }
+                }
+            }
+            time::advance(Duration::from_millis(100)).await;
+        }
+
+        // An initial burst of 10, then 10 per second for 10 seconds.
+        assert!(
+            (100..=110).contains(&acquired),
+            "combined rate is capped, got {acquired}"
+        );
+    }
+
+    #[tokio::test]
+    async fn global_rate_rejections_are_refunded() {
+        time::pause();
+
+        let partition = DefaultLimiter::new(Fixed::new(1))
+            .create_static_partitions(vec![1.])
+            .remove(0)
+            .with_global_rate(10.);
+
+        let token = partition.try_acquire().await.unwrap();
+        for _ in 0..20 {
+            assert!(partition.try_acquire().await.is_none(), "no concurrency");
+        }
+        assert!(partition
+            .acquire_timeout(Duration::from_millis(10))
+            .await
+            .is_none());
+        partition.release(token, None).await;
+        yield_many().await;
+
+        let mut acquired = 0;
+        while let Some(token) = partition.try_acquire().await {
+            acquired += 1;
+            partition.release(token, None).await;
+            yield_many().await;
+        }
+        assert_eq!(acquired, 9, "the rest of the burst is still available");
+    }
+
+    #[tokio::test]
+    async fn slo_partitions_throttle_breaching_partition() {
+        tokio::time::pause();
+
+        let partitions = DefaultLimiter::new(Fixed::new(10)).create_slo_partitions(
+            vec![(1., 0.1..=0.9), (1., 0.1..=0.9)],
+            Duration::from_millis(100),
+            Duration::from_secs(1),
+        );
+        let (slow, fast) = (&partitions[0], &partitions[1]);
+        assert_eq!(slow.partition_limit(), 5);
+
+        for _ in 0..5 {
+            for (partition, latency) in [(slow, 200), (fast, 10)] {
+                let mut token = partition.try_acquire().await.unwrap();
+                token.set_latency(Duration::from_millis(latency));
+                partition.release(token, Some(Outcome::Success)).await;
+            }
+            yield_many().await;
+            tokio::time::advance(Duration::from_secs(1)).await;
+        }
+        fast.try_acquire().await;
+
+        assert!(
+            slow.partition_limit() < 5,
+            "breaching partition's share should shrink. Limit: {}",
+            slow.partition_limit()
+        );
+        assert!(
+            fast.partition_limit() > 5,
+            "compliant partition's share should grow. Limit: {}",
+            fast.partition_limit()
+        );
+        assert!(slow.partition_limit() >= 1, "bounded by minimum fraction");
+
+        for _ in 0..20 {
+            for partition in [slow, fast] {
+                let mut token = partition.try_acquire().await.unwrap();
+                token.set_latency(Duration::from_millis(10));
+                partition.release(token, Some(Outcome::Success)).await;
+            }
+            yield_many().await;
+            tokio::time::advance(Duration::from_secs(1)).await;
+        }
+        fast.try_acquire().await;
+
+        assert_eq!(
+            slow.partition_limit(),
+            5,
+            "recovered partition's share should be restored"
+        );
+    }
+
+    #[tokio::test]
+    async fn waiting() {
+        tokio::time::pause();
+
+        let partitions = DefaultLimiter::new(Fixed::new(2)).create_static_partitions(vec![1., 1.]);
+        let _tokens = [
+            partitions[0].try_acquire().await.unwrap(),
+            partitions[1].try_acquire().await.unwrap(),
+        ];
+        assert_eq!(partitions[0].waiting().await, 0);
+
+        let waiter = tokio::spawn({
+            let partition = partitions[0].clone();
+            async move { partition.acquire_timeout(Duration::from_secs(1)).await }
+        });
+        yield_many().await;
+        assert_eq!(partitions[0].waiting().await, 1);
+        assert_eq!(
+            partitions[1].waiting().await,
+            1,
+            "shared between partitions"
+        );
+
+        assert!(waiter.await.unwrap().is_none());
+        assert_eq!(partitions[0].waiting().await, 0, "timed out");
     }
 }
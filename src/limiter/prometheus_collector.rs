@@ -0,0 +1,175 @@
+use std::sync::{atomic::Ordering, Arc, Mutex, Weak};
+
+use prometheus::{
+    core::{Collector, Desc},
+    proto::MetricFamily,
+    IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+};
+
+use crate::limits::LimitAlgorithm;
+
+use super::DefaultLimiter;
+
+impl<T> DefaultLimiter<T>
+where
+    T: LimitAlgorithm + Send + Sync + 'static,
+{
+    /// Register metrics for this limiter with a Prometheus `registry`, read from the limiter each
+    /// time the registry is gathered.
+    ///
+    /// All metric names are prefixed with `name`. Includes gauges for the limit, jobs in flight and
+    /// available concurrency, and counters for acquired and rejected tokens, including rejections
+    /// by [label](super::Limiter::try_acquire_labeled()).
+    ///
+    /// The registry doesn't keep the limiter alive. Once it has been dropped, no metrics are
+    /// reported.
+    pub fn register(self: &Arc<Self>, registry: &Registry, name: &str) -> prometheus::Result<()> {
+        registry.register(Box::new(LimiterCollector::new(Arc::downgrade(self), name)?))
+    }
+}
+
+/// Collects metrics from a [DefaultLimiter] at gather time.
+struct LimiterCollector<T> {
+    limiter: Weak<DefaultLimiter<T>>,
+
+    limit: IntGauge,
+    in_flight: IntGauge,
+    available: IntGauge,
+    acquired: IntCounter,
+    rejected: IntCounter,
+    rejected_by_label: IntCounterVec,
+
+    /// Serialises collection, since values are reset and set on each collection.
+    collecting: Mutex<()>,
+}
+
+impl<T> LimiterCollector<T> {
+    fn new(limiter: Weak<DefaultLimiter<T>>, name: &str) -> prometheus::Result<Self> {
+        Ok(Self {
+            limiter,
+
+            limit: IntGauge::new(format!("{name}_limit"), "The current concurrency limit.")?,
+            in_flight: IntGauge::new(format!("{name}_in_flight"), "The number of jobs in flight.")?,
+            available: IntGauge::new(
+                format!("{name}_available"),
+                "The amount of concurrency available to use.",
+            )?,
+            acquired: IntCounter::new(
+                format!("{name}_acquired_total"),
+                "The total number of tokens acquired.",
+            )?,
+            rejected: IntCounter::new(
+                format!("{name}_rejected_total"),
+                "The total number of acquisitions rejected.",
+            )?,
+            rejected_by_label: IntCounterVec::new(
+                Opts::new(
+                    format!("{name}_rejected_by_label_total"),
+                    "The number of labeled acquisitions rejected.",
+                ),
+                &["label"],
+            )?,
+
+            collecting: Mutex::new(()),
+        })
+    }
+}
+
+fn gauge_value(value: usize) -> i64 {
+    i64::try_from(value).unwrap_or(i64::MAX)
+}
+
+fn counter_value(value: usize) -> u64 {
+    u64::try_from(value).unwrap_or(u64::MAX)
+}
+
+impl<T> Collector for LimiterCollector<T>
+where
+    T: LimitAlgorithm + Send + Sync + 'static,
+{
+    fn desc(&self) -> Vec<&Desc> {
+        [
+            self.limit.desc(),
+            self.in_flight.desc(),
+            self.available.desc(),
+            self.acquired.desc(),
+            self.rejected.desc(),
+            self.rejected_by_label.desc(),
+        ]
+        .concat()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let Some(limiter) = self.limiter.upgrade() else {
+            return Vec::new();
+        };
+        let _collecting = self.collecting.lock().expect("lock should not be poisoned");
+
+        let state = limiter.state();
+        self.limit.set(gauge_value(state.limit()));
+        self.in_flight.set(gauge_value(state.in_flight()));
+        self.available.set(gauge_value(state.available()));
+
+        self.acquired.reset();
+        self.acquired
+            .inc_by(counter_value(limiter.acquired.load(Ordering::Relaxed)));
+        self.rejected.reset();
+        self.rejected.inc_by(counter_value(limiter.rejected()));
+
+        self.rejected_by_label.reset();
+        for (label, value) in limiter.rejections_by_label() {
+            self.rejected_by_label
+                .with_label_values(&[label])
+                .inc_by(counter_value(value));
+        }
+
+        [
+            self.limit.collect(),
+            self.in_flight.collect(),
+            self.available.collect(),
+            self.acquired.collect(),
+            self.rejected.collect(),
+            self.rejected_by_label.collect(),
+        ]
+        .concat()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use prometheus::{Registry, TextEncoder};
+
+    use crate::{
+        limiter::{DefaultLimiter, Limiter},
+        limits::Fixed,
+    };
+
+    #[tokio::test]
+    async fn gathers_registered_metrics() {
+        let limiter = Arc::new(DefaultLimiter::new(Fixed::new(1)));
+        let registry = Registry::new();
+        limiter.register(&registry, "api").unwrap();
+
+        let _token = limiter.try_acquire().await.unwrap();
+        assert!(limiter.try_acquire_labeled("search").await.is_none());
+
+        let metrics = TextEncoder::new()
+            .encode_to_string(&registry.gather())
+            .unwrap();
+        for sample in [
+            "api_limit 1\n",
+            "api_in_flight 1\n",
+            "api_available 0\n",
+            "api_acquired_total 1\n",
+            "api_rejected_total 1\n",
+            "api_rejected_by_label_total{label=\"search\"} 1\n",
+        ] {
+            assert!(metrics.contains(sample), "missing {sample}:\n{metrics}");
+        }
+
+        drop(limiter);
+        assert!(registry.gather().is_empty(), "limiter dropped");
+    }
+}
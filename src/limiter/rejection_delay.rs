@@ -1,8 +1,12 @@
-use std::time::Duration;
+use std::{fmt::Debug, time::Duration};
 
 use async_trait::async_trait;
+use tokio::{
+    sync::watch,
+    time::{sleep_until, timeout, Instant},
+};
 
-use super::{Limiter, Outcome, Token};
+use super::{Limiter, LimiterState, Outcome, Token};
 
 /// A wrapper which adds rejection delay.
 ///
@@ -13,18 +17,77 @@ use super::{Limiter, Outcome, Token};
 /// appropriate backoff.
 #[derive(Debug)]
 pub struct RejectionDelay {
-    delay: Duration,
+    delay: Delay,
+    shutdown: Option<watch::Receiver<bool>>,
     inner: Box<dyn Limiter>,
 }
 
+/// A function computing the rejection delay from the state of the wrapped limiter.
+pub type DelayFn = Box<dyn Fn(&LimiterState) -> Duration + Send + Sync>;
+
+enum Delay {
+    Fixed(Duration),
+    Fn(DelayFn),
+}
+
 impl RejectionDelay {
     #[allow(missing_docs)]
     pub fn new(delay: Duration, limiter: impl Limiter + 'static) -> Self {
         Self {
-            delay,
+            delay: Delay::Fixed(delay),
+            shutdown: None,
             inner: Box::new(limiter),
         }
     }
+
+    /// Cut rejection delays short when `shutdown` is set to `true`.
+    ///
+    /// Any pending rejection delays will end immediately, and no further delays will be added. This
+    /// prevents rejected callers from blocking a clean shutdown.
+    pub fn with_shutdown_signal(mut self, shutdown: watch::Receiver<bool>) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    /// Compute the delay at rejection time from the current state of the wrapped limiter, instead
+    /// of using a fixed delay.
+    ///
+    /// Allows arbitrary policies, e.g. scaling the delay with the number of jobs in flight. No
+    /// delay is added if the wrapped limiter doesn't expose its state. See
+    /// [Limiter::limiter_state()].
+    pub fn with_rejection_delay_fn(mut self, delay: DelayFn) -> Self {
+        self.delay = Delay::Fn(delay);
+        self
+    }
+
+    fn delay(&self) -> Duration {
+        match &self.delay {
+            Delay::Fixed(delay) => *delay,
+            Delay::Fn(delay) => self
+                .inner
+                .limiter_state()
+                .map_or(Duration::ZERO, |state| delay(&state)),
+        }
+    }
+
+    async fn on_rejection(&self) {
+        let delay = self.delay();
+
+        let Some(shutdown) = &self.shutdown else {
+            tokio::time::sleep(delay).await;
+            return;
+        };
+
+        let start = Instant::now();
+        let mut shutdown = shutdown.clone();
+        let signalled = timeout(delay, shutdown.wait_for(|&shutdown| shutdown))
+            .await
+            .map(|result| result.is_ok());
+        if let Ok(false) = signalled {
+            // The sender has been dropped, so we'll never be signalled.
+            sleep_until(start + delay).await;
+        }
+    }
 }
 
 #[async_trait]
@@ -33,7 +96,17 @@ impl Limiter for RejectionDelay {
         let token = self.inner.try_acquire().await;
 
         if token.is_none() {
-            tokio::time::sleep(self.delay).await;
+            self.on_rejection().await;
+        }
+
+        token
+    }
+
+    async fn try_acquire_labeled(&self, label: &'static str) -> Option<Token> {
+        let token = self.inner.try_acquire_labeled(label).await;
+
+        if token.is_none() {
+            self.on_rejection().await;
         }
 
         token
@@ -43,7 +116,7 @@ impl Limiter for RejectionDelay {
         let token = self.inner.acquire_timeout(duration).await;
 
         if token.is_none() {
-            tokio::time::sleep(self.delay).await;
+            self.on_rejection().await;
         }
 
         token
@@ -52,13 +125,29 @@ impl Limiter for RejectionDelay {
     async fn release(&self, token: Token, outcome: Option<Outcome>) -> usize {
         self.inner.release(token, outcome).await
     }
+
+    fn limiter_state(&self) -> Option<LimiterState> {
+        self.inner.limiter_state()
+    }
+}
+
+impl Debug for Delay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fixed(delay) => f.debug_tuple("Fixed").field(delay).finish(),
+            Self::Fn(_) => f.write_str("Fn"),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::time::Duration;
 
-    use tokio::time::{self, Instant};
+    use tokio::{
+        sync::watch,
+        time::{self, Instant},
+    };
 
     use crate::assert_elapsed;
     use crate::{
@@ -100,6 +189,60 @@ mod tests {
         assert_elapsed!(before_acquire, delay * 2, Duration::from_millis(10));
     }
 
+    #[tokio::test]
+    async fn shutdown_cancels_rejection_delay() {
+        time::pause();
+
+        let delay = Duration::from_secs(60);
+        let (shutdown, shutdown_rx) = watch::channel(false);
+
+        let limiter = RejectionDelay::new(delay, DefaultLimiter::new(Fixed::new(1)))
+            .with_shutdown_signal(shutdown_rx);
+
+        let _token = limiter.try_acquire().await.unwrap();
+
+        let before_acquire = Instant::now();
+        let (rejected, _) = tokio::join!(limiter.try_acquire(), async {
+            time::sleep(Duration::from_secs(1)).await;
+            shutdown.send(true).unwrap();
+        });
+
+        assert!(rejected.is_none());
+        assert_elapsed!(
+            before_acquire,
+            Duration::from_secs(1),
+            Duration::from_millis(10)
+        );
+
+        let before_acquire = Instant::now();
+        assert!(limiter.try_acquire().await.is_none());
+        assert_elapsed!(before_acquire, Duration::ZERO, Duration::from_millis(10));
+    }
+
+    #[tokio::test]
+    async fn rejection_delay_fn() {
+        time::pause();
+
+        let limiter = RejectionDelay::new(Duration::ZERO, DefaultLimiter::new(Fixed::new(3)))
+            .with_rejection_delay_fn(Box::new(|state| {
+                Duration::from_secs(state.in_flight() as u64)
+            }));
+
+        let _tokens = [
+            limiter.try_acquire().await.unwrap(),
+            limiter.try_acquire().await.unwrap(),
+            limiter.try_acquire().await.unwrap(),
+        ];
+
+        let before_acquire = Instant::now();
+        assert!(limiter.try_acquire().await.is_none());
+        assert_elapsed!(
+            before_acquire,
+            Duration::from_secs(3),
+            Duration::from_millis(10)
+        );
+    }
+
     /// Assert that a given duration has elapsed since `start`, within the given tolerance.
     #[macro_export]
     #[cfg(test)]
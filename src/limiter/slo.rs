@@ -0,0 +1,40 @@
+use std::{collections::VecDeque, time::Duration};
+
+use tokio::sync::Mutex;
+
+/// Tracks the fraction of recent jobs completing within a latency SLO.
+#[derive(Debug)]
+pub(crate) struct Slo {
+    threshold: Duration,
+    /// Whether each recent job met the SLO, newest last.
+    recent: Mutex<VecDeque<bool>>,
+}
+
+impl Slo {
+    /// The number of recent jobs to consider.
+    const WINDOW_SIZE: usize = 1000;
+
+    pub(crate) fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            recent: Mutex::new(VecDeque::with_capacity(Self::WINDOW_SIZE)),
+        }
+    }
+
+    pub(crate) async fn record(&self, latency: Duration) {
+        let mut recent = self.recent.lock().await;
+        if recent.len() == Self::WINDOW_SIZE {
+            recent.pop_front();
+        }
+        recent.push_back(latency <= self.threshold);
+    }
+
+    pub(crate) async fn compliance(&self) -> f64 {
+        let recent = self.recent.lock().await;
+        if recent.is_empty() {
+            return 1.;
+        }
+        let met = recent.iter().filter(|&&met| met).count();
+        met as f64 / recent.len() as f64
+    }
+}
@@ -0,0 +1,165 @@
+use std::{ops::RangeInclusive, time::Duration};
+
+use async_trait::async_trait;
+use tokio::{sync::Mutex, time::Instant};
+
+use super::{CapacityUnit, Limiter, LimiterState, Outcome, Token};
+
+/// A wrapper which adds a self-tuning rejection delay.
+///
+/// Like [RejectionDelay](super::RejectionDelay), but the delay adapts to retry pressure. If a
+/// rejection arrives sooner after the previous rejected caller was released than the current delay,
+/// retries are assumed to be too eager, and the delay is doubled. Otherwise retries are assumed to
+/// be well-spaced, and the delay is reduced.
+///
+/// The limiter can't identify callers, so this is approximated using all rejections.
+#[derive(Debug)]
+pub struct SmartRejectionDelay {
+    bounds: RangeInclusive<Duration>,
+    state: Mutex<State>,
+    inner: Box<dyn Limiter>,
+}
+
+#[derive(Debug)]
+struct State {
+    delay: Duration,
+    /// When the most recently delayed caller was released.
+    last_released: Option<Instant>,
+}
+
+impl SmartRejectionDelay {
+    const INCREASE_FACTOR: f64 = 2.;
+    const DECREASE_FACTOR: f64 = 0.8;
+
+    /// Keep the rejection delay within `bounds`, starting at the minimum.
+    pub fn new(bounds: RangeInclusive<Duration>, limiter: impl Limiter + 'static) -> Self {
+        assert!(
+            *bounds.start() > Duration::ZERO,
+            "minimum delay must be > 0"
+        );
+        assert!(
+            bounds.start() <= bounds.end(),
+            "minimum delay must not be greater than maximum"
+        );
+        Self {
+            state: Mutex::new(State {
+                delay: *bounds.start(),
+                last_released: None,
+            }),
+            bounds,
+            inner: Box::new(limiter),
+        }
+    }
+
+    /// The delay which will be applied to the next rejection.
+    pub async fn current_delay(&self) -> Duration {
+        self.state.lock().await.delay
+    }
+
+    async fn on_rejection(&self) {
+        let delay = {
+            let mut state = self.state.lock().await;
+
+            let factor = match state.last_released {
+                Some(released) if released.elapsed() < state.delay => Self::INCREASE_FACTOR,
+                Some(_) => Self::DECREASE_FACTOR,
+                None => 1.,
+            };
+            state.delay = state
+                .delay
+                .mul_f64(factor)
+                .clamp(*self.bounds.start(), *self.bounds.end());
+
+            state.delay
+        };
+
+        tokio::time::sleep(delay).await;
+
+        self.state.lock().await.last_released = Some(Instant::now());
+    }
+}
+
+#[async_trait]
+impl Limiter for SmartRejectionDelay {
+    async fn try_acquire(&self) -> Option<Token> {
+        let token = self.inner.try_acquire().await;
+
+        if token.is_none() {
+            self.on_rejection().await;
+        }
+
+        token
+    }
+
+    async fn try_acquire_labeled(&self, label: &'static str) -> Option<Token> {
+        let token = self.inner.try_acquire_labeled(label).await;
+
+        if token.is_none() {
+            self.on_rejection().await;
+        }
+
+        token
+    }
+
+    async fn acquire_timeout(&self, duration: Duration) -> Option<Token> {
+        let token = self.inner.acquire_timeout(duration).await;
+
+        if token.is_none() {
+            self.on_rejection().await;
+        }
+
+        token
+    }
+
+    async fn release(&self, token: Token, outcome: Option<Outcome>) -> CapacityUnit {
+        self.inner.release(token, outcome).await
+    }
+
+    fn limiter_state(&self) -> Option<LimiterState> {
+        self.inner.limiter_state()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time;
+
+    use crate::{
+        limiter::{DefaultLimiter, Limiter, SmartRejectionDelay},
+        limits::Fixed,
+    };
+
+    #[tokio::test]
+    async fn adapts_to_retry_spacing() {
+        time::pause();
+
+        let limiter = SmartRejectionDelay::new(
+            Duration::from_millis(10)..=Duration::from_secs(10),
+            DefaultLimiter::new(Fixed::new(1)),
+        );
+        let _token = limiter.try_acquire().await.unwrap();
+
+        // Tight retry loop.
+        for _ in 0..5 {
+            assert!(limiter.try_acquire().await.is_none());
+        }
+        let tight = limiter.current_delay().await;
+        assert!(
+            tight > Duration::from_millis(10),
+            "immediate retries: increase delay, got {tight:?}"
+        );
+
+        // Well-spaced retries.
+        for _ in 0..5 {
+            time::sleep(Duration::from_secs(60)).await;
+            assert!(limiter.try_acquire().await.is_none());
+        }
+        let spaced = limiter.current_delay().await;
+        assert!(
+            spaced < tight,
+            "spaced retries: decrease delay. Tight: {tight:?}, spaced: {spaced:?}"
+        );
+    }
+}
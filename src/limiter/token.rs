@@ -1,14 +1,16 @@
 use std::{
     sync::{
         atomic::{self, AtomicUsize},
-        Arc,
+        Arc, Mutex,
     },
     time::Duration,
 };
 
-use tokio::{sync::OwnedSemaphorePermit, time::Instant};
+use tokio::{sync::OwnedSemaphorePermit, task::AbortHandle, time::Instant};
 
-use super::partitioning::Scheduler;
+use crate::limits::LimitAlgorithm;
+
+use super::{fallback::Source, partitioning::Scheduler, DefaultLimiter, Outcome};
 
 /// A concurrency token, required to run a job.
 ///
@@ -17,15 +19,38 @@ use super::partitioning::Scheduler;
 pub struct Token {
     inner: Option<TokenInner>,
     partition: Option<Partition>,
+    /// Which limiter served this token, for each enclosing [Fallback](super::Fallback).
+    ///
+    /// A stack of bits, innermost first, above a leading sentinel bit.
+    sources: u64,
+    /// If set, `inner` is held here instead, so it can be released when the job times out.
+    job_timeout: Option<JobTimeout>,
 
     start: Instant,
     #[cfg(test)]
     latency: Duration,
 }
 
+#[derive(Debug)]
+struct JobTimeout {
+    inner: Arc<Mutex<Option<TokenInner>>>,
+    reaper: Option<AbortHandle>,
+}
+
+/// A handle to a [Token]'s permit, which can release it before the token is dropped, e.g. when a
+/// job times out.
+#[derive(Debug)]
+pub(crate) struct SharedPermit {
+    inner: Arc<Mutex<Option<TokenInner>>>,
+    start: Instant,
+    #[cfg(test)]
+    latency: Duration,
+}
+
 #[derive(Debug)]
 pub(crate) struct TokenInner {
-    _permit: OwnedSemaphorePermit,
+    /// Absent for tokens issued beyond the limit, e.g. in shadow mode.
+    _permit: Option<OwnedSemaphorePermit>,
     in_flight: Arc<AtomicUsize>,
 }
 
@@ -36,7 +61,44 @@ pub(crate) struct Partition {
 }
 
 impl Token {
+    /// Wrap a permit from an externally managed [Semaphore](tokio::sync::Semaphore), so the job
+    /// counts as in flight for `limiter` and can be [released](crate::limiter::Limiter::release())
+    /// through it as normal.
+    ///
+    /// The permit is returned to its own semaphore when the token is released. Since it doesn't
+    /// come from `limiter`, it doesn't count towards `limiter`'s limit, and isn't affected by
+    /// changes to the limit. It is counted as an acquisition in `limiter`'s stats.
+    pub fn from_permit<T: LimitAlgorithm>(
+        permit: OwnedSemaphorePermit,
+        limiter: &DefaultLimiter<T>,
+    ) -> Self {
+        limiter.mint_token(permit)
+    }
+
+    /// Report an intermediate outcome for this job to `limiter` without releasing the token. See
+    /// [DefaultLimiter::report()].
+    pub async fn report<T: LimitAlgorithm + Sync>(
+        &self,
+        limiter: &DefaultLimiter<T>,
+        outcome: Outcome,
+        latency: Duration,
+    ) -> usize {
+        limiter.report(self, outcome, latency).await
+    }
+
     pub(crate) fn new(permit: OwnedSemaphorePermit, in_flight: Arc<AtomicUsize>) -> Self {
+        Self::new_with_optional_permit(Some(permit), in_flight)
+    }
+
+    /// A token which doesn't hold a permit, and so doesn't count towards the limit.
+    pub(crate) fn new_overflow(in_flight: Arc<AtomicUsize>) -> Self {
+        Self::new_with_optional_permit(None, in_flight)
+    }
+
+    fn new_with_optional_permit(
+        permit: Option<OwnedSemaphorePermit>,
+        in_flight: Arc<AtomicUsize>,
+    ) -> Self {
         in_flight.fetch_add(1, atomic::Ordering::SeqCst);
         Self {
             inner: Some(TokenInner {
@@ -44,6 +106,8 @@ impl Token {
                 in_flight,
             }),
             partition: None,
+            sources: 1,
+            job_timeout: None,
             start: Instant::now(),
             #[cfg(test)]
             latency: Duration::ZERO,
@@ -54,18 +118,110 @@ impl Token {
         Self {
             inner: Some(inner),
             partition: None,
+            sources: 1,
+            job_timeout: None,
             start: Instant::now(),
             #[cfg(test)]
             latency: Duration::ZERO,
         }
     }
 
+    /// Share this token's permit, so it can be released early. Set the task which will do so with
+    /// [set_reaper()](Self::set_reaper()).
+    pub(crate) fn share_permit(&mut self) -> SharedPermit {
+        assert!(
+            self.partition.is_none(),
+            "partitioned tokens can't be shared"
+        );
+        let inner = Arc::new(Mutex::new(self.inner.take()));
+        self.job_timeout = Some(JobTimeout {
+            inner: inner.clone(),
+            reaper: None,
+        });
+        SharedPermit {
+            inner,
+            start: self.start,
+            #[cfg(test)]
+            latency: self.latency,
+        }
+    }
+
+    /// Set the task which will release this token's [shared permit](Self::share_permit()). It is
+    /// aborted when the token is dropped.
+    pub(crate) fn set_reaper(&mut self, reaper: AbortHandle) {
+        self.job_timeout
+            .as_mut()
+            .expect("permit should be shared")
+            .reaper = Some(reaper);
+    }
+
+    /// Whether this token's [shared permit](Self::share_permit()) has already been released.
+    pub(crate) fn is_expired(&self) -> bool {
+        self.job_timeout.as_ref().is_some_and(|job_timeout| {
+            job_timeout
+                .inner
+                .lock()
+                .expect("lock should not be poisoned")
+                .is_none()
+        })
+    }
+
     pub(crate) fn for_partition(mut self, partition: Partition) -> Self {
         partition.in_flight.fetch_add(1, atomic::Ordering::SeqCst);
         self.partition = Some(partition);
         self
     }
 
+    pub(crate) fn push_source(&mut self, source: Source) {
+        assert!(
+            self.sources.leading_zeros() > 0,
+            "too many nested fallback limiters"
+        );
+        self.sources = (self.sources << 1) | u64::from(source == Source::Secondary);
+    }
+
+    pub(crate) fn pop_source(&mut self) -> Option<Source> {
+        if self.sources == 1 {
+            return None;
+        }
+        let source = if self.sources & 1 == 1 {
+            Source::Secondary
+        } else {
+            Source::Primary
+        };
+        self.sources >>= 1;
+        Some(source)
+    }
+
+    /// Move this token's accounting to a different partition.
+    ///
+    /// Panics if the token isn't assigned to a partition sharing the same scheduler.
+    pub(crate) fn transfer_partition(&mut self, to: Partition) {
+        let from = self
+            .partition
+            .as_ref()
+            .expect("token should be assigned to a partition");
+        assert!(
+            Arc::ptr_eq(&from.scheduler, &to.scheduler),
+            "partitions must belong to the same limiter"
+        );
+
+        from.in_flight.fetch_sub(1, atomic::Ordering::SeqCst);
+        to.in_flight.fetch_add(1, atomic::Ordering::SeqCst);
+        self.partition = Some(to);
+    }
+
+    pub(crate) fn is_for_partition(&self, in_flight: &Arc<AtomicUsize>) -> bool {
+        self.partition
+            .as_ref()
+            .is_some_and(|p| Arc::ptr_eq(&p.in_flight, in_flight))
+    }
+
+    /// When this token was acquired.
+    pub(crate) fn started_at(&self) -> Instant {
+        self.start
+    }
+
     #[cfg(test)]
     pub(crate) fn set_latency(&mut self, latency: Duration) {
         use std::ops::Sub;
@@ -90,6 +246,18 @@ impl Token {
 impl Drop for Token {
     /// Reduces the number of jobs in flight and releases the token back to the available pool.
     fn drop(&mut self) {
+        if let Some(job_timeout) = self.job_timeout.take() {
+            if let Some(reaper) = job_timeout.reaper {
+                reaper.abort();
+            }
+            drop(
+                job_timeout
+                    .inner
+                    .lock()
+                    .expect("lock should not be poisoned")
+                    .take(),
+            );
+        }
         if let Some(partition) = self.partition.take() {
             partition.in_flight.fetch_sub(1, atomic::Ordering::SeqCst);
             partition.scheduler.reuse_permit(
@@ -107,6 +275,25 @@ impl Drop for TokenInner {
     }
 }
 
+impl SharedPermit {
+    /// Take the permit, as a token which started at the same time as the original. Returns `None`
+    /// if the original token has already been dropped.
+    pub(crate) fn take(&self) -> Option<Token> {
+        let inner = self
+            .inner
+            .lock()
+            .expect("lock should not be poisoned")
+            .take()?;
+        let mut token = Token::new_from_inner(inner);
+        token.start = self.start;
+        #[cfg(test)]
+        {
+            token.latency = self.latency;
+        }
+        Some(token)
+    }
+}
+
 impl Partition {
     pub(crate) fn new(in_flight: Arc<AtomicUsize>, scheduler: Arc<Scheduler>) -> Self {
         Self {
@@ -115,3 +302,35 @@ impl Partition {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::Semaphore;
+
+    use crate::{
+        limiter::{DefaultLimiter, Limiter, Outcome, Token},
+        limits::Fixed,
+    };
+
+    #[tokio::test]
+    async fn from_external_permit() {
+        let limiter = DefaultLimiter::new(Fixed::new(1));
+        let semaphore = Arc::new(Semaphore::new(1));
+
+        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        let token = Token::from_permit(permit, &limiter);
+        assert_eq!(limiter.state().in_flight(), 1);
+        assert_eq!(limiter.take_stats().acquired(), 1, "acquisition recorded");
+        assert_eq!(
+            limiter.state().available(),
+            1,
+            "doesn't count towards the limit"
+        );
+
+        limiter.release(token, Some(Outcome::Success)).await;
+        assert_eq!(limiter.state().in_flight(), 0);
+        assert_eq!(semaphore.available_permits(), 1, "permit returned");
+    }
+}
@@ -0,0 +1,298 @@
+use std::{
+    ops::RangeInclusive,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+
+use crate::{
+    limits::{defaults, Sample},
+    moving_avg,
+};
+
+use super::{aimd::multiplicative_decrease, defaults::MIN_SAMPLE_LATENCY, LimitAlgorithm};
+
+/// Combines latency and loss signals, each tracked with its own moving average.
+///
+/// - Latency: each sample is compared against a long window average. A sample slower than the
+///   average by more than the latency tolerance indicates congestion, and the limit is reduced in
+///   proportion to how much slower it was (by at most half).
+/// - Loss: the rate of overloads is smoothed exponentially. An overload while this rate is above
+///   the loss threshold indicates congestion, and the limit is reduced by the loss decrease
+///   factor.
+///
+/// If either signal indicates congestion the limit is decreased, by whichever decrease is larger.
+/// Otherwise, the limit is increased by one while utilisation is high.
+#[derive(Debug)]
+pub struct Adaptive {
+    min_limit: usize,
+    max_limit: usize,
+
+    latency_tolerance: f64,
+    loss_threshold: f64,
+    loss_decrease_factor: f64,
+
+    limit: AtomicUsize,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    long_window_latency: moving_avg::ExpSmoothed,
+    loss_rate: f64,
+}
+
+impl Adaptive {
+    const DEFAULT_LATENCY_TOLERANCE: f64 = 2.;
+    const DEFAULT_LOSS_THRESHOLD: f64 = 0.05;
+    const DEFAULT_LOSS_DECREASE_FACTOR: f64 = 0.5;
+
+    const LONG_WINDOW_SAMPLES: u16 = 100;
+    const LOSS_SMOOTHING: f64 = 0.1;
+    const MIN_LATENCY_DECREASE_FACTOR: f64 = 0.5;
+
+    const INCREASE_MIN_UTILISATION: f64 = 0.8;
+
+    #[allow(missing_docs)]
+    pub fn new_with_initial_limit(initial_limit: usize) -> Self {
+        Self::new(
+            initial_limit,
+            defaults::DEFAULT_MIN_LIMIT..=defaults::DEFAULT_MAX_LIMIT,
+        )
+    }
+
+    /// Create with an initial limit, which will be kept within `limit_range`.
+    ///
+    /// If the minimum and maximum are equal, the limit is fixed and updates do nothing.
+    pub fn new(initial_limit: usize, limit_range: RangeInclusive<usize>) -> Self {
+        assert!(*limit_range.start() >= 1, "Limits must be at least 1");
+        assert!(
+            limit_range.start() <= limit_range.end(),
+            "Minimum limit must not be greater than maximum"
+        );
+        assert!(
+            initial_limit >= *limit_range.start(),
+            "Initial limit less than minimum"
+        );
+        assert!(
+            initial_limit <= *limit_range.end(),
+            "Initial limit more than maximum"
+        );
+
+        Self {
+            min_limit: *limit_range.start(),
+            max_limit: *limit_range.end(),
+
+            latency_tolerance: Self::DEFAULT_LATENCY_TOLERANCE,
+            loss_threshold: Self::DEFAULT_LOSS_THRESHOLD,
+            loss_decrease_factor: Self::DEFAULT_LOSS_DECREASE_FACTOR,
+
+            limit: AtomicUsize::new(initial_limit),
+            inner: Mutex::new(Inner {
+                long_window_latency: moving_avg::ExpSmoothed::new_with_window_size(
+                    Self::LONG_WINDOW_SAMPLES,
+                ),
+                loss_rate: 0.,
+            }),
+        }
+    }
+
+    /// How many times the long window latency a sample can take before it indicates congestion.
+    ///
+    /// Defaults to 2.
+    pub fn with_latency_tolerance(self, tolerance: f64) -> Self {
+        assert!(tolerance >= 1.0, "tolerance must be >= 1");
+        Self {
+            latency_tolerance: tolerance,
+            ..self
+        }
+    }
+
+    /// The smoothed overload rate above which overloads indicate congestion.
+    ///
+    /// Defaults to 0.05, so a single overload after a run of successes is enough.
+    pub fn with_loss_threshold(self, threshold: f64) -> Self {
+        assert!(
+            (0.0..1.0).contains(&threshold),
+            "threshold must be in [0, 1)"
+        );
+        Self {
+            loss_threshold: threshold,
+            ..self
+        }
+    }
+
+    /// The factor to multiply the limit by when loss indicates congestion. Defaults to 0.5.
+    pub fn with_loss_decrease_factor(self, factor: f64) -> Self {
+        assert!(
+            factor > 0. && factor < 1.,
+            "decrease factor must be in (0, 1)"
+        );
+        Self {
+            loss_decrease_factor: factor,
+            ..self
+        }
+    }
+}
+
+#[async_trait]
+impl LimitAlgorithm for Adaptive {
+    fn limit(&self) -> usize {
+        self.limit.load(Ordering::Acquire)
+    }
+
+    fn min_limit(&self) -> usize {
+        self.min_limit
+    }
+
+    fn max_limit(&self) -> usize {
+        self.max_limit
+    }
+
+    /// The long window average latency.
+    async fn baseline_latency(&self) -> Option<Duration> {
+        let inner = self.inner.lock().await;
+        let latency = inner.long_window_latency.value();
+        (latency > Duration::ZERO).then_some(latency)
+    }
+
+    async fn update(&self, sample: Sample) -> usize {
+        if self.min_limit == self.max_limit {
+            return self.max_limit;
+        }
+
+        let mut inner = self.inner.lock().await;
+
+        let overload = sample.outcome.is_overload();
+        let loss = if overload { 1. } else { 0. };
+        inner.loss_rate += (loss - inner.loss_rate) * Self::LOSS_SMOOTHING;
+
+        // Latencies this low carry no information, so only the loss signal is used.
+        let latency_factor = if sample.latency < MIN_SAMPLE_LATENCY {
+            1.0
+        } else {
+            let long = inner.long_window_latency.sample(sample.latency);
+            let ratio = long.as_secs_f64() / sample.latency.as_secs_f64();
+            (self.latency_tolerance * ratio).clamp(Self::MIN_LATENCY_DECREASE_FACTOR, 1.0)
+        };
+
+        let loss_factor = if overload && inner.loss_rate > self.loss_threshold {
+            self.loss_decrease_factor
+        } else {
+            1.0
+        };
+
+        let limit = self.limit.load(Ordering::Acquire);
+
+        let factor = latency_factor.min(loss_factor);
+        let new_limit = if factor < 1.0 {
+            multiplicative_decrease(limit, factor)
+        } else {
+            let utilisation = sample.in_flight as f64 / limit as f64;
+            if utilisation > Self::INCREASE_MIN_UTILISATION {
+                limit + 1
+            } else {
+                limit
+            }
+        }
+        .clamp(self.min_limit, self.max_limit);
+
+        self.limit.store(new_limit, Ordering::Release);
+
+        new_limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::limiter::Outcome;
+
+    use super::*;
+
+    async fn warm_up(adaptive: &Adaptive) {
+        for _ in 0..20 {
+            adaptive
+                .update(Sample {
+                    latency: Duration::from_millis(10),
+                    in_flight: 1,
+                    outcome: Outcome::Success,
+                })
+                .await;
+        }
+        assert_eq!(adaptive.limit(), 10, "low utilisation: no change");
+    }
+
+    #[tokio::test]
+    async fn latency_congestion() {
+        let adaptive = Adaptive::new_with_initial_limit(10);
+        warm_up(&adaptive).await;
+
+        // Within tolerance
+        adaptive
+            .update(Sample {
+                latency: Duration::from_millis(15),
+                in_flight: 1,
+                outcome: Outcome::Success,
+            })
+            .await;
+        assert_eq!(adaptive.limit(), 10);
+
+        // 10x slower: limited to halving
+        adaptive
+            .update(Sample {
+                latency: Duration::from_millis(100),
+                in_flight: 1,
+                outcome: Outcome::Success,
+            })
+            .await;
+        assert_eq!(adaptive.limit(), 5);
+    }
+
+    #[tokio::test]
+    async fn loss_congestion() {
+        let adaptive = Adaptive::new_with_initial_limit(10)
+            .with_loss_threshold(0.15)
+            .with_loss_decrease_factor(0.8);
+        warm_up(&adaptive).await;
+
+        let overload = Sample {
+            latency: Duration::from_millis(10),
+            in_flight: 1,
+            outcome: Outcome::overload(),
+        };
+
+        adaptive.update(overload.clone()).await;
+        assert_eq!(adaptive.limit(), 10, "loss rate below threshold");
+
+        adaptive.update(overload).await;
+        assert_eq!(adaptive.limit(), 8, "loss rate above threshold");
+    }
+
+    #[tokio::test]
+    async fn combined_congestion_uses_larger_decrease() {
+        let adaptive = Adaptive::new_with_initial_limit(10).with_loss_decrease_factor(0.8);
+        warm_up(&adaptive).await;
+
+        // Loss is worse than mildly increased latency.
+        adaptive
+            .update(Sample {
+                latency: Duration::from_millis(24),
+                in_flight: 1,
+                outcome: Outcome::overload(),
+            })
+            .await;
+        assert_eq!(adaptive.limit(), 8);
+
+        // Much increased latency is worse than loss.
+        adaptive
+            .update(Sample {
+                latency: Duration::from_millis(100),
+                in_flight: 1,
+                outcome: Outcome::overload(),
+            })
+            .await;
+        assert_eq!(adaptive.limit(), 4);
+    }
+}
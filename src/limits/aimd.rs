@@ -1,14 +1,19 @@
 use std::{
     ops::RangeInclusive,
-    sync::atomic::{AtomicUsize, Ordering},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Duration,
 };
 
 use async_trait::async_trait;
 use conv::ConvAsUtil;
+use tokio::time::Instant;
 
 use crate::{limiter::Outcome, limits::Sample};
 
-use super::{defaults, LimitAlgorithm};
+use super::{confirmations::DecreaseConfirmations, defaults, LimitAlgorithm};
 
 /// Loss-based overload avoidance.
 ///
@@ -25,15 +30,43 @@ pub struct Aimd {
     max_limit: usize,
     decrease_factor: f64,
     increase_by: usize,
+    /// If set, increase by this fraction of the headroom below the maximum limit, if more than
+    /// `increase_by`.
+    headroom_increase: Option<f64>,
     min_utilisation_threshold: f64,
+    /// Once increasing, the limit will continue to be increased until utilisation drops below this.
+    stop_utilisation_threshold: f64,
+    /// Whether low utilisation prevents increases.
+    utilisation_gate: bool,
+
+    decrease_confirmations: DecreaseConfirmations,
+
+    /// Further increases are suppressed for this long after an increase.
+    increase_cooldown: Option<Duration>,
+    last_increase: Mutex<Option<Instant>>,
+
+    /// Fraction of the gap back to the pre-decrease limit to close on each success while
+    /// recovering.
+    recovery_boost: Option<f64>,
+    recovery: Mutex<Option<Recovery>>,
 
     limit: AtomicUsize,
+    increasing: AtomicBool,
+}
+
+/// Progress towards the limit from before a decrease streak.
+#[derive(Debug, Clone, Copy)]
+struct Recovery {
+    target: usize,
+    /// Boosted increases left.
+    remaining: usize,
 }
 
 impl Aimd {
     const DEFAULT_DECREASE_FACTOR: f64 = 0.9;
     const DEFAULT_INCREASE: usize = 1;
     const DEFAULT_INCREASE_MIN_UTILISATION: f64 = 0.8;
+    const RECOVERY_BOOSTED_INCREASES: usize = 5;
 
     #[allow(missing_docs)]
     pub fn new_with_initial_limit(initial_limit: usize) -> Self {
@@ -43,9 +76,15 @@ impl Aimd {
         )
     }
 
-    #[allow(missing_docs)]
+    /// Create with an initial limit, which will be kept within `limit_range`.
+    ///
+    /// If the minimum and maximum are equal, the limit is fixed and updates do nothing.
     pub fn new(initial_limit: usize, limit_range: RangeInclusive<usize>) -> Self {
         assert!(*limit_range.start() >= 1, "Limits must be at least 1");
+        assert!(
+            limit_range.start() <= limit_range.end(),
+            "Minimum limit must not be greater than maximum"
+        );
         assert!(
             initial_limit >= *limit_range.start(),
             "Initial limit less than minimum"
@@ -60,9 +99,20 @@ impl Aimd {
             max_limit: *limit_range.end(),
             decrease_factor: Self::DEFAULT_DECREASE_FACTOR,
             increase_by: Self::DEFAULT_INCREASE,
+            headroom_increase: None,
             min_utilisation_threshold: Self::DEFAULT_INCREASE_MIN_UTILISATION,
+            stop_utilisation_threshold: Self::DEFAULT_INCREASE_MIN_UTILISATION,
+            utilisation_gate: true,
+            decrease_confirmations: DecreaseConfirmations::new(1),
+
+            increase_cooldown: None,
+            last_increase: Mutex::new(None),
+
+            recovery_boost: None,
+            recovery: Mutex::new(None),
 
             limit: AtomicUsize::new(initial_limit),
+            increasing: AtomicBool::new(false),
         }
     }
 
@@ -84,9 +134,30 @@ impl Aimd {
         }
     }
 
+    /// Scale increases with the headroom below the maximum limit: increase by
+    /// `ceil((max_limit - limit) * factor)`, or the [constant increase](Self::increase_by()) if
+    /// that's larger.
+    ///
+    /// Approaches a high maximum quickly, then fine-tunes as it gets closer. Less aggressive than a
+    /// multiplicative increase.
+    pub fn with_headroom_increase(self, factor: f64) -> Self {
+        assert!(
+            factor > 0. && factor <= 1.,
+            "headroom factor must be in (0, 1]"
+        );
+        Self {
+            headroom_increase: Some(factor),
+            ..self
+        }
+    }
+
     #[allow(missing_docs)]
     pub fn with_max_limit(self, max: usize) -> Self {
         assert!(max > 0);
+        assert!(
+            max >= self.min_limit,
+            "Maximum limit must not be less than minimum"
+        );
         Self {
             max_limit: max,
             ..self
@@ -94,52 +165,262 @@ impl Aimd {
     }
 
     /// A threshold below which the limit won't be increased. 0.5 = 50%.
+    ///
+    /// Inclusive: the limit will be increased when utilisation is exactly at the threshold.
     pub fn with_min_utilisation_threshold(self, min_util: f64) -> Self {
         assert!(min_util > 0. && min_util < 1.);
         Self {
             min_utilisation_threshold: min_util,
+            stop_utilisation_threshold: min_util,
             ..self
         }
     }
-}
 
-#[async_trait]
-impl LimitAlgorithm for Aimd {
-    fn limit(&self) -> usize {
-        self.limit.load(Ordering::Acquire)
+    /// Start increasing the limit when utilisation reaches `high`, and continue increasing until
+    /// it drops below `low`.
+    ///
+    /// Prevents flapping between increasing and not increasing when utilisation fluctuates around
+    /// a single threshold.
+    pub fn with_utilisation_hysteresis(self, low: f64, high: f64) -> Self {
+        assert!(
+            low > 0. && high < 1.,
+            "utilisation thresholds must be > 0 and < 1"
+        );
+        assert!(
+            low <= high,
+            "low threshold must not be above high threshold"
+        );
+        Self {
+            min_utilisation_threshold: high,
+            stop_utilisation_threshold: low,
+            ..self
+        }
     }
 
-    async fn update(&self, sample: Sample) -> usize {
+    /// Only increase the limit when utilisation is above the threshold. Enabled by default.
+    ///
+    /// Disabling this allows increases on any qualifying success, which can help when several
+    /// limiters share a backend, e.g. partitions, and each sees low utilisation individually. The
+    /// limit can then grow far beyond what is actually used, and overshoot badly when load arrives.
+    pub fn with_utilisation_gate(self, enabled: bool) -> Self {
+        Self {
+            utilisation_gate: enabled,
+            ..self
+        }
+    }
+
+    /// Require `confirmations` consecutive samples indicating overload before decreasing the
+    /// limit.
+    ///
+    /// A sample without overload resets the count. Defaults to 1, i.e. decreasing immediately.
+    pub fn with_decrease_confirmations(self, confirmations: usize) -> Self {
+        Self {
+            decrease_confirmations: DecreaseConfirmations::new(confirmations),
+            ..self
+        }
+    }
+
+    /// After increasing the limit, don't increase it again for `cooldown`. Decreases are still
+    /// applied.
+    ///
+    /// The effect of a higher limit, e.g. increased latency or errors, can take time to show.
+    /// Waiting for it reduces overshoot.
+    pub fn with_increase_cooldown(self, cooldown: Duration) -> Self {
+        Self {
+            increase_cooldown: Some(cooldown),
+            ..self
+        }
+    }
+
+    /// After a streak of decreases, spring back towards the limit from before the streak.
+    ///
+    /// On the first few increases after the streak, the limit is increased by `boost` times the
+    /// gap to the pre-decrease limit, instead of the usual additive increase. E.g. with 0.5, each
+    /// increase closes half the remaining gap. Never increases above the pre-decrease limit.
+    ///
+    /// Useful for recovering quickly from transient incidents.
+    pub fn with_recovery_boost(self, boost: f64) -> Self {
+        assert!(boost > 0. && boost <= 1., "boost must be in (0, 1]");
+        Self {
+            recovery_boost: Some(boost),
+            ..self
+        }
+    }
+
+    /// The increase to apply from `limit`, taking any headroom scaling and recovery into account.
+    fn increase_from(&self, limit: usize, recovery: Option<Recovery>) -> usize {
+        let increase = match self.headroom_increase {
+            Some(factor) => {
+                let headroom = self.max_limit.saturating_sub(limit);
+                let scaled = (headroom as f64 * factor)
+                    .ceil()
+                    .approx()
+                    .unwrap_or(headroom);
+                scaled.max(self.increase_by)
+            }
+            None => self.increase_by,
+        };
+        match (self.recovery_boost, recovery) {
+            (Some(boost), Some(recovery)) if recovery.target > limit => {
+                let gap = recovery.target - limit;
+                let boosted = (gap as f64 * boost).ceil().approx().unwrap_or(gap);
+                boosted.max(increase).min(gap)
+            }
+            _ => increase,
+        }
+    }
+
+    fn in_increase_cooldown(&self) -> bool {
+        let Some(cooldown) = self.increase_cooldown else {
+            return false;
+        };
+        self.last_increase
+            .lock()
+            .expect("lock should not be poisoned")
+            .is_some_and(|last| last.elapsed() < cooldown)
+    }
+
+    fn update_now(&self, sample: Sample) -> usize {
+        if self.min_limit == self.max_limit {
+            return self.max_limit;
+        }
+
         use Outcome::*;
         match sample.outcome {
             Success => {
-                self.limit
+                self.decrease_confirmations.confirm(false);
+
+                let threshold = if self.increasing.load(Ordering::Acquire) {
+                    self.stop_utilisation_threshold
+                } else {
+                    self.min_utilisation_threshold
+                };
+                let cooling_down = self.in_increase_cooldown();
+                let mut recovery = self.recovery.lock().expect("lock should not be poisoned");
+
+                let old_limit = self
+                    .limit
                     .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |limit| {
                         let utilisation = sample.in_flight as f64 / limit as f64;
 
-                        if utilisation > self.min_utilisation_threshold {
-                            let limit = limit + self.increase_by;
+                        let increase = !self.utilisation_gate || utilisation >= threshold;
+                        self.increasing.store(increase, Ordering::Release);
+
+                        if increase && !cooling_down {
+                            let limit = limit + self.increase_from(limit, *recovery);
                             Some(limit.clamp(self.min_limit, self.max_limit))
                         } else {
                             Some(limit)
                         }
                     })
                     .expect("we always return Some(limit)");
+                let new_limit = self.limit.load(Ordering::SeqCst);
+
+                if new_limit > old_limit {
+                    if let Some(r) = recovery.as_mut() {
+                        r.remaining -= 1;
+                        if r.remaining == 0 || new_limit >= r.target {
+                            *recovery = None;
+                        }
+                    }
+                }
+                drop(recovery);
+
+                if self.increase_cooldown.is_some() && new_limit > old_limit {
+                    self.last_increase
+                        .lock()
+                        .expect("lock should not be poisoned")
+                        .replace(Instant::now());
+                }
             }
-            Overload => {
-                self.limit
+            Overload(_) => {
+                self.increasing.store(false, Ordering::Release);
+
+                if !self.decrease_confirmations.confirm(true) {
+                    return self.limit.load(Ordering::SeqCst);
+                }
+
+                let old_limit = self
+                    .limit
                     .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |limit| {
                         let limit = multiplicative_decrease(limit, self.decrease_factor);
 
                         Some(limit.clamp(self.min_limit, self.max_limit))
                     })
                     .expect("we always return Some(limit)");
+
+                if self.recovery_boost.is_some() {
+                    let mut recovery = self.recovery.lock().expect("lock should not be poisoned");
+                    let target = match *recovery {
+                        // Still in the same decrease streak.
+                        Some(r) if r.remaining == Self::RECOVERY_BOOSTED_INCREASES => r.target,
+                        _ => old_limit,
+                    };
+                    *recovery = Some(Recovery {
+                        target,
+                        remaining: Self::RECOVERY_BOOSTED_INCREASES,
+                    });
+                }
             }
         }
         self.limit.load(Ordering::SeqCst)
     }
 }
 
+/// Clones the current limit, as well as the configuration.
+impl Clone for Aimd {
+    fn clone(&self) -> Self {
+        Self {
+            min_limit: self.min_limit,
+            max_limit: self.max_limit,
+            decrease_factor: self.decrease_factor,
+            increase_by: self.increase_by,
+            headroom_increase: self.headroom_increase,
+            min_utilisation_threshold: self.min_utilisation_threshold,
+            stop_utilisation_threshold: self.stop_utilisation_threshold,
+            utilisation_gate: self.utilisation_gate,
+            decrease_confirmations: self.decrease_confirmations.clone(),
+
+            increase_cooldown: self.increase_cooldown,
+            last_increase: Mutex::new(self.last_increase.try_lock().ok().and_then(|last| *last)),
+
+            recovery_boost: self.recovery_boost,
+            recovery: Mutex::new(self.recovery.try_lock().ok().and_then(|r| *r)),
+
+            limit: AtomicUsize::new(self.limit.load(Ordering::Acquire)),
+            increasing: AtomicBool::new(self.increasing.load(Ordering::Acquire)),
+        }
+    }
+}
+
+#[async_trait]
+impl LimitAlgorithm for Aimd {
+    fn limit(&self) -> usize {
+        self.limit.load(Ordering::Acquire)
+    }
+
+    fn min_limit(&self) -> usize {
+        self.min_limit
+    }
+
+    fn max_limit(&self) -> usize {
+        self.max_limit
+    }
+
+    async fn update(&self, sample: Sample) -> usize {
+        self.update_now(sample)
+    }
+
+    fn try_update_sync(&self, sample: Sample) -> Result<usize, Sample> {
+        Ok(self.update_now(sample))
+    }
+
+    /// Treated as a success at full utilisation.
+    async fn on_acquire_timeout(&self, sample: Sample) -> usize {
+        self.update(sample).await
+    }
+}
+
 pub(super) fn multiplicative_decrease(limit: usize, decrease_factor: f64) -> usize {
     assert!(decrease_factor <= 1.0, "should not increase the limit");
 
@@ -152,7 +433,7 @@ pub(super) fn multiplicative_decrease(limit: usize, decrease_factor: f64) -> usi
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
+    use std::{sync::Arc, time::Duration};
 
     use tokio::sync::Notify;
 
@@ -171,11 +452,65 @@ mod tests {
         let limiter = DefaultLimiter::new(aimd).with_release_notifier(release_notifier.clone());
 
         let token = limiter.try_acquire().await.unwrap();
-        limiter.release(token, Some(Outcome::Overload)).await;
+        limiter.release(token, Some(Outcome::overload())).await;
         release_notifier.notified().await;
         assert_eq!(limiter.limit(), 5, "overload: decrease");
     }
 
+    #[tokio::test]
+    async fn decrease_confirmations() {
+        let aimd = Aimd::new_with_initial_limit(10).with_decrease_confirmations(3);
+
+        let success = Sample {
+            latency: Duration::from_millis(10),
+            in_flight: 1,
+            outcome: Outcome::Success,
+        };
+        let overload = Sample {
+            outcome: Outcome::overload(),
+            ..success.clone()
+        };
+
+        assert_eq!(aimd.update(overload.clone()).await, 10, "single overload");
+        assert_eq!(aimd.update(overload.clone()).await, 10);
+        aimd.update(success).await;
+
+        assert_eq!(aimd.update(overload.clone()).await, 10);
+        assert_eq!(aimd.update(overload.clone()).await, 10);
+        assert!(
+            aimd.update(overload).await < 10,
+            "sustained overload => decrease limit"
+        );
+    }
+
+    #[tokio::test]
+    async fn increase_cooldown() {
+        tokio::time::pause();
+
+        let aimd = Aimd::new_with_initial_limit(10).with_increase_cooldown(Duration::from_secs(1));
+        let success = |in_flight| Sample {
+            latency: Duration::from_millis(10),
+            in_flight,
+            outcome: Outcome::Success,
+        };
+
+        assert_eq!(aimd.update(success(10)).await, 11, "increase");
+        assert_eq!(
+            aimd.update(success(11)).await,
+            11,
+            "cooling down: no increase"
+        );
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(aimd.update(success(11)).await, 12, "cooled down: increase");
+
+        let overload = Sample {
+            outcome: Outcome::overload(),
+            ..success(12)
+        };
+        assert!(aimd.update(overload).await < 12, "cooling down: decrease");
+    }
+
     #[tokio::test]
     async fn should_increase_limit_on_success_when_using_gt_util_threshold() {
         let aimd = Aimd::new_with_initial_limit(4)
@@ -208,6 +543,22 @@ mod tests {
         assert_eq!(limiter.limit(), 4, "success: ignore when < half limit");
     }
 
+    #[tokio::test]
+    async fn should_increase_limit_on_success_at_exact_util_threshold() {
+        let aimd = Aimd::new_with_initial_limit(4)
+            .increase_by(1)
+            .with_min_utilisation_threshold(0.5);
+
+        let limit = aimd
+            .update(Sample {
+                latency: Duration::from_millis(10),
+                in_flight: 2,
+                outcome: Outcome::Success,
+            })
+            .await;
+        assert_eq!(limit, 5, "threshold is inclusive");
+    }
+
     #[tokio::test]
     async fn should_not_change_limit_when_no_outcome() {
         let aimd = Aimd::new_with_initial_limit(10)
@@ -220,4 +571,172 @@ mod tests {
         limiter.release(token, None).await;
         assert_eq!(limiter.limit(), 10, "ignore");
     }
+
+    #[tokio::test]
+    async fn should_not_flap_with_hysteresis() {
+        let aimd = Aimd::new_with_initial_limit(10)
+            .increase_by(1)
+            .with_utilisation_hysteresis(0.5, 0.8);
+
+        let sample = |in_flight| Sample {
+            latency: Duration::from_millis(10),
+            in_flight,
+            outcome: Outcome::Success,
+        };
+
+        assert_eq!(
+            aimd.update(sample(9)).await,
+            11,
+            "above high: start increasing"
+        );
+        assert_eq!(
+            aimd.update(sample(7)).await,
+            12,
+            "above low: keep increasing"
+        );
+        assert_eq!(
+            aimd.update(sample(5)).await,
+            12,
+            "below low: stop increasing"
+        );
+        assert_eq!(
+            aimd.update(sample(7)).await,
+            12,
+            "below high: don't start increasing"
+        );
+    }
+
+    #[tokio::test]
+    async fn clone_copies_current_limit() {
+        let aimd = Aimd::new_with_initial_limit(10).decrease_factor(0.5);
+        aimd.update(Sample {
+            latency: Duration::from_millis(10),
+            in_flight: 1,
+            outcome: Outcome::overload(),
+        })
+        .await;
+
+        let cloned = aimd.clone();
+        assert_eq!(cloned.limit(), 5);
+
+        cloned
+            .update(Sample {
+                latency: Duration::from_millis(10),
+                in_flight: 1,
+                outcome: Outcome::overload(),
+            })
+            .await;
+        assert_eq!(aimd.limit(), 5, "clones are independent");
+    }
+
+    #[tokio::test]
+    async fn should_report_limit_bounds() {
+        let aimd = Aimd::new(10, 5..=50);
+        assert_eq!(aimd.min_limit(), 5);
+        assert_eq!(aimd.max_limit(), 50);
+
+        let limiter = DefaultLimiter::new(aimd.with_max_limit(100));
+        assert_eq!(limiter.state().max_limit(), 100);
+    }
+
+    #[tokio::test]
+    async fn equal_bounds_fix_the_limit() {
+        let aimd = Aimd::new(5, 5..=5);
+
+        let limit = aimd
+            .update(Sample {
+                latency: Duration::from_millis(10),
+                in_flight: 5,
+                outcome: Outcome::overload(),
+            })
+            .await;
+        assert_eq!(limit, 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "Minimum limit must not be greater than maximum")]
+    fn inverted_bounds() {
+        #[allow(clippy::reversed_empty_ranges)]
+        Aimd::new(5, 10..=1);
+    }
+
+    #[tokio::test]
+    async fn recovery_boost() {
+        let success = |in_flight| Sample {
+            latency: Duration::from_millis(10),
+            in_flight,
+            outcome: Outcome::Success,
+        };
+        let overload = Sample {
+            outcome: Outcome::overload(),
+            ..success(1)
+        };
+
+        let plain = Aimd::new(100, 1..=200).decrease_factor(0.5);
+        let boosted = Aimd::new(100, 1..=200)
+            .decrease_factor(0.5)
+            .with_recovery_boost(0.5);
+
+        for aimd in [&plain, &boosted] {
+            for _ in 0..3 {
+                aimd.update(overload.clone()).await;
+            }
+            assert_eq!(aimd.limit(), 12, "decreased");
+
+            for _ in 0..5 {
+                aimd.update(success(aimd.limit())).await;
+            }
+        }
+
+        assert_eq!(plain.limit(), 17, "additive increase");
+        assert_eq!(
+            boosted.limit(),
+            98,
+            "boosted: most of the way back to the pre-decrease limit"
+        );
+
+        for _ in 0..10 {
+            boosted.update(success(boosted.limit())).await;
+        }
+        assert_eq!(boosted.limit(), 108, "boost exhausted: additive increase");
+    }
+
+    #[tokio::test]
+    async fn utilisation_gate() {
+        let low_utilisation = Sample {
+            latency: Duration::from_millis(10),
+            in_flight: 1,
+            outcome: Outcome::Success,
+        };
+
+        let gated = Aimd::new_with_initial_limit(10);
+        assert_eq!(gated.update(low_utilisation.clone()).await, 10);
+
+        let ungated = Aimd::new_with_initial_limit(10).with_utilisation_gate(false);
+        assert_eq!(ungated.update(low_utilisation).await, 11, "increase");
+    }
+
+    #[tokio::test]
+    async fn headroom_increase() {
+        async fn limit_after_increases(aimd: Aimd) -> usize {
+            for _ in 0..10 {
+                aimd.update(Sample {
+                    latency: Duration::from_millis(10),
+                    in_flight: aimd.limit(),
+                    outcome: Outcome::Success,
+                })
+                .await;
+            }
+            aimd.limit()
+        }
+
+        assert_eq!(limit_after_increases(Aimd::new(10, 1..=1000)).await, 20);
+
+        let headroom =
+            limit_after_increases(Aimd::new(10, 1..=1000).with_headroom_increase(0.1)).await;
+        assert!(
+            (600..1000).contains(&headroom),
+            "approaches the maximum quickly, got {headroom}"
+        );
+    }
 }
@@ -0,0 +1,258 @@
+use std::{ops::RangeInclusive, time::Duration};
+
+use async_trait::async_trait;
+
+use crate::aggregation::{Aggregator, Average, MovingPercentile, Percentile};
+
+use super::{Aimd, Fixed, Gradient, LimitAlgorithm, Sample, SuccessRate, Vegas, Windowed};
+
+/// Any of the built-in limit algorithms, for when the algorithm is chosen at runtime, e.g. from
+/// configuration.
+///
+/// Gives a single concrete type to use with a [DefaultLimiter](crate::limiter::DefaultLimiter).
+#[derive(Debug)]
+#[allow(missing_docs)]
+pub enum AnyLimit {
+    Fixed(Fixed),
+    Aimd(Aimd),
+    Vegas(Vegas),
+    Gradient(Gradient),
+    SuccessRate(SuccessRate),
+    Windowed(Box<Windowed<AnyLimit, AnyAggregator>>),
+}
+
+/// Any of the built-in [Aggregator]s, for use with [AnyLimit::Windowed].
+#[derive(Debug)]
+#[allow(missing_docs)]
+pub enum AnyAggregator {
+    Average(Average),
+    Percentile(Percentile),
+    MovingPercentile(MovingPercentile),
+}
+
+/// Configuration for constructing an [AnyLimit].
+#[derive(Debug, Clone)]
+#[allow(missing_docs)]
+pub enum LimitConfig {
+    Fixed {
+        limit: usize,
+    },
+    Aimd {
+        initial_limit: usize,
+        limit_range: RangeInclusive<usize>,
+    },
+    Vegas {
+        initial_limit: usize,
+        limit_range: RangeInclusive<usize>,
+    },
+    Gradient {
+        initial_limit: usize,
+        limit_range: RangeInclusive<usize>,
+    },
+    SuccessRate {
+        initial_limit: usize,
+        limit_range: RangeInclusive<usize>,
+        target: f64,
+    },
+    Windowed {
+        inner: Box<LimitConfig>,
+        aggregation: AggregationConfig,
+    },
+}
+
+/// Configuration for constructing an [AnyAggregator].
+#[derive(Debug, Clone, Copy)]
+#[allow(missing_docs)]
+pub enum AggregationConfig {
+    Average,
+    Percentile(f64),
+    MovingPercentile { percentile: f64, step: f64 },
+}
+
+impl AnyLimit {
+    /// Construct an algorithm, using defaults for anything not in the config.
+    pub fn from_config(config: LimitConfig) -> Self {
+        match config {
+            LimitConfig::Fixed { limit } => Self::Fixed(Fixed::new(limit)),
+            LimitConfig::Aimd {
+                initial_limit,
+                limit_range,
+            } => Self::Aimd(Aimd::new(initial_limit, limit_range)),
+            LimitConfig::Vegas {
+                initial_limit,
+                limit_range,
+            } => Self::Vegas(Vegas::new(initial_limit, limit_range)),
+            LimitConfig::Gradient {
+                initial_limit,
+                limit_range,
+            } => Self::Gradient(Gradient::new(initial_limit, limit_range)),
+            LimitConfig::SuccessRate {
+                initial_limit,
+                limit_range,
+                target,
+            } => Self::SuccessRate(
+                SuccessRate::new(initial_limit, limit_range).with_target_success_rate(target),
+            ),
+            LimitConfig::Windowed { inner, aggregation } => {
+                Self::Windowed(Box::new(Windowed::new(
+                    Self::from_config(*inner),
+                    AnyAggregator::from_config(aggregation),
+                )))
+            }
+        }
+    }
+}
+
+impl AnyAggregator {
+    #[allow(missing_docs)]
+    pub fn from_config(config: AggregationConfig) -> Self {
+        match config {
+            AggregationConfig::Average => Self::Average(Average::default()),
+            AggregationConfig::Percentile(percentile) => {
+                Self::Percentile(Percentile::new(percentile))
+            }
+            AggregationConfig::MovingPercentile { percentile, step } => {
+                Self::MovingPercentile(MovingPercentile::new(percentile, step))
+            }
+        }
+    }
+}
+
+macro_rules! dispatch {
+    ($self:ident, $algo:ident => $body:expr) => {
+        match $self {
+            AnyLimit::Fixed($algo) => $body,
+            AnyLimit::Aimd($algo) => $body,
+            AnyLimit::Vegas($algo) => $body,
+            AnyLimit::Gradient($algo) => $body,
+            AnyLimit::SuccessRate($algo) => $body,
+            AnyLimit::Windowed($algo) => $body,
+        }
+    };
+}
+
+#[async_trait]
+impl LimitAlgorithm for AnyLimit {
+    fn limit(&self) -> usize {
+        dispatch!(self, algo => algo.limit())
+    }
+
+    fn min_limit(&self) -> usize {
+        dispatch!(self, algo => algo.min_limit())
+    }
+
+    fn max_limit(&self) -> usize {
+        dispatch!(self, algo => algo.max_limit())
+    }
+
+    async fn update(&self, sample: Sample) -> usize {
+        dispatch!(self, algo => algo.update(sample).await)
+    }
+
+    async fn baseline_latency(&self) -> Option<Duration> {
+        dispatch!(self, algo => algo.baseline_latency().await)
+    }
+
+    async fn on_acquire_timeout(&self, sample: Sample) -> usize {
+        dispatch!(self, algo => algo.on_acquire_timeout(sample).await)
+    }
+}
+
+impl Aggregator for AnyAggregator {
+    fn sample(&mut self, sample: Sample) -> Sample {
+        match self {
+            Self::Average(agg) => agg.sample(sample),
+            Self::Percentile(agg) => agg.sample(sample),
+            Self::MovingPercentile(agg) => agg.sample(sample),
+        }
+    }
+
+    fn sample_size(&self) -> usize {
+        match self {
+            Self::Average(agg) => agg.sample_size(),
+            Self::Percentile(agg) => agg.sample_size(),
+            Self::MovingPercentile(agg) => agg.sample_size(),
+        }
+    }
+
+    fn overload_count(&self) -> usize {
+        match self {
+            Self::Average(agg) => agg.overload_count(),
+            Self::Percentile(agg) => agg.overload_count(),
+            Self::MovingPercentile(agg) => agg.overload_count(),
+        }
+    }
+
+    fn reset(&mut self) {
+        match self {
+            Self::Average(agg) => agg.reset(),
+            Self::Percentile(agg) => agg.reset(),
+            Self::MovingPercentile(agg) => agg.reset(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::limiter::Outcome;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn dispatches_to_configured_algorithm() {
+        let overload = Sample {
+            latency: Duration::from_millis(10),
+            in_flight: 10,
+            outcome: Outcome::overload(),
+        };
+
+        let configs = [
+            (LimitConfig::Fixed { limit: 10 }, 10),
+            (
+                LimitConfig::Aimd {
+                    initial_limit: 10,
+                    limit_range: 1..=100,
+                },
+                9,
+            ),
+            (
+                LimitConfig::Vegas {
+                    initial_limit: 10,
+                    limit_range: 1..=100,
+                },
+                Vegas::new(10, 1..=100).update(overload.clone()).await,
+            ),
+            (
+                LimitConfig::Gradient {
+                    initial_limit: 10,
+                    limit_range: 1..=100,
+                },
+                Gradient::new(10, 1..=100).update(overload.clone()).await,
+            ),
+            (
+                LimitConfig::SuccessRate {
+                    initial_limit: 10,
+                    limit_range: 1..=100,
+                    target: 1.0,
+                },
+                9,
+            ),
+            (
+                LimitConfig::Windowed {
+                    inner: Box::new(LimitConfig::Aimd {
+                        initial_limit: 10,
+                        limit_range: 1..=100,
+                    }),
+                    aggregation: AggregationConfig::Average,
+                },
+                10,
+            ),
+        ];
+
+        for (config, expected) in configs {
+            let algo = AnyLimit::from_config(config.clone());
+            assert_eq!(algo.limit(), 10, "{config:?}");
+            assert_eq!(algo.update(overload.clone()).await, expected, "{config:?}");
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use tokio::{
+    sync::mpsc::{
+        self,
+        error::{TryRecvError, TrySendError},
+    },
+    time::{interval_at, Instant, Interval},
+};
+
+use crate::aggregation::Aggregator;
+
+use super::{LimitAlgorithm, Sample};
+
+/// A wrapper around a [LimitAlgorithm] which buffers samples and periodically applies them in a
+/// single update, in the background.
+///
+/// Unlike [Windowed](super::Windowed), updates are driven by a timer, and samples are buffered
+/// without waiting for any locks. Useful under very high throughput, where updating the limit on
+/// every job completion is too costly.
+///
+/// Limit changes made in the background will be applied by the limiter on the next release.
+///
+/// The buffer is bounded. If it fills up, e.g. because the background task is starved, or if the
+/// background task has stopped, samples are applied to the inner algorithm directly instead.
+///
+/// Various [aggregators](crate::aggregation) are available to aggregate samples.
+#[derive(Debug)]
+pub struct Batched<L, S> {
+    interval: Duration,
+
+    inner: Arc<L>,
+
+    sender: mpsc::Sender<Sample>,
+    /// Taken when the background task is started.
+    pending: Mutex<Option<(mpsc::Receiver<Sample>, S)>>,
+    started: AtomicBool,
+}
+
+impl<L, S> Batched<L, S>
+where
+    L: LimitAlgorithm + Send + Sync + 'static,
+    S: Aggregator + Send + 'static,
+{
+    const DEFAULT_BATCH_INTERVAL: Duration = Duration::from_millis(100);
+    const DEFAULT_BUFFER_CAPACITY: usize = 4096;
+
+    #[allow(missing_docs)]
+    pub fn new(inner: L, aggregator: S) -> Self {
+        let (sender, receiver) = mpsc::channel(Self::DEFAULT_BUFFER_CAPACITY);
+        Self {
+            interval: Self::DEFAULT_BATCH_INTERVAL,
+            inner: Arc::new(inner),
+            sender,
+            pending: Mutex::new(Some((receiver, aggregator))),
+            started: AtomicBool::new(false),
+        }
+    }
+
+    /// The maximum number of samples to buffer between batches. Defaults to 4096.
+    pub fn with_buffer_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "buffer capacity must be > 0");
+        let (sender, receiver) = mpsc::channel(capacity);
+        let pending = self.pending.get_mut().expect("lock should not be poisoned");
+        let (_, aggregator) = pending
+            .take()
+            .expect("background task should not be started");
+        *pending = Some((receiver, aggregator));
+        self.sender = sender;
+        self
+    }
+
+    /// How often to apply buffered samples to the limit algorithm.
+    pub fn with_batch_interval(mut self, interval: Duration) -> Self {
+        assert!(interval > Duration::ZERO, "batch interval must be > 0");
+        self.interval = interval;
+        self
+    }
+
+    /// Start the background task on first use, so that a runtime is available.
+    fn ensure_started(&self) {
+        if self.started.load(Ordering::Acquire) {
+            return;
+        }
+        let Some((receiver, aggregator)) = self
+            .pending
+            .lock()
+            .expect("lock should not be poisoned")
+            .take()
+        else {
+            return;
+        };
+        self.started.store(true, Ordering::Release);
+
+        let interval = interval_at(Instant::now() + self.interval, self.interval);
+        tokio::spawn(apply_batches(
+            self.inner.clone(),
+            receiver,
+            aggregator,
+            interval,
+        ));
+    }
+}
+
+/// Periodically drain the buffered samples and apply them in a single update.
+///
+/// Stops when the [Batched] wrapper has been dropped.
+async fn apply_batches<L, S>(
+    inner: Arc<L>,
+    mut receiver: mpsc::Receiver<Sample>,
+    mut aggregator: S,
+    mut interval: Interval,
+) where
+    L: LimitAlgorithm,
+    S: Aggregator,
+{
+    loop {
+        interval.tick().await;
+
+        let mut batch = None;
+        let closed = loop {
+            match receiver.try_recv() {
+                Ok(sample) => batch = Some(aggregator.sample(sample)),
+                Err(TryRecvError::Empty) => break false,
+                Err(TryRecvError::Disconnected) => break true,
+            }
+        };
+
+        if let Some(sample) = batch {
+            aggregator.reset();
+            inner.update(sample).await;
+        }
+
+        if closed {
+            return;
+        }
+    }
+}
+
+#[async_trait]
+impl<L, S> LimitAlgorithm for Batched<L, S>
+where
+    L: LimitAlgorithm + Send + Sync + 'static,
+    S: Aggregator + Send + 'static,
+{
+    fn limit(&self) -> usize {
+        self.inner.limit()
+    }
+
+    fn min_limit(&self) -> usize {
+        self.inner.min_limit()
+    }
+
+    fn max_limit(&self) -> usize {
+        self.inner.max_limit()
+    }
+
+    async fn baseline_latency(&self) -> Option<Duration> {
+        self.inner.baseline_latency().await
+    }
+
+    async fn update(&self, sample: Sample) -> usize {
+        self.ensure_started();
+
+        match self.sender.try_send(sample) {
+            Ok(()) => self.inner.limit(),
+            Err(TrySendError::Full(sample) | TrySendError::Closed(sample)) => {
+                self.inner.update(sample).await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time;
+
+    use crate::{aggregation::Average, limiter::Outcome, limits::Aimd};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn applies_samples_in_batches() {
+        time::pause();
+
+        let batched = Batched::new(Aimd::new_with_initial_limit(10), Average::default())
+            .with_batch_interval(Duration::from_secs(1));
+
+        let sample = Sample {
+            in_flight: 10,
+            latency: Duration::from_millis(10),
+            outcome: Outcome::Success,
+        };
+
+        for _ in 0..20 {
+            let limit = batched.update(sample.clone()).await;
+            assert_eq!(limit, 10, "not updated on every sample");
+        }
+
+        time::sleep(Duration::from_millis(1100)).await;
+
+        assert_eq!(batched.limit(), 11, "one update per batch");
+
+        time::sleep(Duration::from_secs(1)).await;
+
+        assert_eq!(batched.limit(), 11, "no update without samples");
+    }
+
+    #[tokio::test]
+    async fn full_buffer_applies_directly() {
+        time::pause();
+
+        let batched = Batched::new(Aimd::new_with_initial_limit(10), Average::default())
+            .with_buffer_capacity(1);
+
+        let sample = Sample {
+            in_flight: 10,
+            latency: Duration::from_millis(10),
+            outcome: Outcome::Success,
+        };
+
+        assert_eq!(batched.update(sample.clone()).await, 10, "buffered");
+        assert_eq!(batched.update(sample).await, 11, "buffer full");
+    }
+}
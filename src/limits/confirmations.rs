@@ -0,0 +1,47 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts consecutive congestion signals, so that a limit decrease can require confirmation from
+/// several samples, rather than reacting to a single outlier.
+#[derive(Debug)]
+pub(crate) struct DecreaseConfirmations {
+    required: usize,
+    consecutive: AtomicUsize,
+}
+
+impl DecreaseConfirmations {
+    pub(crate) fn new(required: usize) -> Self {
+        assert!(required > 0, "decrease confirmations must be > 0");
+        Self {
+            required,
+            consecutive: AtomicUsize::new(0),
+        }
+    }
+
+    /// Record whether a sample indicated congestion.
+    ///
+    /// Returns `true` if the limit should be decreased, i.e. there have been enough consecutive
+    /// congestion signals. The count is reset by a decrease or a sample without congestion.
+    pub(crate) fn confirm(&self, congested: bool) -> bool {
+        if !congested {
+            self.consecutive.store(0, Ordering::Release);
+            return false;
+        }
+
+        let consecutive = self.consecutive.fetch_add(1, Ordering::AcqRel) + 1;
+        if consecutive >= self.required {
+            self.consecutive.store(0, Ordering::Release);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Clone for DecreaseConfirmations {
+    fn clone(&self) -> Self {
+        Self {
+            required: self.required,
+            consecutive: AtomicUsize::new(self.consecutive.load(Ordering::Acquire)),
+        }
+    }
+}
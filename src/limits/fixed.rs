@@ -3,7 +3,7 @@ use async_trait::async_trait;
 use super::{LimitAlgorithm, Sample};
 
 /// A simple, fixed concurrency limit.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Fixed(usize);
 impl Fixed {
     #[allow(missing_docs)]
@@ -20,7 +20,19 @@ impl LimitAlgorithm for Fixed {
         self.0
     }
 
+    fn min_limit(&self) -> usize {
+        self.0
+    }
+
+    fn max_limit(&self) -> usize {
+        self.0
+    }
+
     async fn update(&self, _reading: Sample) -> usize {
         self.0
     }
+
+    fn try_update_sync(&self, _reading: Sample) -> Result<usize, Sample> {
+        Ok(self.0)
+    }
 }
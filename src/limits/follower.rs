@@ -0,0 +1,108 @@
+use std::ops::RangeInclusive;
+
+use async_trait::async_trait;
+use conv::ConvUtil;
+use tokio::sync::watch;
+
+use super::{defaults, LimitAlgorithm, Sample};
+
+/// Follows another limiter's limit, scaled by a factor, instead of learning independently.
+///
+/// Useful for canary or shadow deployments, to compare configurations under the same limit as a
+/// primary limiter. Samples are ignored. Changes to the primary's limit are picked up on the next
+/// release.
+///
+/// Subscribe to the primary's limit using
+/// [DefaultLimiter::subscribe()](crate::limiter::DefaultLimiter::subscribe()).
+#[derive(Debug)]
+pub struct Follower {
+    primary: watch::Receiver<usize>,
+    scale: f64,
+    min_limit: usize,
+    max_limit: usize,
+}
+
+impl Follower {
+    #[allow(missing_docs)]
+    pub fn new(primary: watch::Receiver<usize>, scale: f64) -> Self {
+        Self::new_with_limit_range(
+            primary,
+            scale,
+            defaults::DEFAULT_MIN_LIMIT..=defaults::DEFAULT_MAX_LIMIT,
+        )
+    }
+
+    /// Follow `primary`'s limit multiplied by `scale`, kept within `limit_range`.
+    pub fn new_with_limit_range(
+        primary: watch::Receiver<usize>,
+        scale: f64,
+        limit_range: RangeInclusive<usize>,
+    ) -> Self {
+        assert!(
+            scale.is_finite() && scale > 0.,
+            "scale must be finite and > 0"
+        );
+        assert!(*limit_range.start() >= 1, "Limits must be at least 1");
+        assert!(
+            limit_range.start() <= limit_range.end(),
+            "Minimum limit must not be greater than maximum"
+        );
+        Self {
+            primary,
+            scale,
+            min_limit: *limit_range.start(),
+            max_limit: *limit_range.end(),
+        }
+    }
+}
+
+#[async_trait]
+impl LimitAlgorithm for Follower {
+    fn limit(&self) -> usize {
+        let scaled = (*self.primary.borrow() as f64 * self.scale)
+            .round()
+            .approx_as::<usize>()
+            .unwrap_or(usize::MAX);
+        scaled.clamp(self.min_limit, self.max_limit)
+    }
+
+    fn min_limit(&self) -> usize {
+        self.min_limit
+    }
+
+    fn max_limit(&self) -> usize {
+        self.max_limit
+    }
+
+    async fn update(&self, _sample: Sample) -> usize {
+        self.limit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        limiter::{DefaultLimiter, Limiter, Outcome},
+        limits::{Aimd, Follower},
+    };
+
+    #[tokio::test]
+    async fn follows_scaled_primary_limit() {
+        let primary = DefaultLimiter::new(Aimd::new_with_initial_limit(10).decrease_factor(0.5));
+        let follower = DefaultLimiter::new(Follower::new(primary.subscribe(), 2.));
+        assert_eq!(follower.state().limit(), 20);
+
+        let token = primary.try_acquire().await.unwrap();
+        primary.release(token, Some(Outcome::overload())).await;
+        assert_eq!(primary.state().limit(), 5);
+        assert_eq!(
+            follower.state().limit(),
+            20,
+            "not reconciled until a release"
+        );
+
+        let token = follower.try_acquire().await.unwrap();
+        follower.release(token, Some(Outcome::overload())).await;
+        assert_eq!(follower.state().limit(), 10, "primary limit * 2");
+    }
+}
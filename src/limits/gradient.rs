@@ -1,18 +1,19 @@
 use std::{
     ops::RangeInclusive,
     sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
 };
 
 use async_trait::async_trait;
 use conv::ConvAsUtil;
-use tokio::sync::Mutex;
+use tokio::{sync::Mutex, time::Instant};
 
 use crate::{
     limits::{defaults, Sample},
     moving_avg,
 };
 
-use super::{defaults::MIN_SAMPLE_LATENCY, LimitAlgorithm};
+use super::{confirmations::DecreaseConfirmations, defaults::MIN_SAMPLE_LATENCY, LimitAlgorithm};
 
 /// Delay-based congestion avoidance.
 ///
@@ -32,6 +33,23 @@ pub struct Gradient {
     min_limit: usize,
     max_limit: usize,
 
+    /// Decay the long window towards new samples after a gap in traffic.
+    long_window_half_life: Option<Duration>,
+
+    decrease_confirmations: DecreaseConfirmations,
+
+    /// Consecutive fast samples required before speeding up the long window's return to baseline.
+    baseline_speedup_threshold: usize,
+
+    /// Clamp latencies below the minimum up to it, instead of ignoring the sample.
+    clamp_min_latency: bool,
+
+    /// Whether low utilisation prevents increases.
+    utilisation_gate: bool,
+
+    /// Latency-based decreases are suppressed below this many jobs in flight.
+    min_in_flight_for_delay: usize,
+
     limit: AtomicUsize,
     inner: Mutex<Inner>,
 }
@@ -40,6 +58,9 @@ pub struct Gradient {
 struct Inner {
     long_window_latency: moving_avg::ExpSmoothed,
     limit: f64,
+    last_sample: Option<Instant>,
+    /// Consecutive samples much faster than the long window.
+    fast_samples: usize,
 }
 
 impl Gradient {
@@ -48,6 +69,7 @@ impl Gradient {
     const DEFAULT_INCREASE_MIN_GRADIENT: f64 = 0.9;
 
     const DEFAULT_LONG_WINDOW_SAMPLES: u16 = 500;
+    const DEFAULT_BASELINE_SPEEDUP_THRESHOLD: usize = 3;
 
     const DEFAULT_TOLERANCE: f64 = 2.;
     const DEFAULT_SMOOTHING: f64 = 0.2;
@@ -60,9 +82,15 @@ impl Gradient {
         )
     }
 
-    #[allow(missing_docs)]
+    /// Create with an initial limit, which will be kept within `limit_range`.
+    ///
+    /// If the minimum and maximum are equal, the limit is fixed and updates do nothing.
     pub fn new(initial_limit: usize, limit_range: RangeInclusive<usize>) -> Self {
         assert!(*limit_range.start() >= 1, "Limits must be at least 1");
+        assert!(
+            limit_range.start() <= limit_range.end(),
+            "Minimum limit must not be greater than maximum"
+        );
         assert!(
             initial_limit >= *limit_range.start(),
             "Initial limit less than minimum"
@@ -76,12 +104,24 @@ impl Gradient {
             min_limit: *limit_range.start(),
             max_limit: *limit_range.end(),
 
+            long_window_half_life: None,
+
+            decrease_confirmations: DecreaseConfirmations::new(1),
+
+            baseline_speedup_threshold: Self::DEFAULT_BASELINE_SPEEDUP_THRESHOLD,
+
+            clamp_min_latency: false,
+            utilisation_gate: true,
+            min_in_flight_for_delay: 0,
+
             limit: AtomicUsize::new(initial_limit),
             inner: Mutex::new(Inner {
                 long_window_latency: moving_avg::ExpSmoothed::new_with_window_size(
                     Self::DEFAULT_LONG_WINDOW_SAMPLES,
                 ),
                 limit: initial_limit as f64,
+                last_sample: None,
+                fast_samples: 0,
             }),
         }
     }
@@ -89,11 +129,115 @@ impl Gradient {
     #[allow(missing_docs)]
     pub fn with_max_limit(self, max: usize) -> Self {
         assert!(max > 0);
+        assert!(
+            max >= self.min_limit,
+            "Maximum limit must not be less than minimum"
+        );
         Self {
             max_limit: max,
             ..self
         }
     }
+
+    /// Require `confirmations` consecutive samples indicating congestion before decreasing the
+    /// limit.
+    ///
+    /// A sample without congestion resets the count. Defaults to 1, i.e. decreasing immediately.
+    pub fn with_decrease_confirmations(self, confirmations: usize) -> Self {
+        Self {
+            decrease_confirmations: DecreaseConfirmations::new(confirmations),
+            ..self
+        }
+    }
+
+    /// The number of consecutive samples less than half the long window latency required before
+    /// the long window is moved more quickly towards them.
+    ///
+    /// This speeds up the return to baseline after a long period of increased load, without a
+    /// single fast outlier collapsing the baseline. Defaults to 3.
+    pub fn with_baseline_speedup_threshold(self, samples: usize) -> Self {
+        assert!(samples > 0, "at least one sample required");
+        Self {
+            baseline_speedup_threshold: samples,
+            ..self
+        }
+    }
+
+    /// Clamp sample latencies below 1µs up to 1µs, instead of ignoring those samples.
+    ///
+    /// Useful for very fast resources, e.g. in-memory, where all latencies are legitimately below
+    /// 1µs. Latency then carries little information, but the limit can still adapt to
+    /// utilisation. Disabled by default.
+    pub fn with_clamp_min_latency(self, enabled: bool) -> Self {
+        Self {
+            clamp_min_latency: enabled,
+            ..self
+        }
+    }
+
+    /// Only increase the limit when utilisation is high. Enabled by default.
+    ///
+    /// Disabling this allows increases on any qualifying success, which can help when several
+    /// limiters share a backend, e.g. partitions, and each sees low utilisation individually. The
+    /// limit can then grow far beyond what is actually used, and overshoot badly when load arrives.
+    pub fn with_utilisation_gate(self, enabled: bool) -> Self {
+        Self {
+            utilisation_gate: enabled,
+            ..self
+        }
+    }
+
+    /// Suppress latency-based decreases while fewer than `min_in_flight` jobs are in flight.
+    ///
+    /// With very few jobs in flight there can't be much queueing, so high latency is more likely
+    /// to be noise, e.g. a single slow request. Defaults to 0, i.e. no minimum.
+    pub fn with_min_in_flight_for_delay(self, min_in_flight: usize) -> Self {
+        Self {
+            min_in_flight_for_delay: min_in_flight,
+            ..self
+        }
+    }
+
+    /// Configure the long window: the number of samples it averages over, and the number of initial
+    /// samples to take a simple average of before switching to exponential smoothing.
+    ///
+    /// The warmup must be at least 1. Defaults to 500 samples with a warmup of 10.
+    pub fn with_long_window(mut self, samples: u16, warmup: u16) -> Self {
+        self.inner.get_mut().long_window_latency =
+            moving_avg::ExpSmoothed::new_with_window_and_warmup(samples, warmup);
+        self
+    }
+
+    /// Decay the long window latency towards the latest sample when there is a gap between samples.
+    ///
+    /// After a gap of one `half_life`, the long window will move halfway to the latest sample.
+    /// Without this, the long window can hold stale latencies from before the gap, causing a
+    /// spurious gradient when traffic resumes.
+    pub fn with_long_window_half_life(self, half_life: Duration) -> Self {
+        assert!(half_life > Duration::ZERO, "half life must be > 0");
+        Self {
+            long_window_half_life: Some(half_life),
+            ..self
+        }
+    }
+}
+
+impl Inner {
+    /// Move the long window towards the latest sample, depending on the time since the last sample.
+    fn decay_long_window(&mut self, latency: Duration, half_life: Duration) {
+        let Some(last_sample) = self.last_sample else {
+            return;
+        };
+
+        let half_lives = last_sample.elapsed().as_secs_f64() / half_life.as_secs_f64();
+        let weight = 1. - 0.5_f64.powf(half_lives);
+
+        let long = self.long_window_latency.value().as_secs_f64();
+        let decayed = long + (latency.as_secs_f64() - long) * weight;
+
+        self.long_window_latency
+            .set(Duration::from_secs_f64(decayed.max(0.)));
+    }
 }
 
 #[async_trait]
@@ -102,13 +246,38 @@ impl LimitAlgorithm for Gradient {
         self.limit.load(Ordering::Acquire)
     }
 
-    async fn update(&self, sample: Sample) -> usize {
+    fn min_limit(&self) -> usize {
+        self.min_limit
+    }
+
+    fn max_limit(&self) -> usize {
+        self.max_limit
+    }
+
+    /// The long window average latency.
+    async fn baseline_latency(&self) -> Option<Duration> {
+        let inner = self.inner.lock().await;
+        inner.last_sample.map(|_| inner.long_window_latency.value())
+    }
+
+    async fn update(&self, mut sample: Sample) -> usize {
+        if self.min_limit == self.max_limit {
+            return self.max_limit;
+        }
         if sample.latency < MIN_SAMPLE_LATENCY {
-            return self.limit.load(Ordering::Acquire);
+            if !self.clamp_min_latency {
+                return self.limit.load(Ordering::Acquire);
+            }
+            sample.latency = MIN_SAMPLE_LATENCY;
         }
 
         let mut inner = self.inner.lock().await;
 
+        if let Some(half_life) = self.long_window_half_life {
+            inner.decay_long_window(sample.latency, half_life);
+        }
+        inner.last_sample = Some(Instant::now());
+
         // Update long window
         let long = inner.long_window_latency.sample(sample.latency);
 
@@ -116,7 +285,12 @@ impl LimitAlgorithm for Gradient {
 
         // Speed up return to baseline after long period of increased load.
         if ratio > 2.0 {
-            inner.long_window_latency.set(long.mul_f64(0.95));
+            inner.fast_samples += 1;
+            if inner.fast_samples >= self.baseline_speedup_threshold {
+                inner.long_window_latency.set(long.mul_f64(0.95));
+            }
+        } else {
+            inner.fast_samples = 0;
         }
 
         let old_limit = inner.limit;
@@ -124,13 +298,24 @@ impl LimitAlgorithm for Gradient {
         // Only apply downwards gradient (when latency has increased).
         // Limit to >= 0.5 to prevent aggressive load shedding.
         // Tolerate a given amount of latency difference.
-        let gradient = (Self::DEFAULT_TOLERANCE * ratio).clamp(0.5, 1.0);
+        let gradient = if sample.in_flight < self.min_in_flight_for_delay {
+            1.0
+        } else {
+            (Self::DEFAULT_TOLERANCE * ratio).clamp(0.5, 1.0)
+        };
+
+        let congested = gradient < 1.0;
+        if !self.decrease_confirmations.confirm(congested) && congested {
+            // Wait for more samples to confirm the increase in latency.
+            return self.limit.load(Ordering::Acquire);
+        }
 
         let utilisation = sample.in_flight as f64 / old_limit;
 
         // Only apply an increase if we're using enough to justify it
         // and we're not trying to reduce the limit by much.
-        let increase = if utilisation > Self::DEFAULT_INCREASE_MIN_UTILISATION
+        let increase = if (!self.utilisation_gate
+            || utilisation > Self::DEFAULT_INCREASE_MIN_UTILISATION)
             && gradient > Self::DEFAULT_INCREASE_MIN_GRADIENT
         {
             Self::DEFAULT_INCREASE
@@ -208,4 +393,203 @@ mod tests {
             "increased latency: decrease limit"
         );
     }
+
+    #[tokio::test]
+    async fn decrease_confirmations() {
+        let gradient = Gradient::new_with_initial_limit(10).with_decrease_confirmations(3);
+
+        let steady = Sample {
+            latency: Duration::from_millis(10),
+            in_flight: 1,
+            outcome: Outcome::Success,
+        };
+        let congested = Sample {
+            latency: Duration::from_millis(100),
+            ..steady.clone()
+        };
+
+        for _ in 0..20 {
+            gradient.update(steady.clone()).await;
+        }
+        assert_eq!(gradient.limit(), 10, "low utilisation: no change");
+
+        assert_eq!(
+            gradient.update(congested.clone()).await,
+            10,
+            "single outlier"
+        );
+        assert_eq!(gradient.update(congested.clone()).await, 10);
+        gradient.update(steady).await;
+
+        assert_eq!(gradient.update(congested.clone()).await, 10);
+        assert_eq!(gradient.update(congested.clone()).await, 10);
+        assert!(
+            gradient.update(congested).await < 10,
+            "sustained congestion => decrease limit"
+        );
+    }
+
+    #[tokio::test]
+    async fn fast_outlier_does_not_collapse_baseline() {
+        async fn baseline_after_fast_samples(gradient: &Gradient, fast: usize) -> Duration {
+            for _ in 0..20 {
+                gradient
+                    .update(Sample {
+                        latency: Duration::from_millis(10),
+                        in_flight: 1,
+                        outcome: Outcome::Success,
+                    })
+                    .await;
+            }
+            for _ in 0..fast {
+                gradient
+                    .update(Sample {
+                        latency: Duration::from_millis(1),
+                        in_flight: 1,
+                        outcome: Outcome::Success,
+                    })
+                    .await;
+            }
+            gradient.baseline_latency().await.unwrap()
+        }
+
+        let outlier = baseline_after_fast_samples(&Gradient::new_with_initial_limit(10), 1).await;
+        assert!(
+            outlier > Duration::from_micros(9_900),
+            "single fast outlier: baseline barely moves, got {outlier:?}"
+        );
+
+        let sustained = baseline_after_fast_samples(&Gradient::new_with_initial_limit(10), 3).await;
+        assert!(
+            sustained < Duration::from_micros(9_500),
+            "sustained improvement: speed up return to baseline, got {sustained:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn clamp_min_latency() {
+        async fn limit_at_high_utilisation(gradient: Gradient) -> usize {
+            for _ in 0..10 {
+                gradient
+                    .update(Sample {
+                        latency: Duration::from_nanos(100),
+                        in_flight: gradient.limit(),
+                        outcome: Outcome::Success,
+                    })
+                    .await;
+            }
+            gradient.limit()
+        }
+
+        assert_eq!(
+            limit_at_high_utilisation(Gradient::new_with_initial_limit(10)).await,
+            10,
+            "sub-microsecond samples ignored"
+        );
+        let clamped = limit_at_high_utilisation(
+            Gradient::new_with_initial_limit(10).with_clamp_min_latency(true),
+        )
+        .await;
+        assert!(clamped > 10, "clamped: high utilisation => increase limit");
+    }
+
+    #[tokio::test]
+    async fn utilisation_gate() {
+        async fn limit_at_low_utilisation(gradient: Gradient) -> usize {
+            for _ in 0..20 {
+                gradient
+                    .update(Sample {
+                        latency: Duration::from_millis(10),
+                        in_flight: 1,
+                        outcome: Outcome::Success,
+                    })
+                    .await;
+            }
+            gradient.limit()
+        }
+
+        assert_eq!(
+            limit_at_low_utilisation(Gradient::new_with_initial_limit(10)).await,
+            10
+        );
+        let ungated = limit_at_low_utilisation(
+            Gradient::new_with_initial_limit(10).with_utilisation_gate(false),
+        )
+        .await;
+        assert!(ungated > 10, "increase, got {ungated}");
+    }
+
+    #[tokio::test]
+    async fn min_in_flight_for_delay() {
+        async fn limit_after_slow_sample(gradient: Gradient) -> usize {
+            for _ in 0..20 {
+                gradient
+                    .update(Sample {
+                        latency: Duration::from_millis(10),
+                        in_flight: 1,
+                        outcome: Outcome::Success,
+                    })
+                    .await;
+            }
+            gradient
+                .update(Sample {
+                    latency: Duration::from_millis(100),
+                    in_flight: 1,
+                    outcome: Outcome::Success,
+                })
+                .await
+        }
+
+        assert!(
+            limit_after_slow_sample(Gradient::new_with_initial_limit(10)).await < 10,
+            "increased latency: decrease limit"
+        );
+        assert_eq!(
+            limit_after_slow_sample(
+                Gradient::new_with_initial_limit(10).with_min_in_flight_for_delay(2)
+            )
+            .await,
+            10,
+            "too few in flight: ignore latency"
+        );
+    }
+
+    #[tokio::test]
+    async fn long_window_decays_after_gap() {
+        tokio::time::pause();
+
+        async fn limit_after_gap(gradient: Gradient) -> (usize, usize) {
+            for _ in 0..20 {
+                gradient
+                    .update(Sample {
+                        latency: Duration::from_millis(10),
+                        in_flight: 1,
+                        outcome: Outcome::Success,
+                    })
+                    .await;
+            }
+            let before = gradient.limit();
+
+            tokio::time::advance(Duration::from_secs(60)).await;
+
+            let after = gradient
+                .update(Sample {
+                    latency: Duration::from_millis(50),
+                    in_flight: 1,
+                    outcome: Outcome::Success,
+                })
+                .await;
+
+            (before, after)
+        }
+
+        let (before, after) = limit_after_gap(Gradient::new_with_initial_limit(10)).await;
+        assert!(after < before, "stale long window: spurious decrease");
+
+        let (before, after) = limit_after_gap(
+            Gradient::new_with_initial_limit(10).with_long_window_half_life(Duration::from_secs(1)),
+        )
+        .await;
+        assert_eq!(after, before, "decayed long window: no spurious decrease");
+    }
 }
@@ -1,9 +1,16 @@
 //! Algorithms for controlling concurrency limits.
 
+mod adaptive;
 mod aimd;
+mod any;
+mod batched;
+mod confirmations;
 mod defaults;
 mod fixed;
+mod follower;
 mod gradient;
+mod shared;
+mod success_rate;
 mod vegas;
 mod windowed;
 
@@ -12,9 +19,15 @@ use std::time::Duration;
 
 use crate::limiter::Outcome;
 
+pub use adaptive::Adaptive;
 pub use aimd::Aimd;
+pub use any::{AggregationConfig, AnyAggregator, AnyLimit, LimitConfig};
+pub use batched::Batched;
 pub use fixed::Fixed;
+pub use follower::Follower;
 pub use gradient::Gradient;
+pub use shared::SharedAlgorithm;
+pub use success_rate::SuccessRate;
 pub use vegas::Vegas;
 pub use windowed::Windowed;
 
@@ -24,8 +37,46 @@ pub trait LimitAlgorithm {
     /// The current limit.
     fn limit(&self) -> usize;
 
+    /// The lowest limit this algorithm will set.
+    fn min_limit(&self) -> usize {
+        1
+    }
+
+    /// The highest limit this algorithm will set.
+    fn max_limit(&self) -> usize {
+        usize::MAX
+    }
+
     /// Update the concurrency limit in response to a new job completion.
     async fn update(&self, sample: Sample) -> usize;
+
+    /// Update the concurrency limit without awaiting, if the algorithm can. Must behave the same as
+    /// [update()](Self::update()).
+    ///
+    /// Lets the limiter avoid allocating for an async call on every release. Returns the sample
+    /// back by default, in which case `update()` is used instead.
+    fn try_update_sync(&self, sample: Sample) -> Result<usize, Sample> {
+        Err(sample)
+    }
+
+    /// The latency the algorithm considers to be the baseline, i.e. without congestion, if it has
+    /// learned one.
+    ///
+    /// `None` by default.
+    async fn baseline_latency(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Optionally update the concurrency limit when a job timed out waiting to acquire a token,
+    /// suggesting the limit might be too low.
+    ///
+    /// `sample` contains the latency of a recently completed job and the current number of jobs in
+    /// flight.
+    ///
+    /// Ignored by default.
+    async fn on_acquire_timeout(&self, _sample: Sample) -> usize {
+        self.limit()
+    }
 }
 
 /// The result of a job (or jobs), including the [Outcome] (loss) and latency (delay).
@@ -36,3 +87,30 @@ pub struct Sample {
     pub(crate) in_flight: usize,
     pub(crate) outcome: Outcome,
 }
+
+impl Sample {
+    /// A sample with the given latency, number of jobs in flight and outcome, e.g. for testing
+    /// algorithms with `testing::drive()`.
+    pub fn new(latency: Duration, in_flight: usize, outcome: Outcome) -> Self {
+        Self {
+            latency,
+            in_flight,
+            outcome,
+        }
+    }
+
+    /// The outcome of the job(s).
+    pub fn outcome(&self) -> Outcome {
+        self.outcome
+    }
+
+    /// The latency of the job(s).
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    /// The number of jobs in flight when the sample was taken.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+}
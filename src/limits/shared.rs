@@ -0,0 +1,112 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use conv::ConvUtil;
+
+use super::{LimitAlgorithm, Sample};
+
+/// A share of a [LimitAlgorithm] used by multiple independent limiters, e.g. per-region limiters
+/// with a single, global view of congestion.
+///
+/// Each limiter reports its samples to the shared algorithm, and uses a fraction of the shared
+/// limit. Unlike [partitioning](crate::limiter::PartitionedLimiter), each limiter acquires
+/// independently, without borrowing spare capacity from the others.
+///
+/// Samples are scaled up by the inverse of the fraction, approximating the total number of jobs in
+/// flight across all limiters, so the shared algorithm sees a consistent level of utilisation.
+///
+/// Changes to the shared limit caused by one limiter are picked up by the others on their next
+/// release.
+#[derive(Debug)]
+pub struct SharedAlgorithm<L> {
+    inner: Arc<L>,
+    fraction: f64,
+}
+
+impl<L: LimitAlgorithm> SharedAlgorithm<L> {
+    /// Use `fraction` of the limit of the shared algorithm.
+    pub fn new(inner: Arc<L>, fraction: f64) -> Self {
+        assert!(
+            fraction > 0. && fraction <= 1.,
+            "fraction must be > 0 and <= 1"
+        );
+        Self { inner, fraction }
+    }
+
+    fn share(&self, limit: usize) -> usize {
+        (limit as f64 * self.fraction)
+            .ceil()
+            .approx_as::<usize>()
+            .expect("should be less than the shared limit")
+            .max(1)
+    }
+
+    fn scale_up(&self, mut sample: Sample) -> Sample {
+        sample.in_flight = (sample.in_flight as f64 / self.fraction)
+            .round()
+            .approx_as::<usize>()
+            .unwrap_or(usize::MAX);
+        sample
+    }
+}
+
+#[async_trait]
+impl<L> LimitAlgorithm for SharedAlgorithm<L>
+where
+    L: LimitAlgorithm + Send + Sync,
+{
+    fn limit(&self) -> usize {
+        self.share(self.inner.limit())
+    }
+
+    fn min_limit(&self) -> usize {
+        self.share(self.inner.min_limit())
+    }
+
+    fn max_limit(&self) -> usize {
+        self.share(self.inner.max_limit())
+    }
+
+    async fn baseline_latency(&self) -> Option<Duration> {
+        self.inner.baseline_latency().await
+    }
+
+    async fn update(&self, sample: Sample) -> usize {
+        let limit = self.inner.update(self.scale_up(sample)).await;
+        self.share(limit)
+    }
+
+    async fn on_acquire_timeout(&self, sample: Sample) -> usize {
+        let limit = self.inner.on_acquire_timeout(self.scale_up(sample)).await;
+        self.share(limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        limiter::{DefaultLimiter, Limiter, Outcome},
+        limits::{Aimd, LimitAlgorithm, SharedAlgorithm},
+    };
+
+    #[tokio::test]
+    async fn overload_affects_all_limiters() {
+        let global = Arc::new(Aimd::new_with_initial_limit(10).decrease_factor(0.5));
+
+        let region_a = DefaultLimiter::new(SharedAlgorithm::new(global.clone(), 0.5));
+        let region_b = DefaultLimiter::new(SharedAlgorithm::new(global.clone(), 0.5));
+        assert_eq!(region_a.state().limit(), 5);
+        assert_eq!(region_b.state().limit(), 5);
+
+        let token = region_a.try_acquire().await.unwrap();
+        region_a.release(token, Some(Outcome::overload())).await;
+        assert_eq!(global.limit(), 5);
+        assert_eq!(region_a.state().limit(), 3);
+
+        let token = region_b.try_acquire().await.unwrap();
+        region_b.release(token, Some(Outcome::Success)).await;
+        assert_eq!(region_b.state().limit(), 3, "picked up on next release");
+    }
+}
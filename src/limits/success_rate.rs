@@ -0,0 +1,250 @@
+use std::{
+    collections::VecDeque,
+    ops::RangeInclusive,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+
+use crate::limits::Sample;
+
+use super::{aimd::multiplicative_decrease, defaults, LimitAlgorithm};
+
+/// Loss-based overload avoidance, targeting a success rate.
+///
+/// Tracks the fraction of successful jobs over a rolling window of samples. Increases the limit
+/// while the success rate is at or above the target and utilisation is high, and decreases it by a
+/// factor when the success rate drops below the target.
+///
+/// Useful for systems with flat latency, whose success rate degrades under load, e.g. by returning
+/// HTTP 503s. Unlike [Aimd](crate::limits::Aimd), a small background rate of overload is tolerated.
+#[derive(Debug)]
+pub struct SuccessRate {
+    min_limit: usize,
+    max_limit: usize,
+    target: f64,
+    window_size: usize,
+    decrease_factor: f64,
+
+    limit: AtomicUsize,
+    window: Mutex<Window>,
+}
+
+#[derive(Debug)]
+struct Window {
+    /// Whether each recent sample was overloaded.
+    overloaded: VecDeque<bool>,
+    overloads: usize,
+}
+
+impl SuccessRate {
+    const DEFAULT_TARGET: f64 = 0.99;
+    const DEFAULT_WINDOW_SIZE: usize = 100;
+    const DEFAULT_DECREASE_FACTOR: f64 = 0.9;
+    const DEFAULT_INCREASE: usize = 1;
+    const DEFAULT_INCREASE_MIN_UTILISATION: f64 = 0.8;
+
+    #[allow(missing_docs)]
+    pub fn new_with_initial_limit(initial_limit: usize) -> Self {
+        Self::new(
+            initial_limit,
+            defaults::DEFAULT_MIN_LIMIT..=defaults::DEFAULT_MAX_LIMIT,
+        )
+    }
+
+    /// Create with an initial limit, which will be kept within `limit_range`.
+    ///
+    /// If the minimum and maximum are equal, the limit is fixed and updates do nothing.
+    pub fn new(initial_limit: usize, limit_range: RangeInclusive<usize>) -> Self {
+        assert!(*limit_range.start() >= 1, "Limits must be at least 1");
+        assert!(
+            limit_range.start() <= limit_range.end(),
+            "Minimum limit must not be greater than maximum"
+        );
+        assert!(
+            initial_limit >= *limit_range.start(),
+            "Initial limit less than minimum"
+        );
+        assert!(
+            initial_limit <= *limit_range.end(),
+            "Initial limit more than maximum"
+        );
+
+        Self {
+            min_limit: *limit_range.start(),
+            max_limit: *limit_range.end(),
+            target: Self::DEFAULT_TARGET,
+            window_size: Self::DEFAULT_WINDOW_SIZE,
+            decrease_factor: Self::DEFAULT_DECREASE_FACTOR,
+
+            limit: AtomicUsize::new(initial_limit),
+            window: Mutex::new(Window {
+                overloaded: VecDeque::with_capacity(Self::DEFAULT_WINDOW_SIZE),
+                overloads: 0,
+            }),
+        }
+    }
+
+    /// The fraction of jobs which should succeed. 0.99 = 99%, the default.
+    pub fn with_target_success_rate(self, target: f64) -> Self {
+        assert!(
+            target > 0. && target <= 1.,
+            "target success rate must be > 0 and <= 1"
+        );
+        Self { target, ..self }
+    }
+
+    /// The number of recent samples to calculate the success rate over. Defaults to 100.
+    ///
+    /// The window should be large enough that the target can be distinguished, e.g. at least 100
+    /// samples for a 99% target.
+    pub fn with_window(mut self, samples: usize) -> Self {
+        assert!(samples > 0, "at least one sample required per window");
+        self.window_size = samples;
+        self.window.get_mut().overloaded = VecDeque::with_capacity(samples);
+        self
+    }
+
+    /// Set the multiplier which will be applied when decreasing the limit.
+    pub fn decrease_factor(self, factor: f64) -> Self {
+        assert!((0.5..1.0).contains(&factor));
+        Self {
+            decrease_factor: factor,
+            ..self
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn with_max_limit(self, max: usize) -> Self {
+        assert!(max > 0);
+        assert!(
+            max >= self.min_limit,
+            "Maximum limit must not be less than minimum"
+        );
+        Self {
+            max_limit: max,
+            ..self
+        }
+    }
+
+    /// The number of overloads in a full window above which the target is missed.
+    fn allowed_overloads(&self) -> f64 {
+        (1. - self.target) * self.window_size as f64
+    }
+}
+
+impl Window {
+    fn push(&mut self, overloaded: bool, size: usize) {
+        if self.overloaded.len() == size && self.overloaded.pop_front() == Some(true) {
+            self.overloads -= 1;
+        }
+        self.overloaded.push_back(overloaded);
+        if overloaded {
+            self.overloads += 1;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.overloaded.clear();
+        self.overloads = 0;
+    }
+}
+
+#[async_trait]
+impl LimitAlgorithm for SuccessRate {
+    fn limit(&self) -> usize {
+        self.limit.load(Ordering::Acquire)
+    }
+
+    fn min_limit(&self) -> usize {
+        self.min_limit
+    }
+
+    fn max_limit(&self) -> usize {
+        self.max_limit
+    }
+
+    async fn update(&self, sample: Sample) -> usize {
+        if self.min_limit == self.max_limit {
+            return self.max_limit;
+        }
+
+        let mut window = self.window.lock().await;
+        window.push(sample.outcome.is_overload(), self.window_size);
+
+        let limit = self.limit.load(Ordering::Acquire);
+
+        // Decrease as soon as the window can no longer meet the target, even if it isn't full yet.
+        let new_limit = if window.overloads as f64 > self.allowed_overloads() {
+            // Start a new window, so the same overloads don't cause repeated decreases.
+            window.clear();
+            multiplicative_decrease(limit, self.decrease_factor)
+        } else if window.overloaded.len() == self.window_size
+            && sample.in_flight as f64 / limit as f64 > Self::DEFAULT_INCREASE_MIN_UTILISATION
+        {
+            limit + Self::DEFAULT_INCREASE
+        } else {
+            limit
+        };
+
+        let new_limit = new_limit.clamp(self.min_limit, self.max_limit);
+        self.limit.store(new_limit, Ordering::Release);
+
+        new_limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::limiter::Outcome;
+
+    use super::*;
+
+    async fn run(algo: &SuccessRate, samples: usize, overload_every: Option<usize>) -> usize {
+        let mut limit = algo.limit();
+        for i in 0..samples {
+            let overloaded = overload_every.is_some_and(|n| i % n == 0);
+            limit = algo
+                .update(Sample {
+                    latency: Duration::from_millis(10),
+                    in_flight: limit,
+                    outcome: if overloaded {
+                        Outcome::overload()
+                    } else {
+                        Outcome::Success
+                    },
+                })
+                .await;
+        }
+        limit
+    }
+
+    #[tokio::test]
+    async fn increases_above_target() {
+        let algo = SuccessRate::new_with_initial_limit(10).with_target_success_rate(0.95);
+
+        // 99% success.
+        let limit = run(&algo, 200, Some(100)).await;
+
+        assert!(limit > 10, "above target: increase limit, got {limit}");
+    }
+
+    #[tokio::test]
+    async fn decreases_below_target() {
+        let algo = SuccessRate::new_with_initial_limit(20).with_target_success_rate(0.99);
+
+        let healthy = run(&algo, 100, None).await;
+        assert!(healthy >= 20);
+
+        // 90% success.
+        let degraded = run(&algo, 100, Some(10)).await;
+
+        assert!(
+            degraded < healthy,
+            "below target: decrease limit. Before: {healthy}, after: {degraded}"
+        );
+    }
+}
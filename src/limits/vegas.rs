@@ -1,20 +1,26 @@
 use std::{
     fmt::Debug,
     ops::RangeInclusive,
-    sync::atomic::{AtomicUsize, Ordering},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
     time::Duration,
 };
 
 use async_trait::async_trait;
-use tokio::sync::Mutex;
 
-use crate::{limiter::Outcome, limits::defaults};
+use crate::limits::defaults;
 
-use super::{aimd::multiplicative_decrease, defaults::MIN_SAMPLE_LATENCY, LimitAlgorithm, Sample};
+use super::{
+    aimd::multiplicative_decrease, confirmations::DecreaseConfirmations,
+    defaults::MIN_SAMPLE_LATENCY, LimitAlgorithm, Sample,
+};
 
 /// Loss- and delay-based congestion avoidance.
 ///
-/// Additive increase, additive decrease. Multiplicative decrease when overload detected.
+/// Additive increase, additive decrease. Multiplicative decrease when overload detected, unless
+/// [additive overload decrease](Self::with_additive_overload_decrease()) is enabled.
 ///
 /// Estimates queuing delay by comparing the current latency with the minimum observed latency to
 /// estimate the number of jobs being queued.
@@ -46,6 +52,20 @@ pub struct Vegas {
     /// Upper queueing threshold, as a function of the current limit.
     beta: Box<dyn (Fn(usize) -> f64) + Send + Sync>,
 
+    decrease_confirmations: DecreaseConfirmations,
+
+    /// Decrease additively on overload, instead of multiplicatively.
+    additive_overload_decrease: bool,
+
+    /// Clamp latencies below the minimum up to it, instead of ignoring the sample.
+    clamp_min_latency: bool,
+
+    /// Whether low utilisation prevents increases.
+    utilisation_gate: bool,
+
+    /// Latency-based decreases are suppressed below this many jobs in flight.
+    min_in_flight_for_delay: usize,
+
     limit: AtomicUsize,
     inner: Mutex<Inner>,
 }
@@ -76,9 +96,15 @@ impl Vegas {
         )
     }
 
-    #[allow(missing_docs)]
+    /// Create with an initial limit, which will be kept within `limit_range`.
+    ///
+    /// If the minimum and maximum are equal, the limit is fixed and updates do nothing.
     pub fn new(initial_limit: usize, limit_range: RangeInclusive<usize>) -> Self {
         assert!(*limit_range.start() >= 1, "Limits must be at least 1");
+        assert!(
+            limit_range.start() <= limit_range.end(),
+            "Minimum limit must not be greater than maximum"
+        );
         assert!(
             initial_limit >= *limit_range.start(),
             "Initial limit less than minimum"
@@ -100,6 +126,14 @@ impl Vegas {
                 Self::DEFAULT_BETA_MULTIPLIER * (limit as f64).log10().max(1_f64)
             }),
 
+            decrease_confirmations: DecreaseConfirmations::new(1),
+
+            additive_overload_decrease: false,
+
+            clamp_min_latency: false,
+            utilisation_gate: true,
+            min_in_flight_for_delay: 0,
+
             inner: Mutex::new(Inner {
                 base_latency: Duration::MAX,
             }),
@@ -109,11 +143,178 @@ impl Vegas {
     #[allow(missing_docs)]
     pub fn with_max_limit(self, max: usize) -> Self {
         assert!(max > 0);
+        assert!(
+            max >= self.min_limit,
+            "Maximum limit must not be less than minimum"
+        );
         Self {
             max_limit: max,
             ..self
         }
     }
+
+    /// Require `confirmations` consecutive samples indicating congestion before decreasing the
+    /// limit.
+    ///
+    /// A sample without congestion resets the count. Defaults to 1, i.e. decreasing immediately.
+    pub fn with_decrease_confirmations(self, confirmations: usize) -> Self {
+        Self {
+            decrease_confirmations: DecreaseConfirmations::new(confirmations),
+            ..self
+        }
+    }
+
+    /// Decrease the limit additively on overload, the same as when queueing is detected, instead of
+    /// multiplicatively.
+    ///
+    /// Closer to classic TCP Vegas, and gentler where multiplicative decreases are too aggressive.
+    /// Disabled by default.
+    pub fn with_additive_overload_decrease(self, enabled: bool) -> Self {
+        Self {
+            additive_overload_decrease: enabled,
+            ..self
+        }
+    }
+
+    /// Clamp sample latencies below 1µs up to 1µs, instead of ignoring those samples.
+    ///
+    /// Useful for very fast resources, e.g. in-memory, where all latencies are legitimately below
+    /// 1µs. Latency then carries little information, but the limit can still adapt to overload and
+    /// utilisation. Disabled by default.
+    pub fn with_clamp_min_latency(self, enabled: bool) -> Self {
+        Self {
+            clamp_min_latency: enabled,
+            ..self
+        }
+    }
+
+    /// Only increase the limit when utilisation is high. Enabled by default.
+    ///
+    /// Disabling this allows increases on any qualifying success, which can help when several
+    /// limiters share a backend, e.g. partitions, and each sees low utilisation individually. The
+    /// limit can then grow far beyond what is actually used, and overshoot badly when load arrives.
+    pub fn with_utilisation_gate(self, enabled: bool) -> Self {
+        Self {
+            utilisation_gate: enabled,
+            ..self
+        }
+    }
+
+    /// Suppress latency-based decreases while fewer than `min_in_flight` jobs are in flight.
+    ///
+    /// With very few jobs in flight there can't be much queueing, so high latency is more likely
+    /// to be noise, e.g. a single slow request. Decreases on overload still apply. Defaults to 0,
+    /// i.e. no minimum.
+    pub fn with_min_in_flight_for_delay(self, min_in_flight: usize) -> Self {
+        Self {
+            min_in_flight_for_delay: min_in_flight,
+            ..self
+        }
+    }
+
+    /// Set the lower and upper queueing thresholds, as functions of the current limit.
+    ///
+    /// The limit will be increased when fewer than `alpha(limit)` jobs are estimated to be
+    /// queueing, and decreased when more than `beta(limit)` are. Alpha must not be greater than
+    /// beta.
+    pub fn with_thresholds(
+        self,
+        alpha: impl Fn(usize) -> f64 + Send + Sync + 'static,
+        beta: impl Fn(usize) -> f64 + Send + Sync + 'static,
+    ) -> Self {
+        let vegas = Self {
+            alpha: Box::new(alpha),
+            beta: Box::new(beta),
+            ..self
+        };
+        vegas.assert_thresholds();
+        vegas
+    }
+
+    fn assert_thresholds(&self) {
+        let limit = self.limit.load(Ordering::Acquire);
+        assert!(
+            (self.alpha)(limit) <= (self.beta)(limit),
+            "alpha must not be greater than beta"
+        );
+    }
+
+    /// See [update()](LimitAlgorithm::update()).
+    fn update_now(&self, mut sample: Sample) -> usize {
+        if self.min_limit == self.max_limit {
+            return self.max_limit;
+        }
+        if sample.latency < MIN_SAMPLE_LATENCY {
+            if !self.clamp_min_latency {
+                return self.limit.load(Ordering::Acquire);
+            }
+            sample.latency = MIN_SAMPLE_LATENCY;
+        }
+
+        let mut inner = self.inner.lock().expect("lock should not be poisoned");
+
+        if sample.latency < inner.base_latency {
+            // Record a baseline "no load" latency and keep the limit.
+            inner.base_latency = sample.latency;
+            // return self.limit.load(Ordering::Acquire);
+        }
+
+        // Updates are serialised by the lock on `inner`.
+        let limit = self.limit.load(Ordering::Acquire);
+
+        // TODO: periodically reset baseline latency measurement.
+
+        let actual_rate = sample.in_flight as f64 / sample.latency.as_secs_f64();
+
+        let extra_latency = sample.latency.as_secs_f64() - inner.base_latency.as_secs_f64();
+
+        let estimated_queued_jobs = actual_rate * extra_latency;
+
+        let utilisation = sample.in_flight as f64 / limit as f64;
+
+        let increment = limit.ilog10().max(1) as usize;
+
+        let overloaded = sample.outcome.is_overload();
+        let queueing = sample.in_flight >= self.min_in_flight_for_delay
+            && estimated_queued_jobs > (self.beta)(limit);
+        let decrease = self.decrease_confirmations.confirm(overloaded || queueing);
+
+        let new_limit = if overloaded {
+            if decrease && self.additive_overload_decrease {
+                // Limit too big – overload
+                limit - increment
+            } else if decrease {
+                // Limit too big – overload
+                multiplicative_decrease(limit, Self::DEFAULT_DECREASE_FACTOR)
+            } else {
+                limit
+            }
+        } else if queueing {
+            if decrease {
+                // Limit too big – too much queueing
+                limit - increment
+            } else {
+                limit
+            }
+        } else if estimated_queued_jobs < (self.alpha)(limit)
+            && (!self.utilisation_gate || utilisation >= Self::DEFAULT_INCREASE_MIN_UTILISATION)
+        {
+            // Limit too small – low queueing + high utilisation
+
+            // TODO: support some kind of fast start, e.g. increase by beta when almost no queueing
+            limit + increment
+        } else {
+            // Perfect porridge
+            limit
+        };
+
+        self.limit.store(
+            new_limit.clamp(self.min_limit, self.max_limit),
+            Ordering::Release,
+        );
+
+        self.limit.load(Ordering::SeqCst)
+    }
 }
 
 #[async_trait]
@@ -122,6 +323,24 @@ impl LimitAlgorithm for Vegas {
         self.limit.load(Ordering::Acquire)
     }
 
+    fn min_limit(&self) -> usize {
+        self.min_limit
+    }
+
+    fn max_limit(&self) -> usize {
+        self.max_limit
+    }
+
+    /// The minimum observed latency.
+    async fn baseline_latency(&self) -> Option<Duration> {
+        let base_latency = self
+            .inner
+            .lock()
+            .expect("lock should not be poisoned")
+            .base_latency;
+        (base_latency != Duration::MAX).then_some(base_latency)
+    }
+
     /// Vegas algorithm.
     ///
     /// Generally applied over a window size of one or two RTTs.
@@ -161,57 +380,11 @@ impl LimitAlgorithm for Vegas {
     ///               - 1 if Q(w) > beta
     /// ```
     async fn update(&self, sample: Sample) -> usize {
-        if sample.latency < MIN_SAMPLE_LATENCY {
-            return self.limit.load(Ordering::Acquire);
-        }
-
-        let mut inner = self.inner.lock().await;
-
-        if sample.latency < inner.base_latency {
-            // Record a baseline "no load" latency and keep the limit.
-            inner.base_latency = sample.latency;
-            // return self.limit.load(Ordering::Acquire);
-        }
-
-        let update_limit = |limit: usize| {
-            // TODO: periodically reset baseline latency measurement.
-
-            let actual_rate = sample.in_flight as f64 / sample.latency.as_secs_f64();
-
-            let extra_latency = sample.latency.as_secs_f64() - inner.base_latency.as_secs_f64();
-
-            let estimated_queued_jobs = actual_rate * extra_latency;
-
-            let utilisation = sample.in_flight as f64 / limit as f64;
-
-            let increment = limit.ilog10().max(1) as usize;
-
-            let limit = if sample.outcome == Outcome::Overload {
-                // Limit too big – overload
-                multiplicative_decrease(limit, Self::DEFAULT_DECREASE_FACTOR)
-            } else if estimated_queued_jobs > (self.beta)(limit) {
-                // Limit too big – too much queueing
-                limit - increment
-            } else if estimated_queued_jobs < (self.alpha)(limit)
-                && utilisation >= Self::DEFAULT_INCREASE_MIN_UTILISATION
-            {
-                // Limit too small – low queueing + high utilisation
-
-                // TODO: support some kind of fast start, e.g. increase by beta when almost no queueing
-                limit + increment
-            } else {
-                // Perfect porridge
-                limit
-            };
-
-            Some(limit.clamp(self.min_limit, self.max_limit))
-        };
-
-        self.limit
-            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, update_limit)
-            .expect("we always return Some(limit)");
+        self.update_now(sample)
+    }
 
-        self.limit.load(Ordering::SeqCst)
+    fn try_update_sync(&self, sample: Sample) -> Result<usize, Sample> {
+        Ok(self.update_now(sample))
     }
 }
 
@@ -223,6 +396,13 @@ impl Debug for Vegas {
             .field("max_limit", &self.max_limit)
             .field("alpha(1)", &(self.alpha)(1))
             .field("beta(1)", &(self.beta)(1))
+            .field(
+                "additive_overload_decrease",
+                &self.additive_overload_decrease,
+            )
+            .field("clamp_min_latency", &self.clamp_min_latency)
+            .field("utilisation_gate", &self.utilisation_gate)
+            .field("min_in_flight_for_delay", &self.min_in_flight_for_delay)
             .field("inner", &self.inner)
             .finish()
     }
@@ -299,6 +479,182 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn baseline_latency() {
+        let limiter = DefaultLimiter::new(Vegas::new_with_initial_limit(10));
+        assert_eq!(limiter.algorithm_baseline().await, None);
+
+        for latency in [30, 12, 25, 40] {
+            let mut token = limiter.try_acquire().await.unwrap();
+            token.set_latency(Duration::from_millis(latency));
+            limiter.release(token, Some(Outcome::Success)).await;
+        }
+
+        assert_eq!(
+            limiter.algorithm_baseline().await,
+            Some(Duration::from_millis(12)),
+            "minimum observed latency"
+        );
+    }
+
+    #[tokio::test]
+    async fn decrease_confirmations() {
+        let vegas = Vegas::new_with_initial_limit(10).with_decrease_confirmations(3);
+
+        let steady = Sample {
+            in_flight: 5,
+            latency: Duration::from_millis(25),
+            outcome: Outcome::Success,
+        };
+        let congested = Sample {
+            in_flight: 10,
+            latency: Duration::from_millis(250),
+            outcome: Outcome::Success,
+        };
+
+        vegas.update(steady.clone()).await;
+        assert_eq!(vegas.update(congested.clone()).await, 10, "single outlier");
+        vegas.update(steady).await;
+
+        assert_eq!(vegas.update(congested.clone()).await, 10);
+        assert_eq!(vegas.update(congested.clone()).await, 10);
+        assert_eq!(
+            vegas.update(congested).await,
+            9,
+            "sustained congestion => decrease limit"
+        );
+    }
+
+    #[tokio::test]
+    async fn additive_overload_decrease() {
+        async fn trajectory(vegas: Vegas) -> Vec<usize> {
+            let mut limits = vec![];
+            for _ in 0..5 {
+                limits.push(
+                    vegas
+                        .update(Sample {
+                            in_flight: 100,
+                            latency: Duration::from_millis(25),
+                            outcome: Outcome::overload(),
+                        })
+                        .await,
+                );
+            }
+            limits
+        }
+
+        let multiplicative = trajectory(Vegas::new_with_initial_limit(100)).await;
+        let additive =
+            trajectory(Vegas::new_with_initial_limit(100).with_additive_overload_decrease(true))
+                .await;
+
+        assert_eq!(multiplicative, [90, 81, 72, 64, 57]);
+        assert_eq!(additive, [98, 97, 96, 95, 94], "decrease by log10(limit)");
+    }
+
+    #[tokio::test]
+    async fn clamp_min_latency() {
+        async fn update_with_overload(vegas: Vegas) -> usize {
+            vegas
+                .update(Sample {
+                    in_flight: 10,
+                    latency: Duration::from_nanos(100),
+                    outcome: Outcome::overload(),
+                })
+                .await
+        }
+
+        assert_eq!(
+            update_with_overload(Vegas::new_with_initial_limit(10)).await,
+            10,
+            "sub-microsecond sample ignored"
+        );
+        assert_eq!(
+            update_with_overload(Vegas::new_with_initial_limit(10).with_clamp_min_latency(true))
+                .await,
+            9,
+            "clamped: overload => decrease limit"
+        );
+    }
+
+    #[tokio::test]
+    async fn utilisation_gate() {
+        let low_utilisation = Sample {
+            in_flight: 1,
+            latency: Duration::from_millis(25),
+            outcome: Outcome::Success,
+        };
+
+        let gated = Vegas::new_with_initial_limit(10);
+        assert_eq!(gated.update(low_utilisation.clone()).await, 10);
+
+        let ungated = Vegas::new_with_initial_limit(10).with_utilisation_gate(false);
+        assert_eq!(ungated.update(low_utilisation).await, 11, "increase");
+    }
+
+    #[tokio::test]
+    async fn min_in_flight_for_delay() {
+        async fn limit_after_slow_sample(vegas: Vegas) -> usize {
+            let vegas = vegas.with_thresholds(|_| 0.1, |_| 0.5);
+            for latency in [10, 1000] {
+                vegas
+                    .update(Sample {
+                        in_flight: 1,
+                        latency: Duration::from_millis(latency),
+                        outcome: Outcome::Success,
+                    })
+                    .await;
+            }
+            vegas.limit()
+        }
+
+        assert_eq!(
+            limit_after_slow_sample(Vegas::new_with_initial_limit(10)).await,
+            9,
+            "queueing => decrease limit"
+        );
+        assert_eq!(
+            limit_after_slow_sample(
+                Vegas::new_with_initial_limit(10).with_min_in_flight_for_delay(2)
+            )
+            .await,
+            10,
+            "too few in flight: ignore latency"
+        );
+    }
+
+    #[tokio::test]
+    async fn custom_thresholds() {
+        let vegas = Vegas::new_with_initial_limit(10).with_thresholds(|_| 100., |_| 200.);
+
+        vegas
+            .update(Sample {
+                in_flight: 9,
+                latency: Duration::from_millis(25),
+                outcome: Outcome::Success,
+            })
+            .await;
+        let limit = vegas
+            .update(Sample {
+                in_flight: 9,
+                latency: Duration::from_millis(250),
+                outcome: Outcome::Success,
+            })
+            .await;
+
+        assert!(
+            limit > 10,
+            "Queueing below custom alpha => increase limit. Limit: {}",
+            limit
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "alpha must not be greater than beta")]
+    fn inverted_thresholds() {
+        Vegas::new_with_initial_limit(10).with_thresholds(|_| 100., |_| 50.);
+    }
+
     #[tokio::test]
     async fn windowed() {
         use crate::aggregation::Percentile;
@@ -1,9 +1,15 @@
-use std::{ops::RangeInclusive, time::Duration};
+use std::{ops::RangeInclusive, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
-use tokio::{sync::Mutex, time::Instant};
+use tokio::{
+    sync::{watch, Mutex},
+    task::AbortHandle,
+    time::{sleep_until, Instant},
+};
 
-use crate::aggregation::Aggregator;
+use conv::ConvAsUtil;
+
+use crate::{aggregation::Aggregator, limiter::Outcome};
 
 use super::{defaults::MIN_SAMPLE_LATENCY, LimitAlgorithm, Sample};
 
@@ -25,9 +31,28 @@ pub struct Windowed<L, S> {
     /// anything about the state of the server we're trying to communicate with.
     min_latency_threshold: Duration,
 
-    inner: L,
+    /// Re-submit a decayed copy of the previous aggregate when a window ends with no samples.
+    carry_forward: bool,
+
+    /// If set, windows end after exactly this many samples, regardless of time.
+    count_window: Option<usize>,
+
+    inner: Arc<L>,
 
-    window: Mutex<Window<S>>,
+    window: Arc<Mutex<Window<S>>>,
+
+    /// Flushes windows when they end, even without any updates. Aborted on drop.
+    flush_task: Option<AbortHandle>,
+    /// Publishes configuration changes to the flush task.
+    flush_config: watch::Sender<FlushConfig>,
+}
+
+/// The configuration used by the background flush task.
+#[derive(Debug, Clone)]
+struct FlushConfig {
+    window_bounds: RangeInclusive<Duration>,
+    carry_forward: bool,
+    count_window: bool,
 }
 
 #[derive(Debug)]
@@ -36,10 +61,17 @@ struct Window<S> {
     duration: Duration,
 
     aggregator: S,
+    /// The aggregated sample so far in the current window.
+    pending: Option<Sample>,
     /// The minimum latency observed in the current window.
     ///
     /// Used to determine the next window duration.
     min_latency: Duration,
+
+    /// The aggregated sample from the most recent non-empty window.
+    last_aggregate: Option<Sample>,
+    /// The number of consecutive empty windows where the last aggregate has been carried forward.
+    carry_forwards: usize,
 }
 
 impl<L: LimitAlgorithm, S: Aggregator> Windowed<L, S> {
@@ -48,20 +80,37 @@ impl<L: LimitAlgorithm, S: Aggregator> Windowed<L, S> {
     #[allow(missing_docs)]
     pub fn new(inner: L, sampler: S) -> Self {
         let min_window = Duration::from_micros(1);
+        let window_bounds = RangeInclusive::new(min_window, Duration::from_secs(1));
         Self {
-            window_bounds: RangeInclusive::new(min_window, Duration::from_secs(1)),
+            flush_config: watch::Sender::new(FlushConfig {
+                window_bounds: window_bounds.clone(),
+                carry_forward: false,
+                count_window: false,
+            }),
+
+            window_bounds,
             min_samples: Self::DEFAULT_MIN_SAMPLES,
             min_latency_threshold: MIN_SAMPLE_LATENCY,
 
-            inner,
+            carry_forward: false,
+
+            count_window: None,
 
-            window: Mutex::new(Window {
+            inner: Arc::new(inner),
+
+            window: Arc::new(Mutex::new(Window {
                 duration: min_window,
                 start: Instant::now(),
 
                 aggregator: sampler,
+                pending: None,
                 min_latency: Duration::MAX,
-            }),
+
+                last_aggregate: None,
+                carry_forwards: 0,
+            })),
+
+            flush_task: None,
         }
     }
 
@@ -75,6 +124,7 @@ impl<L: LimitAlgorithm, S: Aggregator> Windowed<L, S> {
     /// Minimum time to wait before attempting to update the limit.
     pub fn with_min_window(mut self, min: Duration) -> Self {
         self.window_bounds = min..=*self.window_bounds.end();
+        self.publish_flush_config();
         self
     }
 
@@ -84,10 +134,119 @@ impl<L: LimitAlgorithm, S: Aggregator> Windowed<L, S> {
     /// [with_min_samples()](Self::with_min_samples()).
     pub fn with_max_window(mut self, max: Duration) -> Self {
         self.window_bounds = *self.window_bounds.start()..=max;
+        self.publish_flush_config();
+        self
+    }
+
+    /// End each window after exactly `samples` samples, regardless of how much time has passed.
+    ///
+    /// The time-based window bounds, minimum samples and carry forward are ignored. Useful for
+    /// reproducible tests, or for workloads where time isn't the right axis.
+    pub fn with_count_window(mut self, samples: usize) -> Self {
+        assert!(samples > 0, "at least one sample required per window");
+        self.count_window = Some(samples);
+        self.publish_flush_config();
+        self
+    }
+
+    /// When a window ends without any samples, update the limit using a decayed copy of the
+    /// previous window's aggregated sample.
+    ///
+    /// Keeps the limit algorithm making progress when samples are sparse. Only a limited number of
+    /// consecutive windows will be carried forward.
+    pub fn with_carry_forward(mut self, enabled: bool) -> Self {
+        self.carry_forward = enabled;
+        self.publish_flush_config();
+        self
+    }
+
+    fn publish_flush_config(&self) {
+        self.flush_config.send_replace(FlushConfig {
+            window_bounds: self.window_bounds.clone(),
+            carry_forward: self.carry_forward,
+            count_window: self.count_window.is_some(),
+        });
+    }
+}
+
+impl<L, S> Windowed<L, S>
+where
+    L: LimitAlgorithm + Send + Sync + 'static,
+    S: Aggregator + Send + Sync + 'static,
+{
+    /// How long the background flush waits before checking again when a window has ended without
+    /// any samples to flush, if the maximum window is shorter.
+    const MIN_FLUSH_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+    /// Flush each window when it ends on a background timer, instead of waiting for the next
+    /// update to arrive.
+    ///
+    /// Keeps the limit adapting during lulls in traffic, when the last partial window would
+    /// otherwise never be flushed. Partial windows are flushed even if they have fewer than the
+    /// [minimum samples](Self::with_min_samples()). Ignored with
+    /// [count windows](Self::with_count_window()).
+    ///
+    /// The algorithm's limit is updated in the background, and a
+    /// [DefaultLimiter](crate::limiter::DefaultLimiter) picks up the change on its next update.
+    ///
+    /// Must be called within a tokio runtime. The timer stops when this is dropped.
+    pub fn with_background_flush(mut self, enabled: bool) -> Self {
+        if let Some(task) = self.flush_task.take() {
+            task.abort();
+        }
+        if !enabled {
+            return self;
+        }
+
+        let inner = Arc::clone(&self.inner);
+        let window = Arc::clone(&self.window);
+        let config = self.flush_config.subscribe();
+        let task = tokio::spawn(async move {
+            loop {
+                let FlushConfig {
+                    window_bounds: bounds,
+                    carry_forward,
+                    count_window,
+                } = config.borrow().clone();
+
+                let (flushed, next) = {
+                    let mut window = window.lock().await;
+                    let flushed = if count_window {
+                        None
+                    } else {
+                        window.flush_due(&bounds, carry_forward)
+                    };
+                    (flushed, window.start + window.duration)
+                };
+                if let Some(sample) = flushed {
+                    inner.update(sample).await;
+                    continue;
+                }
+
+                // Wait for the window to end. If it already has, nothing is due until more samples
+                // arrive, so check back later, but not so soon that a zero window busy-loops.
+                let now = Instant::now();
+                sleep_until(if next > now {
+                    next
+                } else {
+                    now + (*bounds.end()).max(Self::MIN_FLUSH_CHECK_INTERVAL)
+                })
+                .await;
+            }
+        });
+        self.flush_task = Some(task.abort_handle());
         self
     }
 }
 
+impl<L, S> Drop for Windowed<L, S> {
+    fn drop(&mut self) {
+        if let Some(task) = &self.flush_task {
+            task.abort();
+        }
+    }
+}
+
 #[async_trait]
 impl<L, S> LimitAlgorithm for Windowed<L, S>
 where
@@ -98,8 +257,26 @@ where
         self.inner.limit()
     }
 
+    fn min_limit(&self) -> usize {
+        self.inner.min_limit()
+    }
+
+    fn max_limit(&self) -> usize {
+        self.inner.max_limit()
+    }
+
+    async fn baseline_latency(&self) -> Option<Duration> {
+        self.inner.baseline_latency().await
+    }
+
     async fn update(&self, sample: Sample) -> usize {
         if sample.latency < self.min_latency_threshold {
+            if self.carry_forward && self.count_window.is_none() {
+                let mut window = self.window.lock().await;
+                if let Some(carried) = window.carry_forward(&self.window_bounds) {
+                    return self.inner.update(carried).await;
+                }
+            }
             return self.inner.limit();
         }
 
@@ -108,26 +285,95 @@ where
         window.min_latency = window.min_latency.min(sample.latency);
 
         let agg_sample = window.aggregator.sample(sample);
+        window.pending = Some(agg_sample.clone());
 
-        if window.aggregator.sample_size() >= self.min_samples
-            && window.start.elapsed() >= window.duration
-        {
+        let window_ended = match self.count_window {
+            Some(count) => window.aggregator.sample_size() >= count,
+            None => {
+                window.aggregator.sample_size() >= self.min_samples
+                    && window.start.elapsed() >= window.duration
+            }
+        };
+
+        if window_ended {
             window.reset(&self.window_bounds);
+            window.last_aggregate = Some(agg_sample.clone());
+            window.carry_forwards = 0;
 
             self.inner.update(agg_sample).await
         } else {
             self.inner.limit()
         }
     }
+
+    async fn on_acquire_timeout(&self, sample: Sample) -> usize {
+        self.inner.on_acquire_timeout(sample).await
+    }
 }
 
 impl<S> Window<S>
 where
     S: Aggregator,
 {
+    /// Stop carrying forward after this many consecutive empty windows, so the limit isn't driven
+    /// indefinitely without any real signal.
+    const MAX_CARRY_FORWARDS: usize = 3;
+    /// Applied to the jobs in flight each time an aggregate is carried forward.
+    const CARRY_FORWARD_DECAY: f64 = 0.5;
+
+    /// If this window has ended, returns the sample to update the limit with, if any, and starts a
+    /// new window.
+    ///
+    /// Unlike [update()](Windowed::update()), this ignores the minimum number of samples.
+    fn flush_due(
+        &mut self,
+        bounds: &RangeInclusive<Duration>,
+        carry_forward: bool,
+    ) -> Option<Sample> {
+        if self.start.elapsed() < self.duration {
+            return None;
+        }
+        match self.pending.take() {
+            Some(aggregate) => {
+                self.reset(bounds);
+                self.last_aggregate = Some(aggregate.clone());
+                self.carry_forwards = 0;
+                Some(aggregate)
+            }
+            None if carry_forward => self.carry_forward(bounds),
+            None => None,
+        }
+    }
+
+    /// If this window has ended without any samples, returns a decayed copy of the last aggregated
+    /// sample and starts a new window.
+    fn carry_forward(&mut self, bounds: &RangeInclusive<Duration>) -> Option<Sample> {
+        if self.aggregator.sample_size() > 0
+            || self.start.elapsed() < self.duration
+            || self.carry_forwards >= Self::MAX_CARRY_FORWARDS
+        {
+            return None;
+        }
+
+        let last = self.last_aggregate.as_mut()?;
+        last.in_flight = (last.in_flight as f64 * Self::CARRY_FORWARD_DECAY)
+            .floor()
+            .approx()
+            .expect("should not have increased");
+        // Don't repeat an overload signal without any evidence.
+        last.outcome = Outcome::Success;
+        let carried = last.clone();
+
+        self.carry_forwards += 1;
+        self.reset(bounds);
+
+        Some(carried)
+    }
+
     fn reset(&mut self, bounds: &RangeInclusive<Duration>) {
         self.min_latency = Duration::MAX;
         self.aggregator.reset();
+        self.pending = None;
 
         self.start = Instant::now();
 
@@ -138,7 +384,10 @@ where
 
 #[cfg(test)]
 mod tests {
-    use crate::{aggregation::Average, limiter::Outcome, limits::Vegas};
+    use crate::{
+        aggregation::Average,
+        limits::{Aimd, Vegas},
+    };
 
     use super::*;
 
@@ -170,10 +419,144 @@ mod tests {
                 .update(Sample {
                     in_flight: 1,
                     latency: Duration::from_millis(100),
-                    outcome: Outcome::Overload,
+                    outcome: Outcome::overload(),
                 })
                 .await;
         }
         assert!(limit < 10, "limit should be reduced");
     }
+
+    #[tokio::test]
+    async fn carry_forward() {
+        let windowed_aimd = Windowed::new(Aimd::new_with_initial_limit(10), Average::default())
+            .with_min_samples(1)
+            .with_min_window(Duration::ZERO)
+            .with_max_window(Duration::ZERO)
+            .with_carry_forward(true);
+
+        let limit = windowed_aimd
+            .update(Sample {
+                in_flight: 20,
+                latency: Duration::from_millis(10),
+                outcome: Outcome::Success,
+            })
+            .await;
+        assert_eq!(limit, 11);
+
+        let empty_sample = Sample {
+            in_flight: 0,
+            latency: Duration::ZERO,
+            outcome: Outcome::Success,
+        };
+
+        let limit = windowed_aimd.update(empty_sample.clone()).await;
+        assert_eq!(limit, 12, "empty window: update with decayed aggregate");
+
+        for _ in 0..5 {
+            windowed_aimd.update(empty_sample.clone()).await;
+        }
+        assert_eq!(
+            windowed_aimd.window.lock().await.carry_forwards,
+            3,
+            "limited number of carry forwards"
+        );
+    }
+
+    #[tokio::test]
+    async fn count_window() {
+        let windowed = Windowed::new(Aimd::new_with_initial_limit(10), Average::default())
+            .with_count_window(3)
+            .with_min_window(Duration::from_secs(1));
+
+        let sample = Sample {
+            in_flight: 10,
+            latency: Duration::from_millis(10),
+            outcome: Outcome::Success,
+        };
+
+        let mut limits = vec![];
+        for _ in 0..9 {
+            limits.push(windowed.update(sample.clone()).await);
+        }
+
+        assert_eq!(
+            limits,
+            vec![10, 10, 11, 11, 11, 12, 12, 12, 13],
+            "updated every 3 samples"
+        );
+    }
+
+    #[tokio::test]
+    async fn background_flush() {
+        tokio::time::pause();
+
+        let windowed = Windowed::new(Aimd::new_with_initial_limit(10), Average::default())
+            .with_min_window(Duration::from_millis(100))
+            .with_max_window(Duration::from_millis(100))
+            .with_background_flush(true);
+
+        let limit = windowed
+            .update(Sample {
+                in_flight: 10,
+                latency: Duration::from_millis(10),
+                outcome: Outcome::Success,
+            })
+            .await;
+        assert_eq!(limit, 10, "not enough samples");
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(windowed.limit(), 11, "flushed on the timer");
+
+        let inner = Arc::downgrade(&windowed.inner);
+        drop(windowed);
+        tokio::task::yield_now().await;
+        assert!(inner.upgrade().is_none(), "timer stopped");
+    }
+
+    #[tokio::test]
+    async fn background_flush_zero_window() {
+        tokio::time::pause();
+
+        let windowed = Windowed::new(Aimd::new_with_initial_limit(10), Average::default())
+            .with_min_window(Duration::ZERO)
+            .with_max_window(Duration::ZERO)
+            .with_background_flush(true);
+
+        windowed
+            .update(Sample {
+                in_flight: 10,
+                latency: Duration::from_millis(10),
+                outcome: Outcome::Success,
+            })
+            .await;
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(windowed.limit(), 11, "flushed on the timer");
+    }
+
+    #[tokio::test]
+    async fn background_flush_uses_later_configuration() {
+        tokio::time::pause();
+
+        let windowed = Windowed::new(Aimd::new_with_initial_limit(10), Average::default())
+            .with_min_window(Duration::from_millis(100))
+            .with_max_window(Duration::from_millis(100))
+            .with_background_flush(true)
+            .with_count_window(5);
+
+        windowed
+            .update(Sample {
+                in_flight: 10,
+                latency: Duration::from_millis(10),
+                outcome: Outcome::Success,
+            })
+            .await;
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(
+            windowed.limit(),
+            10,
+            "count windows aren't flushed on the timer"
+        );
+    }
 }
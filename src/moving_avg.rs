@@ -15,6 +15,7 @@ pub struct ExpSmoothed {
     value: Duration,
 
     // For initial warmup period
+    warmup_samples: u16,
     initial_sum: Duration,
     initial_count: u16,
 }
@@ -29,22 +30,33 @@ impl ExpSmoothed {
     const INITIAL_WARMUP_SAMPLES: u16 = 10;
 
     pub fn new_with_window_size(k: u16) -> Self {
+        Self::new_with_window_and_warmup(k, Self::INITIAL_WARMUP_SAMPLES)
+    }
+
+    /// Use a simple average of the first `warmup` samples as the initial value.
+    pub fn new_with_window_and_warmup(k: u16, warmup: u16) -> Self {
+        assert!(warmup > 0, "warmup must be at least 1 sample");
         Self {
             smoothing_factor: Self::smoothing_for_window(k),
             value: Duration::ZERO,
+            warmup_samples: warmup,
             initial_sum: Duration::ZERO,
             initial_count: 0,
         }
     }
 
     pub fn sample(&mut self, sample: Duration) -> Duration {
-        if self.initial_count < Self::INITIAL_WARMUP_SAMPLES {
+        if self.initial_count < self.warmup_samples {
             self.initial_sum += sample;
             self.initial_count += 1;
 
             self.value = self.initial_sum / self.initial_count.into();
         } else {
-            self.value = self.value + (sample - self.value).mul_f64(self.smoothing_factor);
+            // Durations can't be negative, so smooth using floats.
+            let value = self.value.as_secs_f64();
+            self.value = Duration::from_secs_f64(
+                value + (sample.as_secs_f64() - value) * self.smoothing_factor,
+            );
         }
         self.value
     }
@@ -53,6 +65,10 @@ impl ExpSmoothed {
         self.value = value;
     }
 
+    pub fn value(&self) -> Duration {
+        self.value
+    }
+
     fn smoothing_for_window(k: u16) -> f64 {
         assert!(k > 0, "window size must be > 0");
         assert!(k < u16::MAX, "window size mustn't overflow");
@@ -102,3 +118,50 @@ impl Simple {
         self.avg
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::ExpSmoothed;
+
+    #[test]
+    fn configurable_warmup() {
+        let mut avg = ExpSmoothed::new_with_window_and_warmup(10, 3);
+
+        avg.sample(Duration::from_millis(10));
+        avg.sample(Duration::from_millis(20));
+        let value = avg.sample(Duration::from_millis(30));
+        assert_eq!(
+            value,
+            Duration::from_millis(20),
+            "simple average during warmup"
+        );
+
+        let value = avg.sample(Duration::from_millis(130));
+        assert_eq!(
+            value,
+            Duration::from_millis(40),
+            "exponential smoothing after warmup"
+        );
+    }
+
+    #[test]
+    fn decreasing_samples() {
+        let mut avg = ExpSmoothed::new_with_window_and_warmup(10, 1);
+
+        avg.sample(Duration::from_millis(100));
+        let value = avg.sample(Duration::from_millis(45));
+        assert_eq!(
+            value,
+            Duration::from_millis(90),
+            "moves down towards sample"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "warmup must be at least 1 sample")]
+    fn zero_warmup() {
+        ExpSmoothed::new_with_window_and_warmup(10, 0);
+    }
+}
@@ -0,0 +1,163 @@
+//! Utilities for testing code which uses limiters.
+//!
+//! Requires the `testing` feature.
+
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::pin,
+    sync::{atomic::AtomicUsize, Arc, Mutex},
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+
+use crate::{
+    limiter::{Limiter, Outcome, Token},
+    limits::{LimitAlgorithm, Sample},
+};
+
+/// A scripted [Limiter], for testing integrations without real timing or concurrency limits.
+///
+/// Enqueue the results of acquire calls, then inspect the outcomes of released tokens.
+#[derive(Debug, Default)]
+pub struct MockLimiter {
+    acquire_results: Mutex<VecDeque<bool>>,
+    releases: Mutex<Vec<Option<Outcome>>>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl MockLimiter {
+    #[allow(missing_docs)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make the next acquire call return a [Token] if `succeed` is true, or `None` otherwise.
+    ///
+    /// Applies to both [try_acquire()](Limiter::try_acquire()) and
+    /// [acquire_timeout()](Limiter::acquire_timeout()). Acquire calls will panic if no results have
+    /// been enqueued.
+    pub fn enqueue_acquire(&self, succeed: bool) -> &Self {
+        self.acquire_results
+            .lock()
+            .expect("lock should not be poisoned")
+            .push_back(succeed);
+        self
+    }
+
+    /// The outcomes of all released tokens, in order.
+    pub fn releases(&self) -> Vec<Option<Outcome>> {
+        self.releases
+            .lock()
+            .expect("lock should not be poisoned")
+            .clone()
+    }
+
+    fn next_acquire(&self) -> Option<Token> {
+        let succeed = self
+            .acquire_results
+            .lock()
+            .expect("lock should not be poisoned")
+            .pop_front()
+            .expect("unexpected acquire: no results enqueued");
+
+        succeed.then(|| Token::new_overflow(self.in_flight.clone()))
+    }
+}
+
+#[async_trait]
+impl Limiter for MockLimiter {
+    async fn try_acquire(&self) -> Option<Token> {
+        self.next_acquire()
+    }
+
+    async fn acquire_timeout(&self, _duration: Duration) -> Option<Token> {
+        self.next_acquire()
+    }
+
+    /// Always returns a limit of 1.
+    async fn release(&self, token: Token, outcome: Option<Outcome>) -> usize {
+        self.releases
+            .lock()
+            .expect("lock should not be poisoned")
+            .push(outcome);
+        drop(token);
+        1
+    }
+}
+
+/// Update `algorithm` with each of `samples` in turn, returning the limit after each one.
+///
+/// Runs without a tokio runtime, so algorithms can be tested synchronously, without
+/// `#[tokio::test]`. Time doesn't advance between samples, other than in real time.
+///
+/// # Panics
+///
+/// If the algorithm needs a runtime to update, e.g. [Batched](crate::limits::Batched), which spawns
+/// a task on its first update. Similarly, a [Windowed](crate::limits::Windowed) with a
+/// [background flush](crate::limits::Windowed::with_background_flush()) can't be built without
+/// one.
+pub fn drive<A: LimitAlgorithm>(
+    algorithm: &A,
+    samples: impl IntoIterator<Item = Sample>,
+) -> Vec<usize> {
+    samples
+        .into_iter()
+        .map(|sample| block_on(algorithm.update(sample)))
+        .collect()
+}
+
+/// Run a future to completion on the current thread.
+fn block_on<F: Future>(future: F) -> F::Output {
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::limits::Aimd;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn scripted_acquires() {
+        let limiter = MockLimiter::new();
+        limiter.enqueue_acquire(true).enqueue_acquire(false);
+
+        let token = limiter.try_acquire().await.expect("first acquire succeeds");
+        assert!(limiter.try_acquire().await.is_none(), "second is rejected");
+
+        limiter.release(token, Some(Outcome::overload())).await;
+
+        assert_eq!(limiter.releases(), vec![Some(Outcome::overload())]);
+    }
+
+    #[test]
+    fn drive_aimd() {
+        let aimd = Aimd::new(10, 1..=20).decrease_factor(0.5);
+        let success = Sample::new(Duration::from_millis(10), 10, Outcome::Success);
+        let overload = Sample::new(Duration::from_millis(10), 10, Outcome::overload());
+
+        let limits = drive(&aimd, [success.clone(), success.clone(), overload, success]);
+
+        assert_eq!(limits, vec![11, 12, 6, 7]);
+    }
+}
@@ -2,7 +2,6 @@
 
 use std::{cmp::Reverse, collections::BinaryHeap, time::Duration};
 
-use async_trait::async_trait;
 use conv::ValueFrom;
 use itertools::Itertools;
 use rand::{prelude::Distribution, rngs::SmallRng, Rng, SeedableRng};
@@ -14,7 +13,7 @@ use tokio::time::Instant;
 
 use congestion_limiter::{
     limiter::{DefaultLimiter, Limiter, LimiterState, Outcome, Token},
-    limits::{Aimd, LimitAlgorithm, Sample},
+    limits::{Aimd, AnyLimit},
 };
 
 mod iter_ext;
@@ -29,35 +28,16 @@ struct Simulation {
 
 type Id = usize;
 
-/// The limiting algorithms we'll be testing.
-#[derive(Debug)]
-enum LimitWrapper {
-    Aimd(Aimd),
-}
-#[async_trait]
-impl LimitAlgorithm for LimitWrapper {
-    fn limit(&self) -> usize {
-        match self {
-            LimitWrapper::Aimd(l) => l.limit(),
-        }
-    }
-    async fn update(&self, reading: Sample) -> usize {
-        match self {
-            LimitWrapper::Aimd(l) => l.update(reading).await,
-        }
-    }
-}
-
 /// Models a Poisson process.
 struct Client {
-    limiter: Option<DefaultLimiter<LimitWrapper>>,
+    limiter: Option<DefaultLimiter<AnyLimit>>,
 
     /// Poisson process, exponential interarrival times.
     interarrival: Exp,
 }
 
 struct Server {
-    limiter: Option<DefaultLimiter<LimitWrapper>>,
+    limiter: Option<DefaultLimiter<AnyLimit>>,
 
     latency: Erlang,
 
@@ -99,6 +79,7 @@ struct Event {
     typ: Action,
 }
 #[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
 enum Action {
     StartRequest {
         client_id: Id,
@@ -167,7 +148,7 @@ mod event_log {
 
 impl Client {
     /// Create a client which sends `rps` requests per second on average.
-    fn with_rps(limiter: Option<DefaultLimiter<LimitWrapper>>, rps: f64) -> Self {
+    fn with_rps(limiter: Option<DefaultLimiter<AnyLimit>>, rps: f64) -> Self {
         Self {
             limiter,
             interarrival: Exp::new(rps).unwrap(),
@@ -221,7 +202,7 @@ impl Client {
 impl Server {
     /// Create a server with a concurrency limiter, a latency distribution and a failure rate.
     fn new(
-        limiter: Option<DefaultLimiter<LimitWrapper>>,
+        limiter: Option<DefaultLimiter<AnyLimit>>,
         latency_profile: LatencyProfile,
         failure_rate: f64,
     ) -> Self {
@@ -268,7 +249,7 @@ impl Server {
         let result = if rng.gen_range(0.0..=1.0) > self.failure_rate {
             Outcome::Success
         } else {
-            Outcome::Overload
+            Outcome::overload()
         };
 
         limiter.release(token, Some(result)).await;
@@ -371,13 +352,13 @@ impl Simulation {
                                     if let Some(client_state) = client_state {
                                         let req_outcome = self
                                             .client
-                                            .res(client_state.token, Outcome::Overload)
+                                            .res(client_state.token, Outcome::overload())
                                             .await;
 
                                         event_log.push(event_log::Item::Client(
                                             client_id,
                                             event_log::LimiterEvent::Finished(
-                                                Outcome::Overload,
+                                                Outcome::overload(),
                                                 req_outcome.limit_state,
                                             ),
                                         ));
@@ -406,7 +387,7 @@ impl Simulation {
                             start_time: current_time,
                             end_time: current_time,
                             latency: Duration::ZERO,
-                            result: Outcome::Overload,
+                            result: Outcome::overload(),
                         });
                     }
 
@@ -548,7 +529,7 @@ async fn test() {
     let simulation_duration = Duration::from_secs(1);
 
     let client = Client::with_rps(
-        Some(DefaultLimiter::new(LimitWrapper::Aimd(
+        Some(DefaultLimiter::new(AnyLimit::Aimd(
             Aimd::new_with_initial_limit(10)
                 .with_max_limit(20)
                 .decrease_factor(0.9)